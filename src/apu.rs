@@ -1,3 +1,49 @@
+use crate::console::Region;
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Volume envelope shared by the pulse and noise channels: either a constant volume,
+/// or a level decaying from 15 once per period, optionally looping.
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    /// Clocked by the frame counter's quarter frames.
+    fn clock(&mut self, period: u8, looping: bool) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = period;
+        } else if self.divider == 0 {
+            self.divider = period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.start);
+        w.write_u8(self.divider);
+        w.write_u8(self.decay);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.start = r.read_bool()?;
+        self.divider = r.read_u8()?;
+        self.decay = r.read_u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+/// A square wave channel. The MMC5 has two more of these, without the sweep unit.
 pub struct Pulse {
     // Registers: $4000, $4001, $4002, $4003
     duty: u8,
@@ -12,6 +58,14 @@ pub struct Pulse {
 
     timer: u16,
     length_counter: u8,
+
+    ones_complement: bool, // Pulse 1 negates its sweep one lower than pulse 2
+    enabled: bool,
+    timer_counter: u16,
+    sequence_step: u8,
+    envelope: Envelope,
+    sweep_reload: bool,
+    sweep_divider: u8,
 }
 
 pub struct Triangle {
@@ -21,6 +75,12 @@ pub struct Triangle {
 
     timer: u16,
     length_counter: u8,
+
+    enabled: bool,
+    timer_counter: u16,
+    sequence_step: u8,
+    linear_counter: u8,
+    linear_reload: bool,
 }
 
 pub struct Noise {
@@ -33,6 +93,12 @@ pub struct Noise {
     period: u8,
 
     length_counter: u8,
+
+    noise_periods: &'static [u16; 16],
+    enabled: bool,
+    timer_counter: u16,
+    shift_register: u16,
+    envelope: Envelope,
 }
 
 pub struct DMC {
@@ -44,8 +110,38 @@ pub struct DMC {
     load_counter: u8,
     sample_address: u8,
     sample_length: u8,
+
+    rates: &'static [u16; 16], // CPU cycles per output bit, by region
+
+    // Memory reader
+    current_address: u16,
+    bytes_remaining: u16,
+    sample_buffer: Option<u8>,
+
+    // Output unit
+    timer: u16,
+    shift_register: u8,
+    bits_remaining: u8,
+    silence: bool,
+    output_level: u8,
+    irq_flag: bool,
 }
 
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+const DUTY_TABLE: [u8; 4] = [0b0100_0000, 0b0110_0000, 0b0111_1000, 0b1001_1111];
+const TRIANGLE_TABLE: [u8; 32] = [15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+const NOISE_PERIODS_NTSC: [u16; 16] = [4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068];
+const NOISE_PERIODS_PAL: [u16; 16] = [4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778];
+const DMC_RATES_NTSC: [u16; 16] = [428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54];
+const DMC_RATES_PAL: [u16; 16] = [398, 354, 316, 298, 276, 236, 210, 198, 176, 148, 132, 118, 98, 78, 66, 50];
+
+// CPU cycles at which the frame counter clocks envelopes (every step) and length
+// counters and sweeps (steps 1 and 3). The sequence restarts after the last step.
+const FRAME_STEPS_NTSC: [[u32; 4]; 2] = [[7457, 14913, 22371, 29829], [7457, 14913, 22371, 37281]];
+const FRAME_STEPS_PAL: [[u32; 4]; 2] = [[8313, 16627, 24939, 33253], [8313, 16627, 24939, 41565]];
+
 pub struct APU {
     pulse1: Pulse,
     pulse2: Pulse,
@@ -56,19 +152,40 @@ pub struct APU {
     // Global control
     status: u8, // $4015
     frame_counter: u8, // $4017
+
+    region: Region,
+    frame_cycle: u32, // CPU cycles into the frame counter sequence
+    frame_irq: bool,
+    odd_cycle: bool, // Pulse timers run at half the CPU clock
+
+    // Output, when a sample rate is set
+    sample_rate: Option<u32>,
+    sample_phase: f64,
+    sample_sum: f32,
+    sample_count: u32,
+    samples: Vec<f32>,
 }
 
 impl APU {
-    pub fn new() -> Self {
+    pub fn new(region: Region) -> Self {
         // Initialize all channels to a silent, powered-off state
         APU {
-            pulse1: Pulse { duty: 0, length_counter_halt: false, constant_volume: false, volume: 0, sweep_enabled: false, sweep_period: 0, sweep_negate: false, sweep_shift: 0, timer: 0, length_counter: 0 },
-            pulse2: Pulse { duty: 0, length_counter_halt: false, constant_volume: false, volume: 0, sweep_enabled: false, sweep_period: 0, sweep_negate: false, sweep_shift: 0, timer: 0, length_counter: 0 },
-            triangle: Triangle { control_flag: false, linear_counter_load: 0, timer: 0, length_counter: 0 },
-            noise: Noise { length_counter_halt: false, constant_volume: false, volume: 0, mode: false, period: 0, length_counter: 0 },
-            dmc: DMC { irq_enabled: false, loop_flag: false, frequency: 0, load_counter: 0, sample_address: 0, sample_length: 0 },
+            pulse1: Pulse::new(true),
+            pulse2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(region),
+            dmc: DMC::new(region),
             status: 0,
             frame_counter: 0,
+            region,
+            frame_cycle: 0,
+            frame_irq: false,
+            odd_cycle: false,
+            sample_rate: None,
+            sample_phase: 0.0,
+            sample_sum: 0.0,
+            sample_count: 0,
+            samples: Vec::new(),
         }
     }
 
@@ -76,50 +193,721 @@ impl APU {
     pub fn cpu_write(&mut self, addr: u16, data: u8) {
         match addr {
             // Pulse 1: $4000-$4003
-            0x4000 => { /* Control */ }
-            0x4001 => { /* Sweep */ }
-            0x4002 => { /* Timer low */ }
-            0x4003 => { /* Timer high / Length counter */ }
+            0x4000..=0x4003 => self.pulse1.write(addr, data),
 
             // Pulse 2: $4004-$4007
-            0x4004 => { /* Control */ }
-            0x4005 => { /* Sweep */ }
-            0x4006 => { /* Timer low */ }
-            0x4007 => { /* Timer high / Length counter */ }
+            0x4004..=0x4007 => self.pulse2.write(addr, data),
 
             // Triangle: $4008-$400B
-            0x4008 => { /* Control */ }
+            0x4008 => {
+                self.triangle.control_flag = (data & 0x80) != 0;
+                self.triangle.linear_counter_load = data & 0x7F;
+            }
             0x4009 => { /* Unused */ }
-            0x400A => { /* Timer low */ }
-            0x400B => { /* Timer high / Length counter */ }
+            0x400A => { self.triangle.timer = (self.triangle.timer & 0x0700) | data as u16; }
+            0x400B => {
+                self.triangle.timer = (self.triangle.timer & 0x00FF) | ((data as u16 & 0x07) << 8);
+                if self.triangle.enabled {
+                    self.triangle.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.triangle.linear_reload = true;
+            }
 
             // Noise: $400C-$400F
-            0x400C => { /* Control */ }
+            0x400C => {
+                self.noise.length_counter_halt = (data & 0x20) != 0;
+                self.noise.constant_volume = (data & 0x10) != 0;
+                self.noise.volume = data & 0x0F;
+            }
             0x400D => { /* Unused */ }
-            0x400E => { /* Period */ }
-            0x400F => { /* Length counter */ }
+            0x400E => {
+                self.noise.mode = (data & 0x80) != 0;
+                self.noise.period = data & 0x0F;
+            }
+            0x400F => {
+                if self.noise.enabled {
+                    self.noise.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.noise.envelope.start = true;
+            }
 
             // DMC: $4010-$4013
-            0x4010 => { /* Control */ }
-            0x4011 => { /* Load counter */ }
-            0x4012 => { /* Sample address */ }
-            0x4013 => { /* Sample length */ }
+            0x4010 => {
+                self.dmc.irq_enabled = (data & 0x80) != 0;
+                self.dmc.loop_flag = (data & 0x40) != 0;
+                self.dmc.frequency = data & 0x0F;
+                if !self.dmc.irq_enabled {
+                    self.dmc.irq_flag = false;
+                }
+            }
+            0x4011 => {
+                self.dmc.load_counter = data & 0x7F;
+                self.dmc.output_level = data & 0x7F;
+            }
+            0x4012 => { self.dmc.sample_address = data; }
+            0x4013 => { self.dmc.sample_length = data; }
 
             // Status Register
-            0x4015 => { self.status = data; }
+            0x4015 => {
+                self.status = data;
+                self.pulse1.set_enabled((data & 0x01) != 0);
+                self.pulse2.set_enabled((data & 0x02) != 0);
+                self.triangle.enabled = (data & 0x04) != 0;
+                if !self.triangle.enabled {
+                    self.triangle.length_counter = 0;
+                }
+                self.noise.enabled = (data & 0x08) != 0;
+                if !self.noise.enabled {
+                    self.noise.length_counter = 0;
+                }
+                self.dmc.irq_flag = false;
+                if (data & 0x10) == 0 {
+                    self.dmc.bytes_remaining = 0;
+                } else if self.dmc.bytes_remaining == 0 {
+                    self.dmc.restart();
+                }
+            }
 
             // Frame Counter
-            0x4017 => { self.frame_counter = data; }
+            0x4017 => {
+                self.frame_counter = data;
+                if (data & 0x40) != 0 {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                // The five-step mode clocks everything as soon as it's selected
+                if (data & 0x80) != 0 {
+                    self.quarter_frame();
+                    self.half_frame();
+                }
+            }
 
             _ => {}
         }
     }
 
-    /// Ticks the APU state forward.
-    pub fn tick(&mut self) {
-        // TODO: Implement APU clocking.
-        // The APU is complex. It has its own timing based on the CPU clock.
-        // A frame counter clocks the envelopes and length counters at 240Hz.
-        // Timers for each channel determine the frequency of the sound wave they produce.
+    /// Handles reads of $4015: which channels are still playing, and the pending IRQs.
+    /// Reading acknowledges the frame IRQ.
+    pub fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub fn peek_status(&self) -> u8 {
+        (self.pulse1.length_counter > 0) as u8
+            | ((self.pulse2.length_counter > 0) as u8) << 1
+            | ((self.triangle.length_counter > 0) as u8) << 2
+            | ((self.noise.length_counter > 0) as u8) << 3
+            | ((self.dmc.bytes_remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq_flag as u8) << 7
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.pulse1.save_state(w);
+        self.pulse2.save_state(w);
+        self.triangle.save_state(w);
+        self.noise.save_state(w);
+        self.dmc.save_state(w);
+        w.write_u8(self.status);
+        w.write_u8(self.frame_counter);
+        w.write_u32(self.frame_cycle);
+        w.write_bool(self.frame_irq);
+        w.write_bool(self.odd_cycle);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pulse1.load_state(r)?;
+        self.pulse2.load_state(r)?;
+        self.triangle.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.dmc.load_state(r)?;
+        self.status = r.read_u8()?;
+        self.frame_counter = r.read_u8()?;
+        self.frame_cycle = r.read_u32()?;
+        self.frame_irq = r.read_bool()?;
+        self.odd_cycle = r.read_bool()?;
+        Ok(())
+    }
+
+    /// Level of the APU's /IRQ line.
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq_flag
+    }
+
+    /// Address the DMC wants its next sample byte from, if its buffer has run dry.
+    /// The console performs the read, stalling the CPU, and hands the byte to `dmc_fill`.
+    pub fn dmc_request(&self) -> Option<u16> {
+        if self.dmc.sample_buffer.is_none() && self.dmc.bytes_remaining > 0 { Some(self.dmc.current_address) } else { None }
+    }
+
+    pub fn dmc_fill(&mut self, data: u8) {
+        self.dmc.fill(data);
+    }
+
+    /// Starts or stops collecting audio samples at `rate` Hz.
+    pub fn set_sample_rate(&mut self, rate: Option<u32>) {
+        self.sample_rate = rate;
+        self.sample_phase = 0.0;
+        self.sample_sum = 0.0;
+        self.sample_count = 0;
+        self.samples.clear();
+    }
+
+    /// Returns the samples produced since the last call, in the range 0.0-1.0 or so.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    /// Ticks the APU state forward by one CPU cycle. `expansion` is the cartridge's
+    /// audio level, already scaled to the APU's mixed output.
+    pub fn tick(&mut self, expansion: f32) {
+        self.odd_cycle = !self.odd_cycle;
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.tick();
+        self.clock_frame_counter();
+
+        if let Some(rate) = self.sample_rate {
+            // Average everything between two output samples, a cheap low-pass filter
+            self.sample_sum += self.mix() + expansion;
+            self.sample_count += 1;
+            self.sample_phase += rate as f64;
+            let cpu_hz = self.region.cpu_clock_hz();
+            if self.sample_phase >= cpu_hz {
+                self.sample_phase -= cpu_hz;
+                self.samples.push(self.sample_sum / self.sample_count as f32);
+                self.sample_sum = 0.0;
+                self.sample_count = 0;
+            }
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        let steps = if self.region == Region::Pal { &FRAME_STEPS_PAL } else { &FRAME_STEPS_NTSC };
+        let five_step = (self.frame_counter & 0x80) != 0;
+        let steps = &steps[five_step as usize];
+        self.frame_cycle += 1;
+        match steps.iter().position(|&step| step == self.frame_cycle) {
+            Some(1) => {
+                self.quarter_frame();
+                self.half_frame();
+            }
+            Some(3) => {
+                self.quarter_frame();
+                self.half_frame();
+                if !five_step && (self.frame_counter & 0x40) == 0 {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            Some(_) => self.quarter_frame(),
+            None => {}
+        }
+    }
+
+    fn quarter_frame(&mut self) {
+        self.pulse1.clock_envelope();
+        self.pulse2.clock_envelope();
+        self.noise.envelope.clock(self.noise.volume, self.noise.length_counter_halt);
+
+        let triangle = &mut self.triangle;
+        if triangle.linear_reload {
+            triangle.linear_counter = triangle.linear_counter_load;
+        } else if triangle.linear_counter > 0 {
+            triangle.linear_counter -= 1;
+        }
+        if !triangle.control_flag {
+            triangle.linear_reload = false;
+        }
+    }
+
+    fn half_frame(&mut self) {
+        self.pulse1.clock_length_and_sweep();
+        self.pulse2.clock_length_and_sweep();
+        if !self.triangle.control_flag && self.triangle.length_counter > 0 {
+            self.triangle.length_counter -= 1;
+        }
+        if !self.noise.length_counter_halt && self.noise.length_counter > 0 {
+            self.noise.length_counter -= 1;
+        }
+    }
+
+    /// Combines the channels with the console's non-linear DAC. Full scale is about 1.0.
+    fn mix(&self) -> f32 {
+        let pulse = mix_pulses(self.pulse1.output(), self.pulse2.output());
+        let triangle = TRIANGLE_TABLE[self.triangle.sequence_step as usize] as f32;
+        let noise = self.noise.output() as f32;
+        let dmc = self.dmc.output_level as f32;
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd = if tnd == 0.0 { 0.0 } else { 159.79 / (1.0 / tnd + 100.0) };
+        pulse + tnd
+    }
+}
+
+/// Mixer output for one pulse channel at full volume. Expansion audio is summed with the
+/// APU's on the cartridge, at a level that varies from board to board; we scale every chip
+/// so its loudest channel reaches this, which keeps them all in proportion to the APU.
+pub const PULSE_FULL_VOLUME: f32 = mix_pulses(15, 0);
+
+/// Output of the pulse half of the DAC, for two channels at volume 0-15.
+pub const fn mix_pulses(pulse1: u8, pulse2: u8) -> f32 {
+    let sum = (pulse1 + pulse2) as f32;
+    if sum == 0.0 { 0.0 } else { 95.88 / (8128.0 / sum + 100.0) }
+}
+
+impl Pulse {
+    pub fn new(ones_complement: bool) -> Self {
+        Pulse {
+            duty: 0,
+            length_counter_halt: false,
+            constant_volume: false,
+            volume: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            timer: 0,
+            length_counter: 0,
+            ones_complement,
+            enabled: false,
+            timer_counter: 0,
+            sequence_step: 0,
+            envelope: Envelope::default(),
+            sweep_reload: false,
+            sweep_divider: 0,
+        }
+    }
+
+    /// Handles a write to one of the channel's four registers, picked by the low two address bits.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr & 0x03 {
+            0 => {
+                self.duty = data >> 6;
+                self.length_counter_halt = (data & 0x20) != 0;
+                self.constant_volume = (data & 0x10) != 0;
+                self.volume = data & 0x0F;
+            }
+            1 => {
+                self.sweep_enabled = (data & 0x80) != 0;
+                self.sweep_period = (data >> 4) & 0x07;
+                self.sweep_negate = (data & 0x08) != 0;
+                self.sweep_shift = data & 0x07;
+                self.sweep_reload = true;
+            }
+            2 => self.timer = (self.timer & 0x0700) | data as u16,
+            _ => {
+                self.timer = (self.timer & 0x00FF) | ((data as u16 & 0x07) << 8);
+                if self.enabled {
+                    self.length_counter = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.sequence_step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.length_counter = 0;
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.length_counter > 0
+    }
+
+    /// Clocked every other CPU cycle.
+    pub fn clock_timer(&mut self) {
+        if self.timer_counter == 0 {
+            self.timer_counter = self.timer;
+            self.sequence_step = (self.sequence_step + 1) & 0x07;
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock(self.volume, self.length_counter_halt);
+    }
+
+    pub fn clock_length_and_sweep(&mut self) {
+        if !self.length_counter_halt && self.length_counter > 0 {
+            self.length_counter -= 1;
+        }
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.sweep_muted() {
+            self.timer = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.timer >> self.sweep_shift;
+        if self.sweep_negate {
+            self.timer.saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.timer + change
+        }
+    }
+
+    // The sweep unit silences the channel when the period is out of range, even while disabled
+    fn sweep_muted(&self) -> bool {
+        self.timer < 8 || self.sweep_target() > 0x7FF
+    }
+
+    /// Current volume, 0-15.
+    pub fn output(&self) -> u8 {
+        let high = (DUTY_TABLE[self.duty as usize] << self.sequence_step) & 0x80 != 0;
+        if !high || self.length_counter == 0 || self.sweep_muted() {
+            0
+        } else if self.constant_volume {
+            self.volume
+        } else {
+            self.envelope.decay
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.duty);
+        w.write_bool(self.length_counter_halt);
+        w.write_bool(self.constant_volume);
+        w.write_u8(self.volume);
+        w.write_bool(self.sweep_enabled);
+        w.write_u8(self.sweep_period);
+        w.write_bool(self.sweep_negate);
+        w.write_u8(self.sweep_shift);
+        w.write_u16(self.timer);
+        w.write_u8(self.length_counter);
+        w.write_bool(self.enabled);
+        w.write_u16(self.timer_counter);
+        w.write_u8(self.sequence_step);
+        self.envelope.save_state(w);
+        w.write_bool(self.sweep_reload);
+        w.write_u8(self.sweep_divider);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.duty = r.read_u8()? & 0x03;
+        self.length_counter_halt = r.read_bool()?;
+        self.constant_volume = r.read_bool()?;
+        self.volume = r.read_u8()? & 0x0F;
+        self.sweep_enabled = r.read_bool()?;
+        self.sweep_period = r.read_u8()? & 0x07;
+        self.sweep_negate = r.read_bool()?;
+        self.sweep_shift = r.read_u8()? & 0x07;
+        self.timer = r.read_u16()? & 0x07FF;
+        self.length_counter = r.read_u8()?;
+        self.enabled = r.read_bool()?;
+        self.timer_counter = r.read_u16()? & 0x07FF;
+        self.sequence_step = r.read_u8()? & 0x07;
+        self.envelope.load_state(r)?;
+        self.sweep_reload = r.read_bool()?;
+        self.sweep_divider = r.read_u8()?;
+        Ok(())
+    }
+}
+
+impl Triangle {
+    fn new() -> Self {
+        Triangle {
+            control_flag: false,
+            linear_counter_load: 0,
+            timer: 0,
+            length_counter: 0,
+            enabled: false,
+            timer_counter: 0,
+            sequence_step: 0,
+            linear_counter: 0,
+            linear_reload: false,
+        }
+    }
+
+    /// Clocked every CPU cycle. The sequencer holds its position while either counter is zero.
+    fn clock_timer(&mut self) {
+        if self.timer_counter == 0 {
+            self.timer_counter = self.timer;
+            if self.linear_counter > 0 && self.length_counter > 0 {
+                self.sequence_step = (self.sequence_step + 1) & 0x1F;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.control_flag);
+        w.write_u8(self.linear_counter_load);
+        w.write_u16(self.timer);
+        w.write_u8(self.length_counter);
+        w.write_bool(self.enabled);
+        w.write_u16(self.timer_counter);
+        w.write_u8(self.sequence_step);
+        w.write_u8(self.linear_counter);
+        w.write_bool(self.linear_reload);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.control_flag = r.read_bool()?;
+        self.linear_counter_load = r.read_u8()?;
+        self.timer = r.read_u16()? & 0x07FF;
+        self.length_counter = r.read_u8()?;
+        self.enabled = r.read_bool()?;
+        self.timer_counter = r.read_u16()? & 0x07FF;
+        self.sequence_step = r.read_u8()? & 0x1F;
+        self.linear_counter = r.read_u8()?;
+        self.linear_reload = r.read_bool()?;
+        Ok(())
+    }
+}
+
+impl Noise {
+    fn new(region: Region) -> Self {
+        Noise {
+            length_counter_halt: false,
+            constant_volume: false,
+            volume: 0,
+            mode: false,
+            period: 0,
+            length_counter: 0,
+            noise_periods: if region == Region::Pal { &NOISE_PERIODS_PAL } else { &NOISE_PERIODS_NTSC },
+            enabled: false,
+            timer_counter: 0,
+            shift_register: 1,
+            envelope: Envelope::default(),
+        }
+    }
+
+    /// Clocked every CPU cycle. Shifts the 15-bit LFSR, tapping bit 6 in loop mode and bit 1 otherwise.
+    fn clock_timer(&mut self) {
+        if self.timer_counter == 0 {
+            self.timer_counter = self.noise_periods[self.period as usize] - 1;
+            let tap = if self.mode { 6 } else { 1 };
+            let feedback = (self.shift_register ^ (self.shift_register >> tap)) & 1;
+            self.shift_register = (self.shift_register >> 1) | (feedback << 14);
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if (self.shift_register & 1) != 0 || self.length_counter == 0 {
+            0
+        } else if self.constant_volume {
+            self.volume
+        } else {
+            self.envelope.decay
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.length_counter_halt);
+        w.write_bool(self.constant_volume);
+        w.write_u8(self.volume);
+        w.write_bool(self.mode);
+        w.write_u8(self.period);
+        w.write_u8(self.length_counter);
+        w.write_bool(self.enabled);
+        w.write_u16(self.timer_counter);
+        w.write_u16(self.shift_register);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.length_counter_halt = r.read_bool()?;
+        self.constant_volume = r.read_bool()?;
+        self.volume = r.read_u8()? & 0x0F;
+        self.mode = r.read_bool()?;
+        self.period = r.read_u8()? & 0x0F;
+        self.length_counter = r.read_u8()?;
+        self.enabled = r.read_bool()?;
+        self.timer_counter = r.read_u16()?;
+        self.shift_register = r.read_u16()?;
+        self.envelope.load_state(r)
+    }
+}
+
+impl DMC {
+    fn new(region: Region) -> Self {
+        DMC {
+            irq_enabled: false,
+            loop_flag: false,
+            frequency: 0,
+            load_counter: 0,
+            sample_address: 0,
+            sample_length: 0,
+            rates: if region == Region::Pal { &DMC_RATES_PAL } else { &DMC_RATES_NTSC },
+            current_address: 0xC000,
+            bytes_remaining: 0,
+            sample_buffer: None,
+            timer: DMC_RATES_NTSC[0],
+            shift_register: 0,
+            bits_remaining: 8,
+            silence: true,
+            output_level: 0,
+            irq_flag: false,
+        }
+    }
+
+    /// Starts playing the sample from the top: $C000 + address * 64, length * 16 + 1 bytes.
+    fn restart(&mut self) {
+        self.current_address = 0xC000 | ((self.sample_address as u16) << 6);
+        self.bytes_remaining = ((self.sample_length as u16) << 4) + 1;
+    }
+
+    fn fill(&mut self, data: u8) {
+        self.sample_buffer = Some(data);
+        // The address wraps from $FFFF around to $8000
+        self.current_address = if self.current_address == 0xFFFF { 0x8000 } else { self.current_address + 1 };
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.loop_flag {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq_flag = true;
+            }
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.rates[self.frequency as usize];
+
+        if !self.silence {
+            // Each bit nudges the output level up or down by 2, clamped to 0-127
+            if (self.shift_register & 1) != 0 {
+                if self.output_level <= 125 {
+                    self.output_level += 2;
+                }
+            } else if self.output_level >= 2 {
+                self.output_level -= 2;
+            }
+        }
+        self.shift_register >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.sample_buffer.take() {
+                Some(sample) => {
+                    self.shift_register = sample;
+                    self.silence = false;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bool(self.irq_enabled);
+        w.write_bool(self.loop_flag);
+        w.write_u8(self.frequency);
+        w.write_u8(self.load_counter);
+        w.write_u8(self.sample_address);
+        w.write_u8(self.sample_length);
+        w.write_u16(self.current_address);
+        w.write_u16(self.bytes_remaining);
+        w.write_bool(self.sample_buffer.is_some());
+        w.write_u8(self.sample_buffer.unwrap_or(0));
+        w.write_u16(self.timer);
+        w.write_u8(self.shift_register);
+        w.write_u8(self.bits_remaining);
+        w.write_bool(self.silence);
+        w.write_u8(self.output_level);
+        w.write_bool(self.irq_flag);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enabled = r.read_bool()?;
+        self.loop_flag = r.read_bool()?;
+        self.frequency = r.read_u8()? & 0x0F;
+        self.load_counter = r.read_u8()?;
+        self.sample_address = r.read_u8()?;
+        self.sample_length = r.read_u8()?;
+        self.current_address = r.read_u16()?;
+        self.bytes_remaining = r.read_u16()?;
+        let has_sample = r.read_bool()?;
+        let sample = r.read_u8()?;
+        self.sample_buffer = if has_sample { Some(sample) } else { None };
+        self.timer = r.read_u16()?;
+        self.shift_register = r.read_u8()?;
+        self.bits_remaining = r.read_u8()?;
+        if self.bits_remaining == 0 || self.bits_remaining > 8 {
+            return Err(StateError::Invalid("DMC bit counter"));
+        }
+        self.silence = r.read_bool()?;
+        self.output_level = r.read_u8()? & 0x7F;
+        self.irq_flag = r.read_bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pulse_load_state_masks_out_of_range_fields() {
+        let mut w = StateWriter::new(0);
+        w.write_u8(0xFF); // Duty
+        w.write_bool(false); // Length counter halt
+        w.write_bool(true); // Constant volume
+        w.write_u8(0xFF); // Volume
+        w.write_bool(true); // Sweep enabled
+        w.write_u8(0xFF); // Sweep period
+        w.write_bool(false); // Sweep negate: the target is timer + change
+        w.write_u8(0xF8); // Sweep shift, 0 once masked: the largest change
+        w.write_u16(0xFFFF); // Timer
+        w.write_u8(0xFF); // Length counter
+        w.write_bool(true); // Enabled
+        w.write_u16(0xFFFF); // Timer counter
+        w.write_u8(0xFF); // Sequence step
+        w.write_bytes(&[1, 0xFF, 0xFF]); // Envelope
+        w.write_bool(true); // Sweep reload
+        w.write_u8(0xFF); // Sweep divider
+        let bytes = w.into_bytes();
+
+        let mut r = StateReader::new(&bytes, 0).unwrap();
+        let mut pulse = Pulse::new(false);
+        pulse.load_state(&mut r).unwrap();
+        r.finish().unwrap();
+        assert_eq!(pulse.timer, 0x07FF);
+        assert_eq!(pulse.timer_counter, 0x07FF);
+        for _ in 0..0x1000 {
+            pulse.clock_timer();
+            pulse.clock_length_and_sweep();
+            assert!(pulse.output() <= 15);
+        }
+    }
+
+    #[test]
+    fn triangle_load_state_masks_out_of_range_fields() {
+        let mut w = StateWriter::new(0);
+        w.write_bytes(&[0xFF; 11]);
+        let bytes = w.into_bytes();
+
+        let mut r = StateReader::new(&bytes, 0).unwrap();
+        let mut triangle = Triangle::new();
+        triangle.load_state(&mut r).unwrap();
+        r.finish().unwrap();
+        assert_eq!(triangle.timer, 0x07FF);
+        assert_eq!(triangle.timer_counter, 0x07FF);
+        assert_eq!(triangle.sequence_step, 0x1F);
+        for _ in 0..0x1000 {
+            triangle.clock_timer();
+            assert!(triangle.sequence_step < 32);
+        }
     }
 }
@@ -0,0 +1,430 @@
+use crate::decoder::{AddressMode, InstructionInfo, OPCODE_MAP};
+use std::collections::HashMap;
+
+// Two passes over the source. The first lays out the code: it defines labels and
+// picks zero page or absolute addressing for each instruction, assuming absolute
+// when an operand refers to a label that isn't defined yet. The second pass
+// evaluates every expression and emits bytes with the sizes the first one chose.
+
+/// Assembles standard 6502 syntax into runs of bytes, starting at `origin`.
+/// Each `.org` starts a new run. Supports labels (`name:`), constants
+/// (`name = expr`), `.byte` and `.word`, and expressions made of numbers
+/// (`$FF`, `%1010`, `255`, `'c'`), symbols, `*` for the current address,
+/// `+`/`-`, and a leading `<`/`>` for the low/high byte. `a:` forces absolute
+/// addressing, as does a hex literal of more than two digits (`$00F0`), which is
+/// how the disassembler shows absolute operands in the zero page. Unofficial
+/// opcodes are only accepted when `unofficial` is set.
+pub fn assemble(source: &str, origin: u16, unofficial: bool) -> Result<Vec<(u16, Vec<u8>)>, String> {
+    let mut assembler = Assembler { unofficial, origin, symbols: HashMap::new(), zero_page: HashMap::new(), pc: origin, chunks: Vec::new(), last_pass: false };
+    assembler.pass(source)?;
+    assembler.last_pass = true;
+    assembler.pass(source)?;
+    assembler.chunks.retain(|(_, bytes)| !bytes.is_empty());
+    Ok(assembler.chunks)
+}
+
+struct Assembler {
+    unofficial: bool,
+    origin: u16,
+    symbols: HashMap<String, i32>,
+    zero_page: HashMap<usize, bool>, // Per line, whether the first pass chose the zero page form
+    pc: u16,
+    chunks: Vec<(u16, Vec<u8>)>,
+    last_pass: bool,
+}
+
+impl Assembler {
+    fn pass(&mut self, source: &str) -> Result<(), String> {
+        self.pc = self.origin;
+        self.chunks = vec![(self.origin, Vec::new())];
+        for (index, line) in source.lines().enumerate() {
+            self.line(index, line).map_err(|e| format!("line {}: {}", index + 1, e))?;
+        }
+        Ok(())
+    }
+
+    fn line(&mut self, index: usize, line: &str) -> Result<(), String> {
+        let mut line = strip_comment(line).trim();
+
+        // Labels, possibly followed by a statement
+        if let Some((name, rest)) = line.split_once(':')
+            && is_identifier(name.trim())
+            && !rest.starts_with(':')
+        {
+            self.define(name.trim(), self.pc as i32)?;
+            line = rest.trim();
+        }
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let (word, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let rest = rest.trim();
+        if let Some(expr) = rest.strip_prefix('=') {
+            if !is_identifier(word) {
+                return Err(format!("'{}' is not a valid symbol name", word));
+            }
+            return match self.eval(expr)? {
+                Some(value) => self.define(word, value),
+                None => Ok(()), // Defined in terms of later labels; resolved on the next pass
+            };
+        }
+
+        match word.to_ascii_lowercase().as_str() {
+            ".org" => {
+                let target = self.eval(rest)?.ok_or("'.org' can't refer to labels defined later")?;
+                self.pc = to_word(target)?;
+                self.chunks.push((self.pc, Vec::new()));
+            }
+            ".byte" | ".db" => {
+                for item in split_list(rest) {
+                    if let Some(text) = item.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                        self.emit(text.as_bytes());
+                    } else {
+                        let value = self.eval(item)?.unwrap_or(0);
+                        self.emit(&[to_byte(value)?]);
+                    }
+                }
+            }
+            ".word" | ".dw" | ".addr" => {
+                for item in split_list(rest) {
+                    let value = self.eval(item)?.unwrap_or(0);
+                    self.emit(&to_word(value)?.to_le_bytes());
+                }
+            }
+            _ if word.starts_with('.') => return Err(format!("unknown directive '{}'", word)),
+            _ => self.instruction(index, word, rest)?,
+        }
+        Ok(())
+    }
+
+    fn define(&mut self, name: &str, value: i32) -> Result<(), String> {
+        match self.symbols.insert(name.to_string(), value) {
+            Some(previous) if !self.last_pass && previous != value => Err(format!("'{}' is defined twice", name)),
+            _ => Ok(()),
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        if let Some((_, chunk)) = self.chunks.last_mut() {
+            chunk.extend_from_slice(bytes);
+        }
+        self.pc = self.pc.wrapping_add(bytes.len() as u16);
+    }
+
+    fn instruction(&mut self, index: usize, name: &str, operand: &str) -> Result<(), String> {
+        let name = name.to_ascii_uppercase();
+        let forms: Vec<(u8, &InstructionInfo)> = OPCODE_MAP
+            .iter()
+            .enumerate()
+            .filter_map(|(opcode, info)| Some((opcode as u8, info.as_ref()?)))
+            .filter(|(_, info)| format!("{:?}", info.mnemonic) == name)
+            .collect();
+        if forms.is_empty() {
+            return Err(format!("unknown instruction '{}'", name));
+        }
+        // Official encodings win where an unofficial duplicate exists (NOP, SBC #)
+        let unofficial = self.unofficial;
+        let opcode = |mode: AddressMode| {
+            let mut candidates = forms.iter().filter(|(_, info)| info.mode == mode);
+            let official = candidates.clone().find(|(_, info)| info.is_official());
+            official.or_else(|| candidates.find(|_| unofficial)).map(|&(opcode, _)| opcode)
+        };
+        let has = |mode| forms.iter().any(|(_, info)| info.mode == mode);
+
+        let operand: String = operand.split_whitespace().collect();
+        let upper = operand.to_ascii_uppercase();
+        let (mode, expr) = if operand.is_empty() {
+            (if has(AddressMode::Implied) { AddressMode::Implied } else { AddressMode::Accumulator }, "")
+        } else if upper == "A" {
+            (AddressMode::Accumulator, "")
+        } else if let Some(expr) = operand.strip_prefix('#') {
+            (AddressMode::Immediate, expr)
+        } else if upper.starts_with('(') && upper.ends_with(",X)") {
+            (AddressMode::IndirectX, &operand[1..operand.len() - 3])
+        } else if upper.starts_with('(') && upper.ends_with("),Y") {
+            (AddressMode::IndirectY, &operand[1..operand.len() - 3])
+        } else if upper.starts_with('(') && upper.ends_with(')') {
+            (AddressMode::Indirect, &operand[1..operand.len() - 1])
+        } else if has(AddressMode::Relative) {
+            (AddressMode::Relative, operand.as_str())
+        } else {
+            let (expr, zero_page, absolute) = if upper.ends_with(",X") {
+                (&operand[..operand.len() - 2], AddressMode::ZeroPageX, AddressMode::AbsoluteX)
+            } else if upper.ends_with(",Y") {
+                (&operand[..operand.len() - 2], AddressMode::ZeroPageY, AddressMode::AbsoluteY)
+            } else {
+                (operand.as_str(), AddressMode::ZeroPage, AddressMode::Absolute)
+            };
+            let (expr, forced) = match expr.strip_prefix("a:").or_else(|| expr.strip_prefix("A:")) {
+                Some(expr) => (expr, true),
+                None => (expr, is_wide_literal(expr)),
+            };
+            let short = match self.zero_page.get(&index) {
+                Some(&short) if self.last_pass => short,
+                _ => {
+                    let fits = matches!(self.eval(expr)?, Some(value) if (0..0x100).contains(&value));
+                    let short = !forced && fits && has(zero_page) || !has(absolute);
+                    self.zero_page.insert(index, short);
+                    short
+                }
+            };
+            (if short { zero_page } else { absolute }, expr)
+        };
+
+        let Some(opcode) = opcode(mode) else {
+            let unofficial_only = forms.iter().any(|(_, info)| info.mode == mode && !info.is_official());
+            return Err(match unofficial_only {
+                true => format!("{} {:?} is an unofficial opcode", name, mode),
+                false => format!("{} doesn't support {:?} addressing", name, mode),
+            });
+        };
+        let value = if expr.is_empty() { Some(0) } else { self.eval(expr)? };
+        let value = value.unwrap_or(0);
+        match mode {
+            AddressMode::Implied | AddressMode::Accumulator => self.emit(&[opcode]),
+            AddressMode::Relative => {
+                let offset = value - (self.pc as i32 + 2);
+                if self.last_pass && !(-128..=127).contains(&offset) {
+                    return Err(format!("branch target is {} bytes away, out of range", offset));
+                }
+                self.emit(&[opcode, offset as u8]);
+            }
+            AddressMode::Absolute | AddressMode::AbsoluteX | AddressMode::AbsoluteY | AddressMode::Indirect => {
+                let [lo, hi] = to_word(value)?.to_le_bytes();
+                self.emit(&[opcode, lo, hi]);
+            }
+            _ => self.emit(&[opcode, to_byte(value)?]),
+        }
+        Ok(())
+    }
+
+    /// Evaluates an expression. `None` means it uses a symbol that isn't defined
+    /// yet, which is only an error on the last pass.
+    fn eval(&self, expr: &str) -> Result<Option<i32>, String> {
+        let expr = expr.trim();
+        if let Some(rest) = expr.strip_prefix('<') {
+            return Ok(self.eval(rest)?.map(|value| value & 0xFF));
+        }
+        if let Some(rest) = expr.strip_prefix('>') {
+            return Ok(self.eval(rest)?.map(|value| (value >> 8) & 0xFF));
+        }
+
+        let mut total = Some(0);
+        let mut sign = 1;
+        let mut rest = expr;
+        loop {
+            rest = rest.trim_start();
+            if let Some(negated) = rest.strip_prefix('-') {
+                sign = -sign;
+                rest = negated;
+                continue;
+            }
+            let end = term_end(rest);
+            if end == 0 {
+                return Err(format!("malformed expression '{}'", expr));
+            }
+            let term = self.term(&rest[..end])?;
+            total = total.zip(term).map(|(total, term)| total + sign * term);
+            rest = rest[end..].trim_start();
+            sign = match rest.chars().next() {
+                None => return Ok(total),
+                Some('+') => 1,
+                Some('-') => -1,
+                Some(_) => return Err(format!("malformed expression '{}'", expr)),
+            };
+            rest = &rest[1..];
+        }
+    }
+
+    fn term(&self, term: &str) -> Result<Option<i32>, String> {
+        let number = |digits: &str, radix| i32::from_str_radix(digits, radix).map(Some).map_err(|_| format!("bad number '{}'", term));
+        if term == "*" {
+            Ok(Some(self.pc as i32))
+        } else if let Some(hex) = term.strip_prefix('$') {
+            number(hex, 16)
+        } else if let Some(binary) = term.strip_prefix('%') {
+            number(binary, 2)
+        } else if term.len() == 3 && term.starts_with('\'') && term.ends_with('\'') {
+            Ok(Some(term.as_bytes()[1] as i32))
+        } else if term.starts_with(|c: char| c.is_ascii_digit()) {
+            number(term, 10)
+        } else if let Some(&value) = self.symbols.get(term) {
+            Ok(Some(value))
+        } else if self.last_pass {
+            Err(format!("unknown symbol '{}'", term))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// Length of the term at the start of an expression.
+fn term_end(text: &str) -> usize {
+    if text.starts_with('\'') {
+        return text.len().min(3);
+    }
+    if text.starts_with('*') {
+        return 1;
+    }
+    text.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '$' || c == '%')).unwrap_or(text.len())
+}
+
+/// A hex number written with more than two digits, like `$00F0`.
+fn is_wide_literal(expr: &str) -> bool {
+    expr.strip_prefix('$').is_some_and(|hex| hex.len() > 2 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Removes a `;` comment, leaving semicolons inside quotes alone.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = None;
+    for (i, c) in line.char_indices() {
+        match (c, quoted) {
+            ('"' | '\'', None) => quoted = Some(c),
+            (c, Some(q)) if c == q => quoted = None,
+            (';', None) => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits a directive's comma-separated arguments, keeping quoted strings whole.
+fn split_list(text: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut quoted = None;
+    for (i, c) in text.char_indices() {
+        match (c, quoted) {
+            ('"' | '\'', None) => quoted = Some(c),
+            (c, Some(q)) if c == q => quoted = None,
+            (',', None) => {
+                items.push(text[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(text[start..].trim());
+    items.retain(|item| !item.is_empty());
+    items
+}
+
+fn to_byte(value: i32) -> Result<u8, String> {
+    if (-0x80..0x100).contains(&value) { Ok(value as u8) } else { Err(format!("${:X} doesn't fit in a byte", value)) }
+}
+
+fn to_word(value: i32) -> Result<u16, String> {
+    if (-0x8000..0x10000).contains(&value) { Ok(value as u16) } else { Err(format!("${:X} doesn't fit in a word", value)) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{self, Decoded, MemoryReader};
+
+    /// Bytes placed at an address, for the decoder to read back.
+    struct Memory<'a>(u16, &'a [u8]);
+
+    impl MemoryReader for Memory<'_> {
+        fn read(&self, addr: u16) -> Option<u8> {
+            self.1.get(addr.wrapping_sub(self.0) as usize).copied()
+        }
+    }
+
+    #[test]
+    fn assembles_directives_labels_and_expressions() {
+        let source = "
+            ptr = $10
+            start:
+                lda #<table         ; Low byte of a label defined later
+                sta ptr
+                lda #>table
+                sta ptr+1
+                ldx #'A'
+                bne skip
+                nop
+            skip:
+                jmp start
+            table:
+                .byte 1, %11, \"hi\"
+                .word start, *, table-start
+                .org $9000
+                .byte -1";
+        let chunks = assemble(source, 0x8000, false).unwrap();
+        assert_eq!(
+            chunks,
+            [
+                (
+                    0x8000,
+                    vec![
+                        0xA9, 0x10, 0x85, 0x10, 0xA9, 0x80, 0x85, 0x11, 0xA2, 0x41, 0xD0, 0x01, 0xEA, 0x4C, 0x00, 0x80, // Code
+                        0x01, 0x03, b'h', b'i', 0x00, 0x80, 0x16, 0x80, 0x10, 0x00, // Data
+                    ]
+                ),
+                (0x9000, vec![0xFF]),
+            ]
+        );
+    }
+
+    #[test]
+    fn picks_zero_page_only_for_operands_known_to_fit() {
+        let source = "
+                lda $F0             ; Zero page
+                lda $00F0           ; Written as absolute
+                lda a:$F0           ; Forced absolute
+                lda later           ; Not defined yet, so assumed absolute
+                lda $F0,y           ; No zero page,Y form for LDA
+                ldx $F0,y
+            later = $20";
+        let chunks = assemble(source, 0x8000, false).unwrap();
+        assert_eq!(
+            chunks[0].1,
+            [0xA5, 0xF0, 0xAD, 0xF0, 0x00, 0xAD, 0xF0, 0x00, 0xAD, 0x20, 0x00, 0xB9, 0xF0, 0x00, 0xB6, 0xF0]
+        );
+    }
+
+    #[test]
+    fn accepts_unofficial_opcodes_only_when_asked() {
+        assert!(assemble("lax ($20),y", 0x8000, false).is_err());
+        assert_eq!(assemble("lax ($20),y", 0x8000, true).unwrap(), [(0x8000, vec![0xB3, 0x20])]);
+        // The official encoding wins over an unofficial duplicate
+        assert_eq!(assemble("sbc #1\nnop", 0x8000, true).unwrap(), [(0x8000, vec![0xE9, 0x01, 0xEA])]);
+    }
+
+    #[test]
+    fn reports_errors_with_line_numbers() {
+        assert_eq!(assemble("nop\nfoo", 0x8000, false), Err("line 2: unknown instruction 'FOO'".to_string()));
+        assert!(assemble("bne far\n.org $9000\nfar:", 0x8000, false).unwrap_err().contains("out of range"));
+        assert!(assemble("lda missing", 0x8000, false).unwrap_err().contains("unknown symbol"));
+    }
+
+    #[test]
+    fn reassembles_every_disassembled_opcode() {
+        for (opcode, info) in OPCODE_MAP.iter().enumerate() {
+            let Some(info) = info else { continue };
+            // $00F0 is an absolute operand in the zero page, which mustn't shrink
+            for operand in [[0xF0, 0x00], [0x34, 0x12]] {
+                let bytes = [opcode as u8, operand[0], operand[1]];
+                let Some(Decoded::Instruction(instruction)) = decoder::decode(&Memory(0x8000, &bytes), 0x8000) else {
+                    panic!("${:02X} didn't decode", opcode);
+                };
+                let text = instruction.to_string();
+                let chunks = assemble(&text, 0x8000, true).unwrap_or_else(|e| panic!("{}: {}", text, e));
+                let reassembled = &chunks[0].1;
+                assert_eq!(reassembled[1..], bytes[1..info.bytes as usize], "{}", text);
+
+                // Duplicate encodings (the unofficial NOPs, SBC #) come back as the preferred one
+                let Some(Decoded::Instruction(again)) = decoder::decode(&Memory(0x8000, reassembled), 0x8000) else {
+                    panic!("{} reassembled to ${:02X}, which didn't decode", text, reassembled[0]);
+                };
+                assert_eq!((again.info.mnemonic, again.info.mode), (info.mnemonic, info.mode), "{}", text);
+                assert_eq!(again.to_string(), text);
+            }
+        }
+    }
+}
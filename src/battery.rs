@@ -0,0 +1,47 @@
+use crate::mapper::Mapper;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Keeps a cartridge's battery-backed memory in sync with a `.sav` file next to the ROM.
+pub struct BatteryFile {
+    path: PathBuf,
+    written: Vec<u8>, // What the file currently holds, to skip redundant writes
+}
+
+impl BatteryFile {
+    pub fn for_rom(rom_path: &Path) -> Self {
+        BatteryFile { path: rom_path.with_extension("sav"), written: Vec::new() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads the save file into the mapper. Returns false if there was no file yet.
+    pub fn load(&mut self, mapper: &mut dyn Mapper) -> io::Result<bool> {
+        match fs::read(&self.path) {
+            Ok(data) => {
+                mapper.load_save_data(&data);
+                self.written = data;
+                Ok(true)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the mapper's save data out if it changed since the last flush.
+    pub fn flush(&mut self, mapper: &dyn Mapper) -> io::Result<()> {
+        let Some(data) = mapper.save_data() else { return Ok(()) };
+        if data == self.written.as_slice() {
+            return Ok(());
+        }
+        // Write to a temporary file first so a crash mid-write can't truncate the save
+        let temp = self.path.with_extension("sav.tmp");
+        fs::write(&temp, data)?;
+        fs::rename(&temp, &self.path)?;
+        self.written = data.to_vec();
+        Ok(())
+    }
+}
@@ -0,0 +1,99 @@
+use crate::apu::APU;
+use crate::controller::Controller;
+use crate::mapper::Mapper;
+use crate::ppu::PPU;
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Everything on the CPU bus above internal RAM: PPU and APU registers,
+/// controller ports and the cartridge. RAM itself lives in the CPU core.
+pub struct Bus {
+    pub ppu: PPU,
+    pub apu: APU,
+    pub mapper: Box<dyn Mapper>,
+    pub controllers: [Controller; 2],
+
+    // Page written to $4014, waiting for the console to run the OAM DMA
+    dma_page: Option<u8>,
+}
+
+impl Bus {
+    pub fn new(ppu: PPU, apu: APU, mapper: Box<dyn Mapper>) -> Self {
+        Bus {
+            ppu,
+            apu,
+            mapper,
+            controllers: [Controller::new(), Controller::new()],
+            dma_page: None,
+        }
+    }
+
+    /// Handles CPU reads from $2000-$FFFF.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x2000..=0x3FFF => self.ppu.cpu_read(0x2000 | (addr & 0x07), &mut *self.mapper),
+            0x4015 => self.apu.read_status(),
+            0x4016 => self.controllers[0].read() | 0x40, // Upper bits are open bus
+            0x4017 => self.controllers[1].read() | 0x40,
+            0x4000..=0x401F => 0,
+            _ => self.mapper.cpu_read(addr),
+        }
+    }
+
+    /// Reads $2000-$FFFF without side effects. Registers that can't be peeked read as 0.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4015 => self.apu.peek_status(),
+            0x2000..=0x401F => 0,
+            _ => self.mapper.cpu_peek(addr),
+        }
+    }
+
+    /// Handles CPU writes to $2000-$FFFF.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x2000..=0x3FFF => {
+                let register = 0x2000 | (addr & 0x07);
+                self.mapper.ppu_register_write(register, data);
+                self.ppu.cpu_write(register, data, &mut *self.mapper);
+            }
+            0x4014 => self.dma_page = Some(data), // OAMDMA
+            0x4016 => {
+                // The strobe line is shared by both ports
+                self.controllers[0].write(data);
+                self.controllers[1].write(data);
+            }
+            0x4000..=0x4017 => self.apu.cpu_write(addr, data),
+            0x4018..=0x401F => {}
+            _ => self.mapper.cpu_write(addr, data),
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.mapper.save_state(w);
+        for controller in &self.controllers {
+            controller.save_state(w);
+        }
+        w.write_bool(self.dma_page.is_some());
+        w.write_u8(self.dma_page.unwrap_or(0));
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.mapper.load_state(r)?;
+        for controller in &mut self.controllers {
+            controller.load_state(r)?;
+        }
+        let has_dma = r.read_bool()?;
+        let page = r.read_u8()?;
+        self.dma_page = if has_dma { Some(page) } else { None };
+        Ok(())
+    }
+
+    /// Returns the page of a pending OAM DMA, if the CPU just requested one.
+    pub fn take_dma(&mut self) -> Option<u8> {
+        self.dma_page.take()
+    }
+}
@@ -0,0 +1,262 @@
+use crate::decoder::{AddressMode, DecodedInstruction};
+use crate::disasm::{self, Bank, ByteKind, CodeMap};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+// Source layout: every PRG bank gets its own segment and memory area, written to
+// the output in bank order, so `ld65` rebuilds the PRG ROM byte for byte. Anything
+// ca65 might encode differently (unofficial opcodes, absolute addressing of zero
+// page) is pinned down with `.byte` or an `a:` prefix.
+
+/// Labels for locations in PRG ROM, keyed by bank and offset.
+struct Labels<'a> {
+    banks: &'a [Bank<'a>],
+    code: &'a CodeMap,
+    names: HashMap<(usize, usize), String>,
+}
+
+impl Labels<'_> {
+    /// Where `target`, referenced from `bank`, can carry a label.
+    /// The middle of an instruction can't, so those stay numeric.
+    fn location(&self, bank: usize, target: u16) -> Option<(usize, usize)> {
+        disasm::resolve(self.banks, Some(bank), target)
+            .filter(|&(bank, offset)| self.code.kind(bank, offset) != ByteKind::Operand)
+    }
+
+    fn add(&mut self, bank: usize, target: u16) {
+        if let Some((bank, offset)) = self.location(bank, target) {
+            let addr = self.banks[bank].origin.wrapping_add(offset as u16);
+            // Banks sharing a window would otherwise produce the same name
+            let name = match disasm::resolve(self.banks, None, addr) {
+                Some(_) => format!("L{:04X}", addr),
+                None => format!("B{}_{:04X}", bank, addr),
+            };
+            self.names.entry((bank, offset)).or_insert(name);
+        }
+    }
+
+    fn reference(&self, bank: usize, target: u16) -> Option<&str> {
+        self.location(bank, target).and_then(|location| self.names.get(&location)).map(String::as_str)
+    }
+}
+
+fn instructions<'a>(bank: &'a Bank, code: &'a CodeMap) -> impl Iterator<Item = DecodedInstruction> + 'a {
+    (0..bank.data.len())
+        .filter(|&offset| code.kind(bank.index, offset) == ByteKind::Opcode)
+        .filter_map(|offset| bank.instruction_at(offset))
+}
+
+/// Renders PRG ROM as ca65 source, with labels on every jump, branch and data
+/// reference into ROM. Returns the source and the matching ld65 linker config;
+/// `name` is the file name both are saved under, without extension.
+pub fn export(banks: &[Bank], code: &CodeMap, vectors: [u16; 3], name: &str) -> (String, String) {
+    let mut labels = Labels { banks, code, names: HashMap::new() };
+    for (name, target) in ["Nmi", "Reset", "Irq"].into_iter().zip(vectors) {
+        if let Some(location) = labels.location(banks.len() - 1, target) {
+            labels.names.entry(location).or_insert(name.to_string());
+        }
+    }
+    for bank in banks {
+        for instruction in instructions(bank, code) {
+            let target = match instruction.info.mode {
+                AddressMode::Relative => instruction.branch_target(),
+                AddressMode::Absolute | AddressMode::AbsoluteX | AddressMode::AbsoluteY | AddressMode::Indirect => {
+                    Some(instruction.operand)
+                }
+                _ => None,
+            };
+            if let Some(target) = target {
+                labels.add(bank.index, target);
+            }
+        }
+    }
+
+    let mut source = String::new();
+    let mut config = String::from("MEMORY {\n");
+    for bank in banks {
+        writeln!(
+            config,
+            "    PRG{}: start = ${:04X}, size = ${:04X}, file = %O, fill = yes;",
+            bank.index,
+            bank.origin,
+            bank.data.len()
+        )
+        .unwrap();
+    }
+    config.push_str("}\n\nSEGMENTS {\n");
+    for bank in banks {
+        writeln!(config, "    BANK{0}: load = PRG{0}, type = ro;", bank.index).unwrap();
+    }
+    config.push_str("}\n");
+
+    writeln!(source, "; Build with: ca65 {0}.s && ld65 -C {0}.cfg -o {0}.prg {0}.o", name).unwrap();
+    for bank in banks {
+        writeln!(source, "\n.segment \"BANK{}\"", bank.index).unwrap();
+        write_bank(&mut source, bank, &labels);
+    }
+    (source, config)
+}
+
+fn write_bank(out: &mut String, bank: &Bank, labels: &Labels) {
+    let code = labels.code;
+    let mut offset = 0;
+    while offset < bank.data.len() {
+        if let Some(name) = labels.names.get(&(bank.index, offset)) {
+            writeln!(out, "{}:", name).unwrap();
+        }
+        let addr = bank.origin.wrapping_add(offset as u16);
+        let instruction = match code.kind(bank.index, offset) {
+            ByteKind::Opcode => bank.instruction_at(offset),
+            _ => None,
+        };
+        if let Some(instruction) = instruction {
+            if instruction.info.is_official() {
+                writeln!(out, "    {:?}{}", instruction.info.mnemonic, operand(&instruction, bank.index, labels)).unwrap();
+            } else {
+                let bytes: Vec<String> = instruction.bytes().iter().map(|b| format!("${:02X}", b)).collect();
+                writeln!(out, "    .byte {} ; {}", bytes.join(", "), instruction).unwrap();
+            }
+            offset += instruction.bytes().len();
+            continue;
+        }
+
+        // The vector table, when it points at labelled code
+        if addr == 0xFFFA && offset + 6 == bank.data.len() {
+            let words: Vec<u16> = bank.data[offset..].chunks(2).map(|w| u16::from_le_bytes([w[0], w[1]])).collect();
+            let names: Vec<&str> = words.iter().filter_map(|&w| labels.reference(bank.index, w)).collect();
+            let untouched = (offset..offset + 6).all(|o| code.kind(bank.index, o) == ByteKind::Data)
+                && (offset + 1..offset + 6).all(|o| !labels.names.contains_key(&(bank.index, o)));
+            if names.len() == 3 && untouched {
+                writeln!(out, "    .addr {}", names.join(", ")).unwrap();
+                break;
+            }
+        }
+
+        // Data runs up to the next label or instruction, eight bytes per line
+        let mut len = 1;
+        while len < 8
+            && offset + len < bank.data.len()
+            && code.kind(bank.index, offset + len) == ByteKind::Data
+            && !labels.names.contains_key(&(bank.index, offset + len))
+            && addr.wrapping_add(len as u16) != 0xFFFA
+        {
+            len += 1;
+        }
+        let bytes: Vec<String> = bank.data[offset..offset + len].iter().map(|b| format!("${:02X}", b)).collect();
+        writeln!(out, "    .byte {}", bytes.join(", ")).unwrap();
+        offset += len;
+    }
+}
+
+/// Operand in ca65 syntax, using labels where there are any.
+fn operand(instruction: &DecodedInstruction, bank: usize, labels: &Labels) -> String {
+    let value = instruction.operand;
+    let address = || labels.reference(bank, value).map_or(format!("${:04X}", value), str::to_string);
+    // Keep absolute addressing of zero page, which ca65 would shorten. JMP (ind) has no short form.
+    let absolute = || if value < 0x100 { format!("a:${:04X}", value) } else { address() };
+    match instruction.info.mode {
+        AddressMode::Implied => String::new(),
+        AddressMode::Accumulator => " A".to_string(),
+        AddressMode::Immediate => format!(" #${:02X}", value),
+        AddressMode::ZeroPage => format!(" ${:02X}", value),
+        AddressMode::ZeroPageX => format!(" ${:02X},X", value),
+        AddressMode::ZeroPageY => format!(" ${:02X},Y", value),
+        AddressMode::Relative => {
+            let target = instruction.branch_target().unwrap_or(0);
+            match labels.reference(bank, target) {
+                Some(name) => format!(" {}", name),
+                None => format!(" *{:+}", target.wrapping_sub(instruction.address) as i16),
+            }
+        }
+        AddressMode::Absolute => format!(" {}", absolute()),
+        AddressMode::AbsoluteX => format!(" {},X", absolute()),
+        AddressMode::AbsoluteY => format!(" {},Y", absolute()),
+        AddressMode::Indirect => format!(" ({})", address()),
+        AddressMode::IndirectX => format!(" (${:02X},X)", value),
+        AddressMode::IndirectY => format!(" (${:02X}),Y", value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::cartridge::Cartridge;
+
+    const PROGRAM: &str = "
+            .org $C000
+        reset:
+            sei
+            ldx #0
+        loop:
+            lda table,x
+            sta $00F0           ; Absolute addressing of zero page
+            sta a:$0010,x
+            lax $20             ; Unofficial
+            .byte $04, $44      ; An unofficial NOP with an official twin
+            inx
+            cpx #3
+            bne loop
+            jmp (pointer)
+        nmi:
+            rti
+        pointer:
+            .word reset
+        table:
+            .byte 1, 2, 3
+            .org $FFFA
+            .word nmi, reset, nmi";
+
+    /// ca65 picks zero page for any operand that fits, however many digits it's written
+    /// with, where our assembler keeps `$00F0` absolute. Shortening those literals makes
+    /// only `a:` pin absolute addressing, as in ca65.
+    fn shorten_zero_page(line: &str) -> String {
+        let mut out = String::new();
+        let mut rest = line;
+        while let Some(i) = rest.find("$00") {
+            let (before, after) = rest.split_at(i);
+            let digits = &after[3..];
+            let wide = digits.len() >= 2
+                && digits[..2].chars().all(|c| c.is_ascii_hexdigit())
+                && !digits[2..].starts_with(|c: char| c.is_ascii_hexdigit());
+            out.push_str(before);
+            out.push_str(if wide && !before.ends_with("a:") { "$" } else { "$00" });
+            rest = digits;
+        }
+        out.push_str(rest);
+        out
+    }
+
+    #[test]
+    fn exported_source_reassembles_to_the_same_prg() {
+        let mut prg = vec![0xFF; 0x8000];
+        prg[..4].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+        for (start, bytes) in assembler::assemble(PROGRAM, 0xC000, true).unwrap() {
+            let offset = start as usize - 0x8000;
+            prg[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        let cartridge = Cartridge::for_test(0, 0, prg, Vec::new());
+        let banks = disasm::prg_banks(&cartridge, None).unwrap();
+        let [nmi, reset, irq] = disasm::vectors(&cartridge, None).unwrap();
+        let code = disasm::trace(&banks, &[reset, nmi, irq], &[]);
+        let (source, config) = export(&banks, &code, [nmi, reset, irq], "test");
+
+        assert!(source.contains("STA a:$00F0\n"));
+        assert!(source.contains("STA a:$0010,X\n"));
+        assert!(source.contains("JMP (L"));
+        assert!(source.contains(".byte $A7, $20 ; LAX $20\n"));
+        assert!(source.contains(".byte $04, $44 ; NOP $44\n"));
+        assert!(config.contains("PRG1: start = $C000, size = $4000"));
+
+        // Stand in for ld65: each segment goes at the start of its memory area
+        let source: String = source
+            .lines()
+            .map(|line| match line.strip_prefix(".segment \"BANK").and_then(|rest| rest.strip_suffix('"')) {
+                Some(index) => format!(".org ${:04X}\n", banks[index.parse::<usize>().unwrap()].origin),
+                None => format!("{}\n", shorten_zero_page(line)),
+            })
+            .collect();
+        let rebuilt: Vec<u8> = assembler::assemble(&source, 0x8000, false).unwrap().into_iter().flat_map(|(_, bytes)| bytes).collect();
+        assert!(rebuilt == cartridge.prg_rom, "reassembled PRG differs");
+    }
+}
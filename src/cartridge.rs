@@ -0,0 +1,240 @@
+use crate::console::Region;
+use crate::savestate;
+use crate::unif;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    SingleScreenLower,
+    SingleScreenUpper,
+    FourScreen,
+}
+
+impl Mirroring {
+    /// Maps a nametable address ($2000-$3EFF) to an offset in the console's 2KB of CIRAM.
+    /// Four-screen boards supply their own extra VRAM, so only the first two tables land here.
+    pub fn ciram_index(self, addr: u16) -> usize {
+        let addr = (addr & 0x0FFF) as usize;
+        let table = addr / 0x400;
+        let page = match self {
+            Mirroring::Horizontal => table / 2,
+            Mirroring::Vertical | Mirroring::FourScreen => table % 2,
+            Mirroring::SingleScreenLower => 0,
+            Mirroring::SingleScreenUpper => 1,
+        };
+        page * 0x400 + (addr % 0x400)
+    }
+}
+
+/// Bytes per disk side in an .fds image.
+pub const FDS_SIDE_SIZE: usize = 65500;
+
+/// iNES mapper number reserved for the Famicom Disk System.
+pub const FDS_MAPPER: u16 = 20;
+
+/// A parsed iNES / NES 2.0, UNIF or .fds image.
+pub struct Cartridge {
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>, // Empty when the board uses CHR RAM
+    pub trainer: Option<Vec<u8>>,
+
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+
+    // RAM sizes in bytes. iNES 1.0 headers don't describe these, so we assume the usual 8KB.
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+
+    /// CPU/PPU timing declared by the header, if it declares one.
+    pub region: Option<Region>,
+
+    /// Famicom Disk System sides, in .fds layout; empty for cartridges. For disks,
+    /// `prg_rom` holds the BIOS, which the image doesn't include.
+    pub disk_sides: Vec<Vec<u8>>,
+}
+
+impl Cartridge {
+    /// Parses a ROM image, telling UNIF files and disk images from iNES ones by their magic.
+    pub fn load(bytes: &[u8]) -> Result<Cartridge, String> {
+        if bytes.starts_with(b"UNIF") {
+            unif::parse(bytes)
+        } else if bytes.starts_with(b"FDS\x1A") || bytes.get(1..15) == Some(b"*NINTENDO-HVC*") {
+            Cartridge::from_fds(bytes)
+        } else {
+            Cartridge::from_ines(bytes)
+        }
+    }
+
+    /// Parses an .fds disk image, with or without its 16-byte header. The BIOS isn't part
+    /// of the image, so `prg_rom` is left empty for the caller to fill.
+    pub fn from_fds(bytes: &[u8]) -> Result<Cartridge, String> {
+        let data = if bytes.starts_with(b"FDS\x1A") { bytes.get(16..).unwrap_or_default() } else { bytes };
+        let disk_sides: Vec<Vec<u8>> = data.chunks_exact(FDS_SIDE_SIZE).map(<[u8]>::to_vec).collect();
+        if disk_sides.is_empty() {
+            return Err("FDS image has no complete disk sides.".to_string());
+        }
+        if disk_sides.iter().any(|side| &side[1..15] != b"*NINTENDO-HVC*") {
+            return Err("Invalid FDS disk side header.".to_string());
+        }
+        Ok(Cartridge {
+            prg_rom: Vec::new(),
+            chr_rom: Vec::new(),
+            trainer: None,
+            mapper: FDS_MAPPER,
+            submapper: 0,
+            mirroring: Mirroring::Horizontal,
+            battery: true, // Disk writes are kept in a save file
+            prg_ram_size: 32768,
+            prg_nvram_size: 0,
+            chr_ram_size: 8192,
+            region: Some(Region::Ntsc),
+            disk_sides,
+        })
+    }
+
+    pub fn from_ines(bytes: &[u8]) -> Result<Cartridge, String> {
+        if bytes.len() < 16 || &bytes[0..4] != b"NES\x1A" {
+            return Err("Invalid iNES header.".to_string());
+        }
+        let flags6 = bytes[6];
+        let flags7 = bytes[7];
+        let nes2 = (flags7 & 0x0C) == 0x08;
+
+        let (prg_rom_size, chr_rom_size) = if nes2 {
+            (
+                nes2_rom_size(bytes[4], bytes[9] & 0x0F, 16384),
+                nes2_rom_size(bytes[5], bytes[9] >> 4, 8192),
+            )
+        } else {
+            (bytes[4] as usize * 16384, bytes[5] as usize * 8192)
+        };
+
+        let mut mapper = ((flags7 & 0xF0) | (flags6 >> 4)) as u16;
+        let mut submapper = 0;
+        if nes2 {
+            mapper |= (bytes[8] as u16 & 0x0F) << 8;
+            submapper = bytes[8] >> 4;
+        } else if bytes[12..16].iter().any(|&b| b != 0) {
+            // Old dumping tools wrote signatures like "DiskDude!" over bytes 7-15,
+            // so the upper mapper nibble can't be trusted.
+            mapper &= 0x0F;
+        }
+
+        let mirroring = if flags6 & 0x08 != 0 {
+            Mirroring::FourScreen
+        } else if flags6 & 0x01 != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+
+        let (prg_ram_size, prg_nvram_size, chr_ram_size) = if nes2 {
+            (
+                nes2_ram_size(bytes[10] & 0x0F),
+                nes2_ram_size(bytes[10] >> 4),
+                nes2_ram_size(bytes[11] & 0x0F),
+            )
+        } else {
+            (8192, 0, if chr_rom_size == 0 { 8192 } else { 0 })
+        };
+
+        let region = if nes2 {
+            match bytes[12] & 0x03 {
+                0 => Some(Region::Ntsc),
+                1 => Some(Region::Pal),
+                2 => None, // Multi-region; let the user (or the default) decide
+                _ => Some(Region::Dendy),
+            }
+        } else if bytes[9] & 0x01 != 0 {
+            Some(Region::Pal)
+        } else {
+            None
+        };
+
+        let mut offset = 16;
+        let trainer = if flags6 & 0x04 != 0 {
+            let trainer = bytes.get(offset..offset + 512).ok_or("ROM file is truncated (trainer).")?;
+            offset += 512;
+            Some(trainer.to_vec())
+        } else {
+            None
+        };
+
+        let prg_rom = bytes.get(offset..offset + prg_rom_size).ok_or("ROM file is truncated (PRG ROM).")?;
+        offset += prg_rom_size;
+        let chr_rom = bytes.get(offset..offset + chr_rom_size).ok_or("ROM file is truncated (CHR ROM).")?;
+        if prg_rom.is_empty() {
+            return Err("ROM has no PRG ROM.".to_string());
+        }
+
+        Ok(Cartridge {
+            prg_rom: prg_rom.to_vec(),
+            chr_rom: chr_rom.to_vec(),
+            trainer,
+            mapper,
+            submapper,
+            mirroring,
+            battery: flags6 & 0x02 != 0,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            region,
+            disk_sides: Vec::new(),
+        })
+    }
+
+    /// The CHR memory a board starts with: a copy of CHR ROM, or blank CHR RAM of the size
+    /// the header asks for. Boards map CHR in 8KB at a time, so RAM is never smaller than that.
+    pub fn chr_memory(&self) -> Vec<u8> {
+        if self.chr_rom.is_empty() { vec![0; self.chr_ram_size.max(0x2000)] } else { self.chr_rom.clone() }
+    }
+
+    /// CRC32 of PRG and CHR ROM and any disk, identifying the game regardless of header quirks.
+    pub fn crc32(&self) -> u32 {
+        let mut chunks: Vec<&[u8]> = vec![&self.prg_rom, &self.chr_rom];
+        chunks.extend(self.disk_sides.iter().map(Vec::as_slice));
+        savestate::crc32(&chunks)
+    }
+}
+
+#[cfg(test)]
+impl Cartridge {
+    /// A bare board for tests, with CHR RAM when `chr_rom` is empty.
+    pub fn for_test(mapper: u16, submapper: u8, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Cartridge {
+        Cartridge {
+            chr_ram_size: if chr_rom.is_empty() { 8192 } else { 0 },
+            prg_rom,
+            chr_rom,
+            trainer: None,
+            mapper,
+            submapper,
+            mirroring: Mirroring::Horizontal,
+            battery: false,
+            prg_ram_size: 8192,
+            prg_nvram_size: 0,
+            region: None,
+            disk_sides: Vec::new(),
+        }
+    }
+}
+
+/// Decodes a NES 2.0 ROM size from its LSB byte and MSB nibble.
+fn nes2_rom_size(lsb: u8, msb: u8, unit: usize) -> usize {
+    if msb == 0x0F {
+        // Exponent-multiplier notation: 2^E * (MM*2+1)
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        (1usize << exponent) * multiplier
+    } else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+/// Decodes a NES 2.0 RAM size shift count (64 << n bytes, 0 meaning none).
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::Path;
+
+// FCEUX `.cdl` layout: one flag byte per PRG ROM byte, followed by one per CHR ROM byte.
+
+pub const PRG_CODE: u8 = 0x01; // Executed as an opcode or operand
+pub const PRG_DATA: u8 = 0x02; // Read as data
+// Bits 2-3 hold which 8KB window ($8000/$A000/$C000/$E000) the byte was last accessed through
+pub const PRG_INDIRECT_CODE: u8 = 0x10; // Target of an indirect jump
+pub const PRG_INDIRECT_DATA: u8 = 0x20; // Read through a (zp,X) or (zp),Y pointer
+pub const PRG_PCM: u8 = 0x40; // Fetched by the DMC as sample data
+
+pub const CHR_RENDERED: u8 = 0x01; // Fetched by the PPU while rendering
+pub const CHR_READ: u8 = 0x02; // Read by the CPU through $2007
+
+/// Code/Data Log: how each ROM byte has been used during emulation.
+pub struct CodeDataLog {
+    pub prg: Vec<u8>,
+    pub chr: Vec<u8>, // Empty on CHR RAM boards
+}
+
+impl CodeDataLog {
+    pub fn new(prg_len: usize, chr_len: usize) -> Self {
+        CodeDataLog { prg: vec![0; prg_len], chr: vec![0; chr_len] }
+    }
+
+    /// Opens an existing log to keep adding to it, or starts a new one if there is none.
+    pub fn open(path: &Path, prg_len: usize, chr_len: usize) -> Result<Self, String> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(prg_len, chr_len)),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+        if data.len() != prg_len + chr_len {
+            return Err(format!("{} is {} bytes, but this ROM needs a {} byte log.", path.display(), data.len(), prg_len + chr_len));
+        }
+        let (prg, chr) = data.split_at(prg_len);
+        Ok(CodeDataLog { prg: prg.to_vec(), chr: chr.to_vec() })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut data = self.prg.clone();
+        data.extend_from_slice(&self.chr);
+        fs::write(path, data).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Marks a PRG byte accessed through CPU address `addr`.
+    pub fn log_prg(&mut self, offset: usize, addr: u16, flags: u8) {
+        if let Some(entry) = self.prg.get_mut(offset) {
+            *entry = (*entry & !0x0C) | flags | ((addr >> 11) as u8 & 0x0C);
+        }
+    }
+
+    /// Offsets known to hold the first byte of an instruction: the start of each run of
+    /// code bytes, and indirect jump targets. FCEUX doesn't separate opcodes from operands.
+    pub fn code_starts(&self) -> Vec<usize> {
+        let mut starts = Vec::new();
+        for (offset, &flags) in self.prg.iter().enumerate() {
+            let previous = offset.checked_sub(1).map_or(0, |o| self.prg[o]);
+            if flags & PRG_INDIRECT_CODE != 0 || (flags & PRG_CODE != 0 && previous & PRG_CODE == 0) {
+                starts.push(offset);
+            }
+        }
+        starts
+    }
+}
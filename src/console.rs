@@ -0,0 +1,548 @@
+use crate::apu::APU;
+use crate::bus::Bus;
+use crate::cartridge::Cartridge;
+use crate::cdl::{self, CodeDataLog};
+use crate::cpu::CPU;
+use crate::decoder::{Access, AddressMode, InstructionInfo, MemoryReader, Mnemonic, OPCODE_MAP};
+use crate::mapper::{self, Mapper};
+use crate::ppu::PPU;
+use crate::rewind::Rewind;
+use crate::savestate::{StateError, StateReader, StateWriter};
+use std::str::FromStr;
+
+/// Console timing variant. Everything is derived from a single master clock
+/// that the CPU and PPU divide down by different amounts.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    Dendy, // PAL clocks with an NTSC-like CPU:PPU ratio, common in Russian clones
+}
+
+impl Region {
+    pub fn master_clock_hz(self) -> f64 {
+        match self {
+            Region::Ntsc => 236_250_000.0 / 11.0, // 21.477272 MHz
+            Region::Pal | Region::Dendy => 26_601_712.5,
+        }
+    }
+
+    /// Master clocks per CPU cycle.
+    pub fn cpu_divider(self) -> u64 {
+        match self {
+            Region::Ntsc => 12,
+            Region::Pal => 16,
+            Region::Dendy => 15,
+        }
+    }
+
+    /// Master clocks per PPU dot.
+    pub fn ppu_divider(self) -> u64 {
+        match self {
+            Region::Ntsc => 4,
+            Region::Pal | Region::Dendy => 5,
+        }
+    }
+
+    pub fn cpu_clock_hz(self) -> f64 {
+        self.master_clock_hz() / self.cpu_divider() as f64
+    }
+
+    /// Last scanline of the frame before wrapping to the pre-render line (-1).
+    pub fn last_scanline(self) -> i16 {
+        match self {
+            Region::Ntsc => 260,                 // 262 scanlines
+            Region::Pal | Region::Dendy => 310, // 312 scanlines
+        }
+    }
+
+    /// Scanline on which the VBlank flag is raised.
+    pub fn vblank_scanline(self) -> i16 {
+        match self {
+            Region::Ntsc | Region::Pal => 241,
+            Region::Dendy => 291, // Post-render is stretched so VBlank is as long as NTSC's
+        }
+    }
+
+    /// Only the NTSC 2C02 drops a dot on odd frames while rendering.
+    pub fn skips_odd_frame_dot(self) -> bool {
+        self == Region::Ntsc
+    }
+}
+
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ntsc" => Ok(Region::Ntsc),
+            "pal" => Ok(Region::Pal),
+            "dendy" => Ok(Region::Dendy),
+            _ => Err(format!("Unknown region '{}', expected ntsc, pal or dendy.", s)),
+        }
+    }
+}
+
+// CPU cycles lost to each DMC sample fetch (3-4 on hardware, depending on alignment)
+const DMC_STALL: u64 = 4;
+
+/// The whole machine. The CPU core executes out of its own flat memory, so the
+/// console keeps that memory coherent with the rest of the bus around each instruction:
+/// bytes the instruction will read are fetched from the bus first, and whatever it
+/// wrote is forwarded to the bus afterwards.
+pub struct Console {
+    pub cpu: CPU,
+    pub bus: Bus,
+    region: Region,
+    rom_hash: u32,
+
+    cycles: u64,       // CPU cycles since power-on
+    master_clock: u64, // Master clock ticks since power-on
+    ppu_clock: u64,    // Master clock tick the PPU has caught up to
+    frames: u64,       // Frames completed (VBlank entries) since power-on
+
+    rewind: Option<Rewind>,
+    cdl: Option<CodeDataLog>,             // PRG flags; the PPU keeps the CHR half
+    accesses: Option<Vec<(u16, Access)>>, // Bus accesses made by the last step, when recording
+}
+
+impl Console {
+    pub fn new(cartridge: &Cartridge, region: Region) -> Result<Self, String> {
+        let mapper = mapper::create(cartridge)?;
+        Ok(Console::with_mapper(mapper, region, cartridge.crc32()))
+    }
+
+    /// Builds a console around a board that doesn't come from a cartridge image, such as
+    /// the NSF player's. `rom_hash` ties save states to whatever the board was built from.
+    pub fn with_mapper(mapper: Box<dyn Mapper>, region: Region, rom_hash: u32) -> Self {
+        Console {
+            cpu: CPU::new(),
+            bus: Bus::new(PPU::new(region), APU::new(region), mapper),
+            region,
+            rom_hash,
+            cycles: 0,
+            master_clock: 0,
+            ppu_clock: 0,
+            frames: 0,
+            rewind: None,
+            cdl: None,
+            accesses: None,
+        }
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn reset(&mut self) {
+        self.load_vectors();
+        self.cpu.reset();
+        self.clock(7);
+    }
+
+    /// Executes one instruction, plus any DMA or interrupt it triggers, and runs the
+    /// PPU and APU for the same amount of time. Returns the CPU cycles taken, 0 on JAM.
+    pub fn step(&mut self) -> u32 {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.clear();
+        }
+        let pending_write = self.prefetch();
+        let cycles = self.cpu.step();
+        if cycles == 0 {
+            return 0;
+        }
+        let mut total = cycles as u64;
+        if let Some(addr) = pending_write {
+            let data = self.cpu.memory()[addr as usize];
+            self.write(addr, data);
+        }
+        total += self.clock(cycles as u64);
+
+        if let Some(page) = self.bus.take_dma() {
+            let stall = self.oam_dma(page);
+            total += stall + self.clock(stall);
+        }
+
+        if self.bus.ppu.poll_nmi() {
+            self.load_vectors();
+            self.note_interrupt(0xFFFA);
+            let cycles = self.cpu.nmi() as u64;
+            total += cycles + self.clock(cycles);
+        } else if (self.bus.mapper.irq() || self.bus.apu.irq()) && (self.cpu.status & 0x04) == 0 {
+            self.load_vectors();
+            self.note_interrupt(0xFFFE);
+            let cycles = self.cpu.irq() as u64;
+            total += cycles + self.clock(cycles);
+        }
+
+        if self.bus.ppu.take_frame_complete() {
+            self.frames += 1;
+            self.record_rewind();
+        }
+        total as u32
+    }
+
+    /// Runs until the PPU finishes a frame. Returns false if the CPU jammed.
+    pub fn run_frame(&mut self) -> bool {
+        let start = self.frames;
+        while self.frames == start {
+            if self.step() == 0 {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Number of sides of the disk loaded into the Famicom Disk System; 0 for cartridges.
+    pub fn disk_sides(&self) -> usize {
+        self.bus.mapper.disk_sides()
+    }
+
+    /// Disk side in the drive, or None if it's empty.
+    pub fn disk_side(&self) -> Option<usize> {
+        self.bus.mapper.disk_side()
+    }
+
+    /// Swaps disk sides: inserts `side` (counting from 0, side A of disk 1), or ejects the
+    /// disk with None. Games expect a moment with the drive empty between sides.
+    pub fn insert_disk(&mut self, side: Option<usize>) -> Result<(), String> {
+        match side {
+            _ if self.disk_sides() == 0 => return Err("no disk system loaded".to_string()),
+            Some(side) if side >= self.disk_sides() => {
+                return Err(format!("the disk only has {} side(s)", self.disk_sides()));
+            }
+            _ => {}
+        }
+        self.bus.mapper.insert_disk(side);
+        Ok(())
+    }
+
+    /// Starts keeping a snapshot every `interval` frames, using at most `max_bytes` for history.
+    pub fn enable_rewind(&mut self, interval: u32, max_bytes: usize) {
+        let mut rewind = Rewind::new(interval, max_bytes);
+        rewind.push(self.save_state());
+        self.rewind = Some(rewind);
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// Bytes the rewind history is using, or None if rewinding is off.
+    pub fn rewind_memory_usage(&self) -> Option<usize> {
+        self.rewind.as_ref().map(Rewind::memory_usage)
+    }
+
+    /// Forgets the rewind history, keeping only a snapshot of the current state.
+    pub fn clear_rewind(&mut self) {
+        if let Some(mut rewind) = self.rewind.take() {
+            rewind.clear();
+            rewind.push(self.save_state());
+            self.rewind = Some(rewind);
+        }
+    }
+
+    /// Steps back at least `frames` frames, as far as the history allows.
+    /// Returns the number of frames actually rewound.
+    pub fn rewind(&mut self, frames: u32) -> u64 {
+        let Some((state, rewound)) = self.rewind.as_mut().and_then(|r| r.rewind(frames)) else {
+            return 0;
+        };
+        self.load_state(&state).expect("rewind snapshots come from this console");
+        rewound
+    }
+
+    fn record_rewind(&mut self) {
+        if let Some(mut rewind) = self.rewind.take() {
+            if rewind.frame_done() {
+                rewind.push(self.save_state());
+            }
+            self.rewind = Some(rewind);
+        }
+    }
+
+    /// Starts or stops recording the bus accesses each `step` makes, for watchpoints.
+    pub fn record_accesses(&mut self, enabled: bool) {
+        self.accesses = enabled.then(Vec::new);
+    }
+
+    /// Every bus access the last `step` made, in order, if recording: operand reads and
+    /// writes (an RMW instruction's as a read and a write), pointer fetches, stack pushes
+    /// and pulls, interrupt vector fetches, OAM DMA and DMC sample fetches. Opcode and
+    /// operand byte fetches aren't included; PC breakpoints cover those.
+    pub fn accesses(&self) -> &[(u16, Access)] {
+        self.accesses.as_deref().unwrap_or(&[])
+    }
+
+    fn note(&mut self, addr: u16, access: Access) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push((addr, access));
+        }
+    }
+
+    /// Notes `count` pushes onto the stack, starting where the stack pointer is now.
+    fn note_pushes(&mut self, count: u8) {
+        for i in 0..count {
+            self.note(0x0100 | self.cpu.sp.wrapping_sub(i) as u16, Access::Write);
+        }
+    }
+
+    /// Notes `count` pulls off the stack, starting where the stack pointer is now.
+    fn note_pulls(&mut self, count: u8) {
+        for i in 1..=count {
+            self.note(0x0100 | self.cpu.sp.wrapping_add(i) as u16, Access::Read);
+        }
+    }
+
+    /// Notes what entering an interrupt does to the bus: PC and P pushed, then the vector read.
+    fn note_interrupt(&mut self, vector: u16) {
+        self.note_pushes(3);
+        self.note(vector, Access::Read);
+        self.note(vector + 1, Access::Read);
+    }
+
+    /// Starts recording how ROM is used, adding to whatever `log` already holds.
+    pub fn enable_code_data_log(&mut self, mut log: CodeDataLog) {
+        self.bus.ppu.set_chr_log(Some(std::mem::take(&mut log.chr)));
+        self.cdl = Some(log);
+    }
+
+    /// Snapshot of the Code/Data Log, if logging is enabled.
+    pub fn code_data_log(&self) -> Option<CodeDataLog> {
+        let log = self.cdl.as_ref()?;
+        Some(CodeDataLog { prg: log.prg.clone(), chr: self.bus.ppu.chr_log().unwrap_or_default().to_vec() })
+    }
+
+    fn log_prg(&mut self, addr: u16, flags: u8) {
+        if let Some(log) = &mut self.cdl
+            && let Some(offset) = self.bus.mapper.prg_offset(addr)
+        {
+            log.log_prg(offset, addr, flags);
+        }
+    }
+
+    /// Serializes the whole machine into a versioned state tied to the loaded ROM.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new(self.rom_hash);
+        w.write_u64(self.cycles);
+        w.write_u64(self.master_clock);
+        w.write_u64(self.ppu_clock);
+        w.write_u64(self.frames);
+        self.cpu.save_state(&mut w);
+        self.bus.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    /// Restores a state from `save_state`. On error the machine is left as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data, self.rom_hash)?;
+        let backup = self.save_state();
+        if let Err(e) = self.read_state(&mut r).and_then(|()| r.finish()) {
+            let mut r = StateReader::new(&backup, self.rom_hash).expect("backup state is valid");
+            self.read_state(&mut r).expect("backup state is valid");
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    fn read_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.cycles = r.read_u64()?;
+        self.master_clock = r.read_u64()?;
+        self.ppu_clock = r.read_u64()?;
+        self.frames = r.read_u64()?;
+        self.cpu.load_state(r)?;
+        self.bus.load_state(r)
+    }
+
+    /// Advances the master clock by a number of CPU cycles, letting the PPU catch up.
+    /// Returns the extra cycles the CPU was stalled for by DMC sample fetches.
+    fn clock(&mut self, cpu_cycles: u64) -> u64 {
+        let cpu_divider = self.region.cpu_divider();
+        let ppu_divider = self.region.ppu_divider();
+        let mut remaining = cpu_cycles;
+        let mut stalled = 0;
+        while remaining > 0 {
+            remaining -= 1;
+            self.master_clock += cpu_divider;
+            self.cycles += 1;
+            self.bus.apu.tick(self.bus.mapper.audio_output());
+            self.bus.mapper.cpu_tick();
+            while self.ppu_clock + ppu_divider <= self.master_clock {
+                self.ppu_clock += ppu_divider;
+                self.bus.ppu.step(&mut *self.bus.mapper);
+            }
+            if let Some(addr) = self.bus.apu.dmc_request() {
+                self.log_prg(addr, cdl::PRG_DATA | cdl::PRG_PCM);
+                let data = self.read(addr);
+                self.bus.apu.dmc_fill(data);
+                remaining += DMC_STALL;
+                stalled += DMC_STALL;
+            }
+        }
+        stalled
+    }
+
+    /// Copies a page of CPU memory to OAM. Returns the cycles the CPU is stalled for.
+    fn oam_dma(&mut self, page: u8) -> u64 {
+        for i in 0..=0xFF {
+            let data = self.read(((page as u16) << 8) | i);
+            self.write(0x2004, data);
+        }
+        // One extra cycle to align with a read cycle
+        513 + (self.cycles % 2)
+    }
+
+    /// Performs a CPU bus read, with all its side effects.
+    pub fn read(&mut self, addr: u16) -> u8 {
+        self.note(addr, Access::Read);
+        match addr {
+            0x0000..=0x1FFF => self.cpu.memory()[(addr & 0x07FF) as usize],
+            _ => self.bus.read(addr),
+        }
+    }
+
+    /// Reads the CPU bus without side effects.
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.cpu.memory()[(addr & 0x07FF) as usize],
+            _ => self.bus.peek(addr),
+        }
+    }
+
+    /// Performs a CPU bus write.
+    pub fn write(&mut self, addr: u16, data: u8) {
+        self.note(addr, Access::Write);
+        match addr {
+            0x0000..=0x1FFF => self.cpu.memory_mut()[(addr & 0x07FF) as usize] = data,
+            _ => self.bus.write(addr, data),
+        }
+    }
+
+    /// Refreshes the CPU's copy of a byte from a side-effect-free source (RAM mirrors, cartridge).
+    fn load(&mut self, addr: u16) {
+        let value = match addr {
+            0x0800..=0x1FFF | 0x4020..=0xFFFF => self.peek(addr),
+            _ => return,
+        };
+        self.cpu.memory_mut()[addr as usize] = value;
+    }
+
+    fn load_vectors(&mut self) {
+        for addr in 0xFFFA..=0xFFFF {
+            self.load(addr);
+        }
+    }
+
+    /// Resolves the address an instruction's operand refers to, using the current registers.
+    pub(crate) fn operand_address(&self, mode: AddressMode, operand: u16) -> Option<u16> {
+        let memory = self.cpu.memory();
+        let zero_page_pointer = |ptr: u8| u16::from_le_bytes([memory[ptr as usize], memory[ptr.wrapping_add(1) as usize]]);
+        match mode {
+            AddressMode::ZeroPage => Some(operand & 0xFF),
+            AddressMode::ZeroPageX => Some((operand as u8).wrapping_add(self.cpu.idx) as u16),
+            AddressMode::ZeroPageY => Some((operand as u8).wrapping_add(self.cpu.idy) as u16),
+            AddressMode::Absolute | AddressMode::Indirect => Some(operand),
+            AddressMode::AbsoluteX => Some(operand.wrapping_add(self.cpu.idx as u16)),
+            AddressMode::AbsoluteY => Some(operand.wrapping_add(self.cpu.idy as u16)),
+            AddressMode::IndirectX => Some(zero_page_pointer((operand as u8).wrapping_add(self.cpu.idx))),
+            AddressMode::IndirectY => Some(zero_page_pointer(operand as u8).wrapping_add(self.cpu.idy as u16)),
+            _ => None,
+        }
+    }
+
+    /// Decodes the instruction at `addr` without side effects.
+    fn instruction_at(&self, addr: u16) -> Option<(&'static InstructionInfo, u16)> {
+        let info = OPCODE_MAP[self.peek(addr) as usize].as_ref()?;
+        let operand = match info.bytes {
+            2 => self.peek(addr.wrapping_add(1)) as u16,
+            3 => u16::from_le_bytes([self.peek(addr.wrapping_add(1)), self.peek(addr.wrapping_add(2))]),
+            _ => 0,
+        };
+        Some((info, operand))
+    }
+
+    /// Makes the CPU's memory hold what the next instruction will read. Returns the
+    /// address whose new value must be forwarded to the bus once it has executed.
+    fn prefetch(&mut self) -> Option<u16> {
+        let pc = self.cpu.pc;
+        self.load(pc);
+        let info = OPCODE_MAP[self.cpu.memory()[pc as usize] as usize].as_ref()?;
+        for i in 1..info.bytes as u16 {
+            self.load(pc.wrapping_add(i));
+        }
+        for i in 0..info.bytes as u16 {
+            self.log_prg(pc.wrapping_add(i), cdl::PRG_CODE);
+        }
+        match info.mnemonic {
+            Mnemonic::BRK => {
+                self.load_vectors();
+                self.note_interrupt(0xFFFE);
+            }
+            Mnemonic::PHA | Mnemonic::PHP => self.note_pushes(1),
+            Mnemonic::JSR => self.note_pushes(2),
+            Mnemonic::PLA | Mnemonic::PLP => self.note_pulls(1),
+            Mnemonic::RTS => self.note_pulls(2),
+            Mnemonic::RTI => self.note_pulls(3),
+            _ => {}
+        }
+
+        let (info, operand) = self.instruction_at(pc)?;
+        if info.mode == AddressMode::Indirect {
+            // JMP ($xxFF) fetches its high byte from $xx00
+            let pointer_high = (operand & 0xFF00) | (operand.wrapping_add(1) & 0x00FF);
+            self.load(operand);
+            self.load(pointer_high);
+            self.note(operand, Access::Read);
+            self.note(pointer_high, Access::Read);
+            self.log_prg(operand, cdl::PRG_DATA);
+            self.log_prg(pointer_high, cdl::PRG_DATA);
+            let memory = self.cpu.memory();
+            let target = u16::from_le_bytes([memory[operand as usize], memory[pointer_high as usize]]);
+            self.log_prg(target, cdl::PRG_INDIRECT_CODE);
+            return None;
+        }
+
+        let addr = self.operand_address(info.mode, operand)?;
+        if matches!(info.mode, AddressMode::IndirectX | AddressMode::IndirectY) {
+            // The pointer itself comes from the zero page
+            let pointer = match info.mode {
+                AddressMode::IndirectX => (operand as u8).wrapping_add(self.cpu.idx),
+                _ => operand as u8,
+            };
+            self.note(pointer as u16, Access::Read);
+            self.note(pointer.wrapping_add(1) as u16, Access::Read);
+        }
+        if info.access? != Access::Write {
+            let indirect = matches!(info.mode, AddressMode::IndirectX | AddressMode::IndirectY);
+            self.log_prg(addr, if indirect { cdl::PRG_DATA | cdl::PRG_INDIRECT_DATA } else { cdl::PRG_DATA });
+        }
+        match info.access? {
+            Access::Read => {
+                let value = self.read(addr);
+                self.cpu.memory_mut()[addr as usize] = value;
+                None
+            }
+            Access::Write => Some(addr),
+            Access::ReadModifyWrite => {
+                let value = self.read(addr);
+                self.cpu.memory_mut()[addr as usize] = value;
+                Some(addr)
+            }
+        }
+    }
+}
+
+impl MemoryReader for Console {
+    /// Memory as the CPU sees it right now, read without side effects.
+    fn read(&self, addr: u16) -> Option<u8> {
+        Some(self.peek(addr))
+    }
+}
@@ -0,0 +1,94 @@
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// Button bits, in the order the joypad shifts them out.
+pub const BUTTON_A: u8 = 0x01;
+pub const BUTTON_B: u8 = 0x02;
+pub const BUTTON_SELECT: u8 = 0x04;
+pub const BUTTON_START: u8 = 0x08;
+pub const BUTTON_UP: u8 = 0x10;
+pub const BUTTON_DOWN: u8 = 0x20;
+pub const BUTTON_LEFT: u8 = 0x40;
+pub const BUTTON_RIGHT: u8 = 0x80;
+
+const BUTTON_NAMES: [(&str, u8); 8] = [
+    ("a", BUTTON_A),
+    ("b", BUTTON_B),
+    ("select", BUTTON_SELECT),
+    ("start", BUTTON_START),
+    ("up", BUTTON_UP),
+    ("down", BUTTON_DOWN),
+    ("left", BUTTON_LEFT),
+    ("right", BUTTON_RIGHT),
+];
+
+/// Parses held buttons written like `a+start` or `up+right`, or `none`.
+pub fn parse_buttons(text: &str) -> Result<u8, String> {
+    if text.eq_ignore_ascii_case("none") {
+        return Ok(0);
+    }
+    text.split('+').try_fold(0, |buttons, name| {
+        let (_, bit) = BUTTON_NAMES
+            .iter()
+            .find(|(button, _)| button.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown button '{}', expected a, b, select, start, up, down, left or right", name))?;
+        Ok(buttons | bit)
+    })
+}
+
+/// Names of the buttons set in `buttons`, joined with `+`, or `none`.
+pub fn button_names(buttons: u8) -> String {
+    let names: Vec<&str> = BUTTON_NAMES.iter().filter(|&&(_, bit)| buttons & bit != 0).map(|&(name, _)| name).collect();
+    if names.is_empty() { "none".to_string() } else { names.join("+") }
+}
+
+/// A standard joypad, read one bit at a time through $4016/$4017.
+pub struct Controller {
+    buttons: u8, // Currently held buttons
+    shift: u8,   // Latched copy being shifted out
+    strobe: bool,
+}
+
+impl Controller {
+    pub fn new() -> Self {
+        Controller { buttons: 0, shift: 0, strobe: false }
+    }
+
+    pub fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.buttons = buttons;
+    }
+
+    /// Handles writes to $4016. While the strobe bit is high the buttons are continuously reloaded.
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 0x01 != 0;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.buttons);
+        w.write_u8(self.shift);
+        w.write_bool(self.strobe);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.buttons = r.read_u8()?;
+        self.shift = r.read_u8()?;
+        self.strobe = r.read_bool()?;
+        Ok(())
+    }
+
+    pub fn read(&mut self) -> u8 {
+        if self.strobe {
+            return self.buttons & 0x01;
+        }
+        let bit = self.shift & 0x01;
+        // Official pads return 1 once all eight buttons have been read
+        self.shift = (self.shift >> 1) | 0x80;
+        bit
+    }
+}
@@ -1,4 +1,5 @@
 use crate::decoder::{self, AddressMode, Mnemonic};
+use crate::savestate::{StateError, StateReader, StateWriter};
 use std::fmt;
 
 // ... (flags module is unchanged) ...
@@ -24,6 +25,61 @@ impl CPU {
     // ... (All the old methods are restored here) ...
     // ... mem_read, mem_write, load, reset, step, etc. ...
     // ... These methods will use `self.memory` directly ...
+
+    /// The flat 64KB address space the core executes out of.
+    /// The console keeps it in sync with the rest of the bus around each instruction.
+    pub fn memory(&self) -> &[u8; 0x10000] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8; 0x10000] {
+        &mut self.memory
+    }
+
+    /// Services a non-maskable interrupt. Returns the cycles taken.
+    pub fn nmi(&mut self) -> u8 {
+        self.interrupt(0xFFFA);
+        7
+    }
+
+    /// Services a maskable interrupt. The caller checks the I flag.
+    pub fn irq(&mut self) -> u8 {
+        self.interrupt(0xFFFE);
+        7
+    }
+
+    /// Saves registers and the 2KB of internal RAM. The rest of `memory` mirrors
+    /// the bus and is refetched by the console, so it isn't part of the state.
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u16(self.pc);
+        w.write_u8(self.sp);
+        w.write_u8(self.ac);
+        w.write_u8(self.idx);
+        w.write_u8(self.idy);
+        w.write_u8(self.status);
+        w.write_bytes(&self.memory[..0x0800]);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.pc = r.read_u16()?;
+        self.sp = r.read_u8()?;
+        self.ac = r.read_u8()?;
+        self.idx = r.read_u8()?;
+        self.idy = r.read_u8()?;
+        self.status = r.read_u8()?;
+        r.read_bytes(&mut self.memory[..0x0800])
+    }
+
+    fn interrupt(&mut self, vector: u16) {
+        // B flag clear, unused bit set, as pushed by hardware interrupts
+        let status = (self.status & !0x10) | 0x20;
+        for byte in [(self.pc >> 8) as u8, self.pc as u8, status] {
+            self.memory[0x0100 + self.sp as usize] = byte;
+            self.sp = self.sp.wrapping_sub(1);
+        }
+        self.status |= 0x04; // Set interrupt disable
+        self.pc = u16::from_le_bytes([self.memory[vector as usize], self.memory[vector as usize + 1]]);
+    }
 }
 
 impl fmt::Display for CPU {
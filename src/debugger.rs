@@ -0,0 +1,603 @@
+use crate::assembler;
+use crate::console::Console;
+use crate::controller;
+use crate::decoder::{self, Access, Decoded, flag};
+use crate::symbols::{Location, Symbols};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+Commands:
+  r, regs                      Show CPU registers and PPU position
+  s, step [n]                  Execute n instructions (default 1)
+  t, trace [n]                 Execute n instructions, logging each one with the registers
+  sl, scanline                 Run to the next scanline
+  f, frame                     Run to the start of the next VBlank
+  c, continue                  Run until a breakpoint, watchpoint or JAM
+  b, break <addr>              Set a PC breakpoint
+  w, watch <addr>[-<end>] [r|w|rw]
+                               Set a watchpoint on a bus address or range (default rw)
+  del, delete <addr>           Remove the breakpoint at an address and any watchpoints covering it
+  l, list                      List breakpoints and watchpoints
+  d, disasm [addr] [count]     Disassemble around PC, or from an address
+  i, info [addr]               Describe the instruction at PC or an address: cycles, flags and effects
+  x, mem <addr> [len]          Dump memory (without read side effects)
+  poke <addr> <value>          Write a byte through the bus
+  a, asm <addr> [instruction]  Assemble into memory; without an instruction, read lines until an empty one
+  input <1|2> [buttons]        Show or set the buttons held on a controller, like a+start or none
+  rewind <frames>              Step back in time
+  rewind [on|off|clear]        Show the history's memory use, start or stop recording it, or forget it
+  disk [side|eject]            Show the FDS drive, insert a disk side (from 0) or eject
+  q, quit                      Exit
+Addresses and values are hex, optionally prefixed with $ or 0x. Addresses can also be symbol names.";
+
+// Memory the debugger allows for rewind history
+const REWIND_BUDGET: usize = 32 * 1024 * 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn matches(&self, addr: u16, access: Access) -> bool {
+        if addr < self.start || addr > self.end {
+            return false;
+        }
+        match self.kind {
+            WatchKind::ReadWrite => true,
+            WatchKind::Read => access != Access::Write,
+            WatchKind::Write => access != Access::Read,
+        }
+    }
+}
+
+/// Why a run returned control to the debugger.
+pub enum Stop {
+    Done,
+    Breakpoint(u16),
+    Watchpoint { pc: u16, addr: u16, access: Access, kind: WatchKind }, // `kind` of the watchpoint that fired
+    Jam,
+}
+
+/// PC breakpoints and bus watchpoints, shared by the command-line debugger and the GDB stub.
+pub struct Breakpoints {
+    pub pc: BTreeSet<u16>,
+    pub watch: Vec<Watchpoint>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Breakpoints { pc: BTreeSet::new(), watch: Vec::new() }
+    }
+
+    /// Executes instructions until `done` returns true after one, or a breakpoint,
+    /// watchpoint or JAM interrupts. Breakpoints don't fire on the first instruction,
+    /// so continuing from one makes progress. Watchpoints see every bus access an
+    /// instruction makes, including stack, interrupt and DMA traffic, and stop after it.
+    pub fn run_until(&self, console: &mut Console, mut done: impl FnMut(&Console) -> bool) -> Stop {
+        console.record_accesses(!self.watch.is_empty());
+        let mut first = true;
+        loop {
+            let pc = console.cpu.pc;
+            if !first && self.pc.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+            first = false;
+
+            if console.step() == 0 {
+                return Stop::Jam;
+            }
+            let watched = console.accesses().iter().find_map(|&(addr, access)| {
+                let watchpoint = self.watch.iter().find(|w| w.matches(addr, access))?;
+                Some(Stop::Watchpoint { pc, addr, access, kind: watchpoint.kind })
+            });
+            if let Some(stop) = watched {
+                return stop;
+            }
+            if done(console) {
+                return Stop::Done;
+            }
+        }
+    }
+}
+
+/// Interactive command-line debugger driving a console.
+pub struct Debugger {
+    console: Console,
+    breakpoints: Breakpoints,
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new(mut console: Console, symbols: Symbols) -> Self {
+        console.enable_rewind(1, REWIND_BUDGET);
+        Debugger { console, breakpoints: Breakpoints::new(), symbols }
+    }
+
+    pub fn console(&self) -> &Console {
+        &self.console
+    }
+
+    /// Reads commands from stdin until `quit` or end of input.
+    /// An empty line repeats the previous command.
+    pub fn run(&mut self) {
+        println!("samnes debugger. Type 'help' for a list of commands.");
+        self.print_current();
+        let stdin = io::stdin();
+        let mut last = String::new();
+        loop {
+            print!("(samnes) ");
+            io::stdout().flush().ok();
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+                break;
+            }
+            let line = line.trim();
+            let command = if line.is_empty() { last.clone() } else { line.to_string() };
+            match self.execute(&command) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => println!("Error: {}", e),
+            }
+            last = command;
+        }
+    }
+
+    /// Runs one command line. Returns false when the user asked to quit.
+    fn execute(&mut self, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let Some((&command, args)) = words.split_first() else { return Ok(true) };
+        match command {
+            "h" | "help" => println!("{}", HELP),
+            "q" | "quit" => return Ok(false),
+            "r" | "regs" => self.print_registers(),
+            "s" | "step" => {
+                let count = args.first().map(|n| n.parse::<u64>().map_err(|e| e.to_string())).transpose()?.unwrap_or(1);
+                let mut remaining = count.max(1);
+                let stop = self.run_until(|_| {
+                    remaining -= 1;
+                    remaining == 0
+                });
+                self.report(stop);
+            }
+            "t" | "trace" => {
+                let count = args.first().map(|n| n.parse::<u64>().map_err(|e| e.to_string())).transpose()?.unwrap_or(1);
+                for _ in 0..count.max(1) {
+                    self.print_trace_line();
+                    let stop = self.run_until(|_| true);
+                    if !matches!(stop, Stop::Done) {
+                        self.report(stop);
+                        break;
+                    }
+                }
+            }
+            "sl" | "scanline" => {
+                let start = self.console.bus.ppu.scanline();
+                let stop = self.run_until(|console| console.bus.ppu.scanline() != start);
+                self.report(stop);
+            }
+            "f" | "frame" => {
+                let start = self.console.frames();
+                let stop = self.run_until(|console| console.frames() != start);
+                self.report(stop);
+            }
+            "c" | "continue" => {
+                let stop = self.run_until(|_| false);
+                self.report(stop);
+            }
+            "b" | "break" => {
+                let addr = self.parse_address(args.first().ok_or("usage: break <addr>")?)?;
+                self.breakpoints.pc.insert(addr);
+                println!("Breakpoint at {}", self.describe(addr));
+            }
+            "w" | "watch" => {
+                let range = args.first().ok_or("usage: watch <addr>[-<end>] [r|w|rw]")?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (self.parse_address(start)?, self.parse_address(end)?),
+                    None => {
+                        let addr = self.parse_address(range)?;
+                        (addr, addr)
+                    }
+                };
+                let kind = match args.get(1).copied().unwrap_or("rw") {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" => WatchKind::ReadWrite,
+                    other => return Err(format!("unknown watch kind '{}', expected r, w or rw", other)),
+                };
+                self.breakpoints.watch.push(Watchpoint { start: start.min(end), end: start.max(end), kind });
+                println!("Watchpoint on ${:04X}-${:04X}", start.min(end), start.max(end));
+            }
+            "del" | "delete" => {
+                let addr = self.parse_address(args.first().ok_or("usage: delete <addr>")?)?;
+                let removed = self.breakpoints.pc.remove(&addr) as usize;
+                let before = self.breakpoints.watch.len();
+                self.breakpoints.watch.retain(|w| !(w.start..=w.end).contains(&addr));
+                println!("Removed {} breakpoint(s) and watchpoint(s)", removed + before - self.breakpoints.watch.len());
+            }
+            "l" | "list" => {
+                for &addr in &self.breakpoints.pc {
+                    println!("break {}", self.describe(addr));
+                }
+                for w in &self.breakpoints.watch {
+                    let kind = match w.kind {
+                        WatchKind::Read => "r",
+                        WatchKind::Write => "w",
+                        WatchKind::ReadWrite => "rw",
+                    };
+                    println!("watch ${:04X}-${:04X} {}", w.start, w.end, kind);
+                }
+            }
+            "d" | "disasm" => {
+                let count = args.get(1).map(|n| n.parse::<usize>().map_err(|e| e.to_string())).transpose()?.unwrap_or(10);
+                match args.first() {
+                    Some(addr) => self.print_disassembly(self.parse_address(addr)?, count),
+                    None => {
+                        let pc = self.console.cpu.pc;
+                        self.print_disassembly(self.find_start_before(pc, 3), count);
+                    }
+                }
+            }
+            "i" | "info" => {
+                let addr = args.first().map(|addr| self.parse_address(addr)).transpose()?.unwrap_or(self.console.cpu.pc);
+                self.print_instruction_info(addr)?;
+            }
+            "x" | "mem" => {
+                let addr = self.parse_address(args.first().ok_or("usage: mem <addr> [len]")?)?;
+                let len = args.get(1).map(|n| parse_number(n)).transpose()?.unwrap_or(0x40);
+                self.dump_memory(addr, len);
+            }
+            "poke" => {
+                let (Some(addr), Some(value)) = (args.first(), args.get(1)) else {
+                    return Err("usage: poke <addr> <value>".to_string());
+                };
+                let (addr, value) = (self.parse_address(addr)?, parse_number(value)?);
+                if value > 0xFF {
+                    return Err(format!("${:X} doesn't fit in a byte", value));
+                }
+                self.console.write(addr, value as u8);
+            }
+            "a" | "asm" => {
+                let mut addr = self.parse_address(args.first().ok_or("usage: asm <addr> [instruction]")?)?;
+                if args.len() > 1 {
+                    self.assemble_at(addr, &args[1..].join(" "))?;
+                    return Ok(true);
+                }
+                let stdin = io::stdin();
+                loop {
+                    print!("{:04X}: ", addr);
+                    io::stdout().flush().ok();
+                    let mut line = String::new();
+                    if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 || line.trim().is_empty() {
+                        break;
+                    }
+                    match self.assemble_at(addr, &line) {
+                        Ok(next) => addr = next,
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+            }
+            "input" => {
+                let port = match args.first().copied() {
+                    Some("1") => 0,
+                    Some("2") => 1,
+                    _ => return Err("usage: input <1|2> [buttons]".to_string()),
+                };
+                if let Some(buttons) = args.get(1) {
+                    self.console.bus.controllers[port].set_buttons(controller::parse_buttons(buttons)?);
+                }
+                let held = self.console.bus.controllers[port].buttons();
+                println!("Controller {}: {}", port + 1, controller::button_names(held));
+            }
+            "rewind" => {
+                match args.first().copied() {
+                    None => {}
+                    Some("on") => self.console.enable_rewind(1, REWIND_BUDGET),
+                    Some("off") => self.console.disable_rewind(),
+                    Some("clear") => self.console.clear_rewind(),
+                    Some(frames) => {
+                        let frames = frames.parse::<u32>().map_err(|_| "usage: rewind <frames> | rewind [on|off|clear]")?;
+                        let rewound = self.console.rewind(frames);
+                        println!("Rewound {} frame(s)", rewound);
+                        self.print_current();
+                        return Ok(true);
+                    }
+                }
+                match self.console.rewind_memory_usage() {
+                    Some(bytes) => println!("Rewind history: {} KB of {} KB", bytes / 1024, REWIND_BUDGET / 1024),
+                    None => println!("Rewind is off"),
+                }
+            }
+            "disk" => {
+                match args.first() {
+                    None => {}
+                    Some(&"eject") => self.console.insert_disk(None)?,
+                    Some(side) => self.console.insert_disk(Some(side.parse::<usize>().map_err(|e| e.to_string())?))?,
+                }
+                match self.console.disk_side() {
+                    Some(side) => println!("Disk side {} of {} inserted", side, self.console.disk_sides()),
+                    None => println!("Drive empty ({} side(s) available)", self.console.disk_sides()),
+                }
+            }
+            _ => return Err(format!("unknown command '{}', type 'help' for a list", command)),
+        }
+        Ok(true)
+    }
+
+    fn run_until(&mut self, done: impl FnMut(&Console) -> bool) -> Stop {
+        self.breakpoints.run_until(&mut self.console, done)
+    }
+
+    fn report(&self, stop: Stop) {
+        match stop {
+            Stop::Done => {}
+            Stop::Breakpoint(pc) => println!("Breakpoint at {}", self.describe(pc)),
+            Stop::Watchpoint { pc, addr, access, .. } => {
+                println!("Watchpoint: {:?} of {} by instruction at {}", access, self.describe(addr), self.describe(pc));
+            }
+            Stop::Jam => println!("CPU jammed at ${:04X}", self.console.cpu.pc),
+        }
+        self.print_current();
+    }
+
+    /// Assembles `source` at `addr` and writes it through the bus. Returns the address after it.
+    fn assemble_at(&mut self, addr: u16, source: &str) -> Result<u16, String> {
+        let mut next = addr;
+        for (start, bytes) in assembler::assemble(source, addr, true)? {
+            for (i, &byte) in bytes.iter().enumerate() {
+                self.console.write(start.wrapping_add(i as u16), byte);
+            }
+            next = start.wrapping_add(bytes.len() as u16);
+        }
+        self.print_disassembly(addr, 1);
+        Ok(next)
+    }
+
+    fn print_registers(&self) {
+        println!("{}", self.console.cpu);
+        let ppu = &self.console.bus.ppu;
+        println!(
+            "PPU scanline {} dot {} frame {}, CPU cycle {}",
+            ppu.scanline(),
+            ppu.cycle(),
+            ppu.frame(),
+            self.console.cycles()
+        );
+    }
+
+    fn print_current(&self) {
+        self.print_registers();
+        self.print_disassembly(self.console.cpu.pc, 1);
+    }
+
+    /// Picks a start address up to `count` instructions before `target` whose
+    /// linear disassembly lands exactly on it, falling back to `target` itself.
+    fn find_start_before(&self, target: u16, count: usize) -> u16 {
+        for back in (1..=(count as u16 * 3)).rev() {
+            let start = target.wrapping_sub(back);
+            let (mut len, mut instructions) = (0, 0);
+            for decoded in decoder::disassemble(&self.console, start) {
+                if len >= back as usize {
+                    break;
+                }
+                len += decoded.bytes().len();
+                instructions += 1;
+            }
+            if len == back as usize && instructions <= count {
+                return start;
+            }
+        }
+        target
+    }
+
+    fn print_disassembly(&self, start: u16, count: usize) {
+        let names = |addr| self.name(addr);
+        for decoded in decoder::disassemble(&self.console, start).take(count) {
+            let addr = decoded.address();
+            let location = self.location(addr);
+            if self.symbols.get(location).is_some_and(|(_, index)| index == 0) {
+                println!("{}:", self.name(addr).unwrap_or_default());
+            }
+            let marker = if addr == self.console.cpu.pc { ">" } else { " " };
+            let raw: Vec<String> = decoded.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+            let comment = self.symbols.comment(location).map(|c| format!("  ; {}", c)).unwrap_or_default();
+            let text = match &decoded {
+                Decoded::Instruction(instruction) => format!("{:#}", instruction.with_names(&names)),
+                other => other.to_string(),
+            };
+            println!("{}{:04X}  {:<8}  {}{}", marker, addr, raw.join(" "), text, comment);
+        }
+    }
+
+    /// Describes the instruction at `addr`: its timing, the flags it depends on and
+    /// changes, and what it does to memory and control flow.
+    fn print_instruction_info(&self, addr: u16) -> Result<(), String> {
+        let Some(Decoded::Instruction(instruction)) = decoder::decode(&self.console, addr) else {
+            return Err(format!("no complete instruction at {}", self.describe(addr)));
+        };
+        let info = instruction.info;
+        self.print_disassembly(addr, 1);
+        println!("  {:?} addressing, {} byte(s), {} cycle(s) plus any page crossing or taken branch", info.mode, info.bytes, info.cycles);
+        println!("  Flags read: {}  written: {}", flag_names(info.flags_read), flag_names(info.flags_written));
+        let access = info.access.map_or("none".to_string(), |access| format!("{:?}", access));
+        println!("  Memory access: {}  flow: {:?}  opcode: {:?}", access, info.flow, info.stability);
+        Ok(())
+    }
+
+    /// Logs the instruction about to run and the registers before it, one line per instruction.
+    fn print_trace_line(&self) {
+        let cpu = &self.console.cpu;
+        let names = |addr| self.name(addr);
+        let Some(Decoded::Instruction(instruction)) = decoder::decode(&self.console, cpu.pc) else { return };
+        let raw: Vec<String> = instruction.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+        let text = format!("{:#}", instruction.with_names(&names));
+        println!(
+            "{:04X}  {:<8}  {:<28}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{}",
+            cpu.pc,
+            raw.join(" "),
+            text,
+            cpu.ac,
+            cpu.idx,
+            cpu.idy,
+            cpu.status,
+            cpu.sp,
+            self.console.cycles()
+        );
+    }
+
+    /// Where an address points right now: the PRG byte mapped there, or the address itself.
+    fn location(&self, addr: u16) -> Location {
+        self.console.bus.mapper.prg_offset(addr).map_or(Location::Cpu(addr), Location::Prg)
+    }
+
+    fn name(&self, addr: u16) -> Option<String> {
+        self.symbols.name(addr, self.console.bus.mapper.prg_offset(addr))
+    }
+
+    /// `$C000`, or `$C000 (Reset)` when the address has a name.
+    fn describe(&self, addr: u16) -> String {
+        match self.name(addr) {
+            Some(name) => format!("${:04X} ({})", addr, name),
+            None => format!("${:04X}", addr),
+        }
+    }
+
+    /// Parses a symbol name or hex address. ROM symbols resolve to wherever
+    /// their bank is mapped at the moment.
+    fn parse_address(&self, text: &str) -> Result<u16, String> {
+        match self.symbols.find(text) {
+            Some(Location::Cpu(addr)) => Ok(addr),
+            Some(Location::Prg(offset)) => {
+                // PRG banks are at least 8KB, so only one address per 8KB window can hold the offset
+                (0x6000..=0xFFFFu32)
+                    .step_by(0x2000)
+                    .map(|window| (window as u16) | (offset as u16 & 0x1FFF))
+                    .find(|&addr| self.console.bus.mapper.prg_offset(addr) == Some(offset))
+                    .ok_or_else(|| format!("'{}' is in a PRG bank that isn't mapped right now", text))
+            }
+            None if self.symbols.is_empty() => parse_number(text),
+            None => parse_number(text).map_err(|_| format!("'{}' is neither a symbol nor a hex number", text)),
+        }
+    }
+
+    fn dump_memory(&self, start: u16, len: u16) {
+        for row in (0..len).step_by(16) {
+            let addr = start.wrapping_add(row);
+            let bytes = self.read_bytes(addr, (len - row).min(16) as usize);
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            println!("{:04X}: {}", addr, hex.join(" "));
+        }
+    }
+
+    fn read_bytes(&self, start: u16, len: usize) -> Vec<u8> {
+        (0..len).map(|i| self.console.peek(start.wrapping_add(i as u16))).collect()
+    }
+}
+
+/// Status flags in a mask, in the order the P register shows them, or `-` for none.
+fn flag_names(mask: u8) -> String {
+    let names: String = [(flag::N, 'N'), (flag::V, 'V'), (flag::D, 'D'), (flag::I, 'I'), (flag::Z, 'Z'), (flag::C, 'C')]
+        .iter()
+        .filter(|&&(bit, _)| mask & bit != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    if names.is_empty() { "-".to_string() } else { names }
+}
+
+/// Parses a hex number, with an optional `$` or `0x` prefix.
+fn parse_number(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("'{}' is not a hex number", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::Cartridge;
+    use crate::console::Region;
+
+    const PROGRAM: &str = "
+        reset:
+            ldx #$FF
+            txs
+            lda #$80
+            sta $2000           ; NMI at VBlank
+            lda #$02
+            jsr copy
+        loop:
+            jmp loop
+        copy:
+            sta $4014           ; OAM DMA from $0200-$02FF
+            rts
+        nmi:
+            rti
+            .org $FFFA
+            .word nmi, reset, nmi
+    ";
+
+    fn console() -> Console {
+        let mut prg = vec![0xFF; 0x4000];
+        for (start, bytes) in assembler::assemble(PROGRAM, 0xC000, false).unwrap() {
+            let offset = start as usize - 0xC000;
+            prg[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        let mut console = Console::new(&Cartridge::for_test(0, 0, prg, Vec::new()), Region::Ntsc).unwrap();
+        console.reset();
+        console
+    }
+
+    /// Runs the program until a single watchpoint fires. Returns the PC of the instruction
+    /// responsible, and the address and kind of the access.
+    fn watch(start: u16, end: u16, kind: WatchKind) -> (u16, u16, Access) {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.watch.push(Watchpoint { start, end, kind });
+        let mut console = console();
+        let mut steps = 0;
+        let stop = breakpoints.run_until(&mut console, |_| {
+            steps += 1;
+            steps == 100_000
+        });
+        match stop {
+            Stop::Watchpoint { pc, addr, access, .. } => (pc, addr, access),
+            _ => panic!("watchpoint on ${:04X}-${:04X} never fired", start, end),
+        }
+    }
+
+    #[test]
+    fn watchpoints_see_stack_pushes_and_pulls() {
+        assert_eq!(watch(0x01FE, 0x01FE, WatchKind::Write), (0xC00A, 0x01FE, Access::Write));
+        assert_eq!(watch(0x01FE, 0x01FF, WatchKind::Read), (0xC013, 0x01FE, Access::Read));
+    }
+
+    #[test]
+    fn watchpoints_see_oam_dma() {
+        assert_eq!(watch(0x0280, 0x0280, WatchKind::Read), (0xC010, 0x0280, Access::Read));
+        assert_eq!(watch(0x2004, 0x2004, WatchKind::Write), (0xC010, 0x2004, Access::Write));
+    }
+
+    #[test]
+    fn watchpoints_see_interrupt_vector_fetches() {
+        assert_eq!(watch(0xFFFA, 0xFFFB, WatchKind::Read), (0xC00D, 0xFFFA, Access::Read));
+    }
+
+    #[test]
+    fn watchpoints_see_both_halves_of_read_modify_write() {
+        let mut console = console();
+        console.record_accesses(true);
+        console.cpu.pc = 0x0300;
+        console.write(0x0300, 0xEE); // INC $0010
+        console.write(0x0301, 0x10);
+        console.write(0x0302, 0x00);
+        console.step();
+        assert_eq!(console.accesses(), [(0x0010, Access::Read), (0x0010, Access::Write)]);
+    }
+}
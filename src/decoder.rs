@@ -1,3 +1,5 @@
+use std::fmt;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mnemonic {
     // Load/Store
@@ -44,25 +46,157 @@ pub enum AddressMode {
     IndirectY,
 }
 
+/// How an instruction touches the memory its operand points at.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+/// What an instruction does to the program counter.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Sequential,
+    Branch,    // Conditional, relative
+    Jump,      // JMP, absolute or indirect
+    Call,      // JSR
+    Return,    // RTS, RTI
+    Interrupt, // BRK
+    Halt,      // JAM
+}
+
+/// Whether an opcode is documented, and if not, whether it behaves the same on every console.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Stability {
+    Official,
+    Stable,   // Undocumented but reliable, and used by some games
+    Unstable, // Result depends on the chip, temperature or analog effects
+}
+
+/// Status register bits, for the flag masks in `InstructionInfo`.
+pub mod flag {
+    pub const C: u8 = 0x01;
+    pub const Z: u8 = 0x02;
+    pub const I: u8 = 0x04;
+    pub const D: u8 = 0x08;
+    pub const V: u8 = 0x40;
+    pub const N: u8 = 0x80;
+    pub const ALL: u8 = C | Z | I | D | V | N;
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct InstructionInfo {
     pub mnemonic: Mnemonic,
     pub mode: AddressMode,
     pub bytes: u8,
     pub cycles: u8,
+    pub stability: Stability,
+    pub flags_read: u8,         // Status bits the result or control flow depends on
+    pub flags_written: u8,      // Status bits the instruction may change
+    pub access: Option<Access>, // None for instructions without a memory operand
+    pub flow: Flow,
+}
+
+impl InstructionInfo {
+    /// Fills in the metadata that follows from the mnemonic and addressing mode.
+    const fn new(mnemonic: Mnemonic, mode: AddressMode, bytes: u8, cycles: u8, official: bool) -> Self {
+        let (flags_read, flags_written) = flags(mnemonic);
+        InstructionInfo {
+            mnemonic,
+            mode,
+            bytes,
+            cycles,
+            stability: stability(mnemonic, mode, official),
+            flags_read,
+            flags_written,
+            access: access(mnemonic, mode),
+            flow: flow(mnemonic),
+        }
+    }
+
+    pub fn is_official(&self) -> bool {
+        self.stability == Stability::Official
+    }
+}
+
+const fn flags(mnemonic: Mnemonic) -> (u8, u8) {
+    use Mnemonic::*;
+    use flag::*;
+    match mnemonic {
+        LDA | LDX | LDY | TAX | TAY | TXA | TYA | TSX | PLA | AND | EOR | ORA => (0, N | Z),
+        INC | INX | INY | DEC | DEX | DEY | LAX | LAS | XAA => (0, N | Z),
+        BIT => (0, N | V | Z),
+        // The 2A03 has no decimal mode, so D doesn't affect arithmetic
+        ADC | SBC | ISC | RRA | ARR => (C, N | V | Z | C),
+        CMP | CPX | CPY | ASL | LSR | SLO | SRE | ALR | ANC | AXS | DCP => (0, N | Z | C),
+        ROL | ROR | RLA => (C, N | Z | C),
+        BCC | BCS => (C, 0),
+        BEQ | BNE => (Z, 0),
+        BMI | BPL => (N, 0),
+        BVC | BVS => (V, 0),
+        CLC | SEC => (0, C),
+        CLI | SEI => (0, I),
+        CLD | SED => (0, D),
+        CLV => (0, V),
+        PHP => (ALL, 0),
+        PLP | RTI => (0, ALL),
+        BRK => (ALL, I),
+        _ => (0, 0),
+    }
+}
+
+const fn access(mnemonic: Mnemonic, mode: AddressMode) -> Option<Access> {
+    use Mnemonic::*;
+    match mode {
+        AddressMode::Implied | AddressMode::Accumulator | AddressMode::Immediate | AddressMode::Relative => None,
+        AddressMode::Indirect => Some(Access::Read), // JMP (ind) reads its pointer
+        _ => match mnemonic {
+            JMP | JSR => None,
+            STA | STX | STY | SAX | AHX | SHX | SHY | TAS => Some(Access::Write),
+            ASL | LSR | ROL | ROR | INC | DEC | SLO | RLA | SRE | RRA | DCP | ISC => Some(Access::ReadModifyWrite),
+            _ => Some(Access::Read),
+        },
+    }
+}
+
+const fn flow(mnemonic: Mnemonic) -> Flow {
+    use Mnemonic::*;
+    match mnemonic {
+        BCC | BCS | BEQ | BMI | BNE | BPL | BVC | BVS => Flow::Branch,
+        JMP => Flow::Jump,
+        JSR => Flow::Call,
+        RTS | RTI => Flow::Return,
+        BRK => Flow::Interrupt,
+        JAM => Flow::Halt,
+        _ => Flow::Sequential,
+    }
+}
+
+const fn stability(mnemonic: Mnemonic, mode: AddressMode, official: bool) -> Stability {
+    use Mnemonic::*;
+    if official {
+        return Stability::Official;
+    }
+    match (mnemonic, mode) {
+        // Magic-constant ANDs and the high byte stores
+        (XAA | AHX | SHX | SHY | TAS, _) | (LAX, AddressMode::Immediate) => Stability::Unstable,
+        _ => Stability::Stable,
+    }
 }
 
 macro_rules! opcodes {
-    ($($opcode:expr => ($mnemonic:ident, $mode:ident, $bytes:expr, $cycles:expr)),* $(,)*) => {
+    (
+        Official { $($opcode:expr => ($mnemonic:ident, $mode:ident, $bytes:expr, $cycles:expr)),* $(,)* }
+        Unofficial { $($u_opcode:expr => ($u_mnemonic:ident, $u_mode:ident, $u_bytes:expr, $u_cycles:expr)),* $(,)* }
+    ) => {
         {
             let mut map = [None; 256];
             $(
-                map[$opcode] = Some(InstructionInfo {
-                    mnemonic: Mnemonic::$mnemonic,
-                    mode: AddressMode::$mode,
-                    bytes: $bytes,
-                    cycles: $cycles,
-                });
+                map[$opcode] = Some(InstructionInfo::new(Mnemonic::$mnemonic, AddressMode::$mode, $bytes, $cycles, true));
+            )*
+            $(
+                map[$u_opcode] = Some(InstructionInfo::new(Mnemonic::$u_mnemonic, AddressMode::$u_mode, $u_bytes, $u_cycles, false));
             )*
             map
         }
@@ -74,300 +208,448 @@ macro_rules! opcodes {
 // See http://www.obelisk.me.uk/6502/reference.html for a complete reference.
 // Cycle counts are base values and may take longer (e.g., page boundary crossings).
 pub static OPCODE_MAP: [Option<InstructionInfo>; 256] = opcodes! {
-    // Official Opcodes
-    0x00 => (BRK, Implied, 1, 7),
-    0x01 => (ORA, IndirectX, 2, 6),
-    0x05 => (ORA, ZeroPage, 2, 3),
-    0x06 => (ASL, ZeroPage, 2, 5),
-    0x08 => (PHP, Implied, 1, 3),
-    0x09 => (ORA, Immediate, 2, 2),
-    0x0A => (ASL, Accumulator, 1, 2),
-    0x0D => (ORA, Absolute, 3, 4),
-    0x0E => (ASL, Absolute, 3, 6),
-    0x10 => (BPL, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0x11 => (ORA, IndirectY, 2, 5), // +1 if page crossed
-    0x15 => (ORA, ZeroPageX, 2, 4),
-    0x16 => (ASL, ZeroPageX, 2, 6),
-    0x18 => (CLC, Implied, 1, 2),
-    0x19 => (ORA, AbsoluteY, 3, 4), // +1 if page crossed
-    0x1D => (ORA, AbsoluteX, 3, 4), // +1 if page crossed
-    0x1E => (ASL, AbsoluteX, 3, 7),
-    0x20 => (JSR, Absolute, 3, 6),
-    0x21 => (AND, IndirectX, 2, 6),
-    0x24 => (BIT, ZeroPage, 2, 3),
-    0x25 => (AND, ZeroPage, 2, 3),
-    0x26 => (ROL, ZeroPage, 2, 5),
-    0x28 => (PLP, Implied, 1, 4),
-    0x29 => (AND, Immediate, 2, 2),
-    0x2A => (ROL, Accumulator, 1, 2),
-    0x2C => (BIT, Absolute, 3, 4),
-    0x2D => (AND, Absolute, 3, 4),
-    0x2E => (ROL, Absolute, 3, 6),
-    0x30 => (BMI, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0x31 => (AND, IndirectY, 2, 5), // +1 if page crossed
-    0x35 => (AND, ZeroPageX, 2, 4),
-    0x36 => (ROL, ZeroPageX, 2, 6),
-    0x38 => (SEC, Implied, 1, 2),
-    0x39 => (AND, AbsoluteY, 3, 4), // +1 if page crossed
-    0x3D => (AND, AbsoluteX, 3, 4), // +1 if page crossed
-    0x3E => (ROL, AbsoluteX, 3, 7),
-    0x40 => (RTI, Implied, 1, 6),
-    0x41 => (EOR, IndirectX, 2, 6),
-    0x45 => (EOR, ZeroPage, 2, 3),
-    0x46 => (LSR, ZeroPage, 2, 5),
-    0x48 => (PHA, Implied, 1, 3),
-    0x49 => (EOR, Immediate, 2, 2),
-    0x4A => (LSR, Accumulator, 1, 2),
-    0x4C => (JMP, Absolute, 3, 3),
-    0x4D => (EOR, Absolute, 3, 4),
-    0x4E => (LSR, Absolute, 3, 6),
-    0x50 => (BVC, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0x51 => (EOR, IndirectY, 2, 5), // +1 if page crossed
-    0x55 => (EOR, ZeroPageX, 2, 4),
-    0x56 => (LSR, ZeroPageX, 2, 6),
-    0x58 => (CLI, Implied, 1, 2),
-    0x59 => (EOR, AbsoluteY, 3, 4), // +1 if page crossed
-    0x5D => (EOR, AbsoluteX, 3, 4), // +1 if page crossed
-    0x5E => (LSR, AbsoluteX, 3, 7),
-    0x60 => (RTS, Implied, 1, 6),
-    0x61 => (ADC, IndirectX, 2, 6),
-    0x65 => (ADC, ZeroPage, 2, 3),
-    0x66 => (ROR, ZeroPage, 2, 5),
-    0x68 => (PLA, Implied, 1, 4),
-    0x69 => (ADC, Immediate, 2, 2),
-    0x6A => (ROR, Accumulator, 1, 2),
-    0x6C => (JMP, Indirect, 3, 5),
-    0x6D => (ADC, Absolute, 3, 4),
-    0x6E => (ROR, Absolute, 3, 6),
-    0x70 => (BVS, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0x71 => (ADC, IndirectY, 2, 5), // +1 if page crossed
-    0x75 => (ADC, ZeroPageX, 2, 4),
-    0x76 => (ROR, ZeroPageX, 2, 6),
-    0x78 => (SEI, Implied, 1, 2),
-    0x79 => (ADC, AbsoluteY, 3, 4), // +1 if page crossed
-    0x7D => (ADC, AbsoluteX, 3, 4), // +1 if page crossed
-    0x7E => (ROR, AbsoluteX, 3, 7),
-    0x81 => (STA, IndirectX, 2, 6),
-    0x84 => (STY, ZeroPage, 2, 3),
-    0x85 => (STA, ZeroPage, 2, 3),
-    0x86 => (STX, ZeroPage, 2, 3),
-    0x88 => (DEY, Implied, 1, 2),
-    0x8A => (TXA, Implied, 1, 2),
-    0x8C => (STY, Absolute, 3, 4),
-    0x8D => (STA, Absolute, 3, 4),
-    0x8E => (STX, Absolute, 3, 4),
-    0x90 => (BCC, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0x91 => (STA, IndirectY, 2, 6),
-    0x94 => (STY, ZeroPageX, 2, 4),
-    0x95 => (STA, ZeroPageX, 2, 4),
-    0x96 => (STX, ZeroPageY, 2, 4),
-    0x98 => (TYA, Implied, 1, 2),
-    0x99 => (STA, AbsoluteY, 3, 5),
-    0x9A => (TXS, Implied, 1, 2),
-    0x9D => (STA, AbsoluteX, 3, 5),
-    0xA0 => (LDY, Immediate, 2, 2),
-    0xA1 => (LDA, IndirectX, 2, 6),
-    0xA2 => (LDX, Immediate, 2, 2),
-    0xA4 => (LDY, ZeroPage, 2, 3),
-    0xA5 => (LDA, ZeroPage, 2, 3),
-    0xA6 => (LDX, ZeroPage, 2, 3),
-    0xA8 => (TAY, Implied, 1, 2),
-    0xA9 => (LDA, Immediate, 2, 2),
-    0xAA => (TAX, Implied, 1, 2),
-    0xAC => (LDY, Absolute, 3, 4),
-    0xAD => (LDA, Absolute, 3, 4),
-    0xAE => (LDX, Absolute, 3, 4),
-    0xB0 => (BCS, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0xB1 => (LDA, IndirectY, 2, 5), // +1 if page crossed
-    0xB4 => (LDY, ZeroPageX, 2, 4),
-    0xB5 => (LDA, ZeroPageX, 2, 4),
-    0xB6 => (LDX, ZeroPageY, 2, 4),
-    0xB8 => (CLV, Implied, 1, 2),
-    0xB9 => (LDA, AbsoluteY, 3, 4), // +1 if page crossed
-    0xBA => (TSX, Implied, 1, 2),
-    0xBC => (LDY, AbsoluteX, 3, 4), // +1 if page crossed
-    0xBD => (LDA, AbsoluteX, 3, 4), // +1 if page crossed
-    0xBE => (LDX, AbsoluteY, 3, 4), // +1 if page crossed
-    0xC0 => (CPY, Immediate, 2, 2),
-    0xC1 => (CMP, IndirectX, 2, 6),
-    0xC4 => (CPY, ZeroPage, 2, 3),
-    0xC5 => (CMP, ZeroPage, 2, 3),
-    0xC6 => (DEC, ZeroPage, 2, 5),
-    0xC8 => (INY, Implied, 1, 2),
-    0xC9 => (CMP, Immediate, 2, 2),
-    0xCA => (DEX, Implied, 1, 2),
-    0xCC => (CPY, Absolute, 3, 4),
-    0xCD => (CMP, Absolute, 3, 4),
-    0xCE => (DEC, Absolute, 3, 6),
-    0xD0 => (BNE, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0xD1 => (CMP, IndirectY, 2, 5), // +1 if page crossed
-    0xD5 => (CMP, ZeroPageX, 2, 4),
-    0xD6 => (DEC, ZeroPageX, 2, 6),
-    0xD8 => (CLD, Implied, 1, 2),
-    0xD9 => (CMP, AbsoluteY, 3, 4), // +1 if page crossed
-    0xDD => (CMP, AbsoluteX, 3, 4), // +1 if page crossed
-    0xDE => (DEC, AbsoluteX, 3, 7),
-    0xE0 => (CPX, Immediate, 2, 2),
-    0xE1 => (SBC, IndirectX, 2, 6),
-    0xE4 => (CPX, ZeroPage, 2, 3),
-    0xE5 => (SBC, ZeroPage, 2, 3),
-    0xE6 => (INC, ZeroPage, 2, 5),
-    0xE8 => (INX, Implied, 1, 2),
-    0xE9 => (SBC, Immediate, 2, 2),
-    0xEA => (NOP, Implied, 1, 2),
-    0xEC => (CPX, Absolute, 3, 4),
-    0xED => (SBC, Absolute, 3, 4),
-    0xEE => (INC, Absolute, 3, 6),
-    0xF0 => (BEQ, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
-    0xF1 => (SBC, IndirectY, 2, 5), // +1 if page crossed
-    0xF5 => (SBC, ZeroPageX, 2, 4),
-    0xF6 => (INC, ZeroPageX, 2, 6),
-    0xF8 => (SED, Implied, 1, 2),
-    0xF9 => (SBC, AbsoluteY, 3, 4), // +1 if page crossed
-    0xFD => (SBC, AbsoluteX, 3, 4), // +1 if page crossed
-    0xFE => (INC, AbsoluteX, 3, 7),
+    Official {
+        0x00 => (BRK, Implied, 1, 7),
+        0x01 => (ORA, IndirectX, 2, 6),
+        0x05 => (ORA, ZeroPage, 2, 3),
+        0x06 => (ASL, ZeroPage, 2, 5),
+        0x08 => (PHP, Implied, 1, 3),
+        0x09 => (ORA, Immediate, 2, 2),
+        0x0A => (ASL, Accumulator, 1, 2),
+        0x0D => (ORA, Absolute, 3, 4),
+        0x0E => (ASL, Absolute, 3, 6),
+        0x10 => (BPL, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0x11 => (ORA, IndirectY, 2, 5), // +1 if page crossed
+        0x15 => (ORA, ZeroPageX, 2, 4),
+        0x16 => (ASL, ZeroPageX, 2, 6),
+        0x18 => (CLC, Implied, 1, 2),
+        0x19 => (ORA, AbsoluteY, 3, 4), // +1 if page crossed
+        0x1D => (ORA, AbsoluteX, 3, 4), // +1 if page crossed
+        0x1E => (ASL, AbsoluteX, 3, 7),
+        0x20 => (JSR, Absolute, 3, 6),
+        0x21 => (AND, IndirectX, 2, 6),
+        0x24 => (BIT, ZeroPage, 2, 3),
+        0x25 => (AND, ZeroPage, 2, 3),
+        0x26 => (ROL, ZeroPage, 2, 5),
+        0x28 => (PLP, Implied, 1, 4),
+        0x29 => (AND, Immediate, 2, 2),
+        0x2A => (ROL, Accumulator, 1, 2),
+        0x2C => (BIT, Absolute, 3, 4),
+        0x2D => (AND, Absolute, 3, 4),
+        0x2E => (ROL, Absolute, 3, 6),
+        0x30 => (BMI, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0x31 => (AND, IndirectY, 2, 5), // +1 if page crossed
+        0x35 => (AND, ZeroPageX, 2, 4),
+        0x36 => (ROL, ZeroPageX, 2, 6),
+        0x38 => (SEC, Implied, 1, 2),
+        0x39 => (AND, AbsoluteY, 3, 4), // +1 if page crossed
+        0x3D => (AND, AbsoluteX, 3, 4), // +1 if page crossed
+        0x3E => (ROL, AbsoluteX, 3, 7),
+        0x40 => (RTI, Implied, 1, 6),
+        0x41 => (EOR, IndirectX, 2, 6),
+        0x45 => (EOR, ZeroPage, 2, 3),
+        0x46 => (LSR, ZeroPage, 2, 5),
+        0x48 => (PHA, Implied, 1, 3),
+        0x49 => (EOR, Immediate, 2, 2),
+        0x4A => (LSR, Accumulator, 1, 2),
+        0x4C => (JMP, Absolute, 3, 3),
+        0x4D => (EOR, Absolute, 3, 4),
+        0x4E => (LSR, Absolute, 3, 6),
+        0x50 => (BVC, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0x51 => (EOR, IndirectY, 2, 5), // +1 if page crossed
+        0x55 => (EOR, ZeroPageX, 2, 4),
+        0x56 => (LSR, ZeroPageX, 2, 6),
+        0x58 => (CLI, Implied, 1, 2),
+        0x59 => (EOR, AbsoluteY, 3, 4), // +1 if page crossed
+        0x5D => (EOR, AbsoluteX, 3, 4), // +1 if page crossed
+        0x5E => (LSR, AbsoluteX, 3, 7),
+        0x60 => (RTS, Implied, 1, 6),
+        0x61 => (ADC, IndirectX, 2, 6),
+        0x65 => (ADC, ZeroPage, 2, 3),
+        0x66 => (ROR, ZeroPage, 2, 5),
+        0x68 => (PLA, Implied, 1, 4),
+        0x69 => (ADC, Immediate, 2, 2),
+        0x6A => (ROR, Accumulator, 1, 2),
+        0x6C => (JMP, Indirect, 3, 5),
+        0x6D => (ADC, Absolute, 3, 4),
+        0x6E => (ROR, Absolute, 3, 6),
+        0x70 => (BVS, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0x71 => (ADC, IndirectY, 2, 5), // +1 if page crossed
+        0x75 => (ADC, ZeroPageX, 2, 4),
+        0x76 => (ROR, ZeroPageX, 2, 6),
+        0x78 => (SEI, Implied, 1, 2),
+        0x79 => (ADC, AbsoluteY, 3, 4), // +1 if page crossed
+        0x7D => (ADC, AbsoluteX, 3, 4), // +1 if page crossed
+        0x7E => (ROR, AbsoluteX, 3, 7),
+        0x81 => (STA, IndirectX, 2, 6),
+        0x84 => (STY, ZeroPage, 2, 3),
+        0x85 => (STA, ZeroPage, 2, 3),
+        0x86 => (STX, ZeroPage, 2, 3),
+        0x88 => (DEY, Implied, 1, 2),
+        0x8A => (TXA, Implied, 1, 2),
+        0x8C => (STY, Absolute, 3, 4),
+        0x8D => (STA, Absolute, 3, 4),
+        0x8E => (STX, Absolute, 3, 4),
+        0x90 => (BCC, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0x91 => (STA, IndirectY, 2, 6),
+        0x94 => (STY, ZeroPageX, 2, 4),
+        0x95 => (STA, ZeroPageX, 2, 4),
+        0x96 => (STX, ZeroPageY, 2, 4),
+        0x98 => (TYA, Implied, 1, 2),
+        0x99 => (STA, AbsoluteY, 3, 5),
+        0x9A => (TXS, Implied, 1, 2),
+        0x9D => (STA, AbsoluteX, 3, 5),
+        0xA0 => (LDY, Immediate, 2, 2),
+        0xA1 => (LDA, IndirectX, 2, 6),
+        0xA2 => (LDX, Immediate, 2, 2),
+        0xA4 => (LDY, ZeroPage, 2, 3),
+        0xA5 => (LDA, ZeroPage, 2, 3),
+        0xA6 => (LDX, ZeroPage, 2, 3),
+        0xA8 => (TAY, Implied, 1, 2),
+        0xA9 => (LDA, Immediate, 2, 2),
+        0xAA => (TAX, Implied, 1, 2),
+        0xAC => (LDY, Absolute, 3, 4),
+        0xAD => (LDA, Absolute, 3, 4),
+        0xAE => (LDX, Absolute, 3, 4),
+        0xB0 => (BCS, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0xB1 => (LDA, IndirectY, 2, 5), // +1 if page crossed
+        0xB4 => (LDY, ZeroPageX, 2, 4),
+        0xB5 => (LDA, ZeroPageX, 2, 4),
+        0xB6 => (LDX, ZeroPageY, 2, 4),
+        0xB8 => (CLV, Implied, 1, 2),
+        0xB9 => (LDA, AbsoluteY, 3, 4), // +1 if page crossed
+        0xBA => (TSX, Implied, 1, 2),
+        0xBC => (LDY, AbsoluteX, 3, 4), // +1 if page crossed
+        0xBD => (LDA, AbsoluteX, 3, 4), // +1 if page crossed
+        0xBE => (LDX, AbsoluteY, 3, 4), // +1 if page crossed
+        0xC0 => (CPY, Immediate, 2, 2),
+        0xC1 => (CMP, IndirectX, 2, 6),
+        0xC4 => (CPY, ZeroPage, 2, 3),
+        0xC5 => (CMP, ZeroPage, 2, 3),
+        0xC6 => (DEC, ZeroPage, 2, 5),
+        0xC8 => (INY, Implied, 1, 2),
+        0xC9 => (CMP, Immediate, 2, 2),
+        0xCA => (DEX, Implied, 1, 2),
+        0xCC => (CPY, Absolute, 3, 4),
+        0xCD => (CMP, Absolute, 3, 4),
+        0xCE => (DEC, Absolute, 3, 6),
+        0xD0 => (BNE, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0xD1 => (CMP, IndirectY, 2, 5), // +1 if page crossed
+        0xD5 => (CMP, ZeroPageX, 2, 4),
+        0xD6 => (DEC, ZeroPageX, 2, 6),
+        0xD8 => (CLD, Implied, 1, 2),
+        0xD9 => (CMP, AbsoluteY, 3, 4), // +1 if page crossed
+        0xDD => (CMP, AbsoluteX, 3, 4), // +1 if page crossed
+        0xDE => (DEC, AbsoluteX, 3, 7),
+        0xE0 => (CPX, Immediate, 2, 2),
+        0xE1 => (SBC, IndirectX, 2, 6),
+        0xE4 => (CPX, ZeroPage, 2, 3),
+        0xE5 => (SBC, ZeroPage, 2, 3),
+        0xE6 => (INC, ZeroPage, 2, 5),
+        0xE8 => (INX, Implied, 1, 2),
+        0xE9 => (SBC, Immediate, 2, 2),
+        0xEA => (NOP, Implied, 1, 2),
+        0xEC => (CPX, Absolute, 3, 4),
+        0xED => (SBC, Absolute, 3, 4),
+        0xEE => (INC, Absolute, 3, 6),
+        0xF0 => (BEQ, Relative, 2, 2), // +1 if branch succeeds, +2 if to a new page
+        0xF1 => (SBC, IndirectY, 2, 5), // +1 if page crossed
+        0xF5 => (SBC, ZeroPageX, 2, 4),
+        0xF6 => (INC, ZeroPageX, 2, 6),
+        0xF8 => (SED, Implied, 1, 2),
+        0xF9 => (SBC, AbsoluteY, 3, 4), // +1 if page crossed
+        0xFD => (SBC, AbsoluteX, 3, 4), // +1 if page crossed
+        0xFE => (INC, AbsoluteX, 3, 7),
+    }
 
-    // Unofficial Opcodes
-    0x02 => (JAM, Implied, 1, 0), // Halts CPU
-    0x03 => (SLO, IndirectX, 2, 8),
-    0x04 => (NOP, ZeroPage, 2, 3),
-    0x07 => (SLO, ZeroPage, 2, 5),
-    0x0B => (ANC, Immediate, 2, 2),
-    0x0C => (NOP, Absolute, 3, 4),
-    0x0F => (SLO, Absolute, 3, 6),
-    0x12 => (JAM, Implied, 1, 0),
-    0x13 => (SLO, IndirectY, 2, 8),
-    0x14 => (NOP, ZeroPageX, 2, 4),
-    0x17 => (SLO, ZeroPageX, 2, 6),
-    0x1A => (NOP, Implied, 1, 2),
-    0x1B => (SLO, AbsoluteY, 3, 7),
-    0x1C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0x1F => (SLO, AbsoluteX, 3, 7),
-    0x22 => (JAM, Implied, 1, 0),
-    0x23 => (RLA, IndirectX, 2, 8),
-    0x27 => (RLA, ZeroPage, 2, 5),
-    0x2B => (ANC, Immediate, 2, 2),
-    0x2F => (RLA, Absolute, 3, 6),
-    0x32 => (JAM, Implied, 1, 0),
-    0x33 => (RLA, IndirectY, 2, 8),
-    0x34 => (NOP, ZeroPageX, 2, 4),
-    0x37 => (RLA, ZeroPageX, 2, 6),
-    0x3A => (NOP, Implied, 1, 2),
-    0x3B => (RLA, AbsoluteY, 3, 7),
-    0x3C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0x3F => (RLA, AbsoluteX, 3, 7),
-    0x42 => (JAM, Implied, 1, 0),
-    0x43 => (SRE, IndirectX, 2, 8),
-    0x44 => (NOP, ZeroPage, 2, 3),
-    0x47 => (SRE, ZeroPage, 2, 5),
-    0x4B => (ALR, Immediate, 2, 2),
-    0x4F => (SRE, Absolute, 3, 6),
-    0x52 => (JAM, Implied, 1, 0),
-    0x53 => (SRE, IndirectY, 2, 8),
-    0x54 => (NOP, ZeroPageX, 2, 4),
-    0x57 => (SRE, ZeroPageX, 2, 6),
-    0x5A => (NOP, Implied, 1, 2),
-    0x5B => (SRE, AbsoluteY, 3, 7),
-    0x5C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0x5F => (SRE, AbsoluteX, 3, 7),
-    0x62 => (JAM, Implied, 1, 0),
-    0x63 => (RRA, IndirectX, 2, 8),
-    0x64 => (NOP, ZeroPage, 2, 3),
-    0x67 => (RRA, ZeroPage, 2, 5),
-    0x6B => (ARR, Immediate, 2, 2),
-    0x6F => (RRA, Absolute, 3, 6),
-    0x72 => (JAM, Implied, 1, 0),
-    0x73 => (RRA, IndirectY, 2, 8),
-    0x74 => (NOP, ZeroPageX, 2, 4),
-    0x77 => (RRA, ZeroPageX, 2, 6),
-    0x7A => (NOP, Implied, 1, 2),
-    0x7B => (RRA, AbsoluteY, 3, 7),
-    0x7C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0x7F => (RRA, AbsoluteX, 3, 7),
-    0x80 => (NOP, Immediate, 2, 2),
-    0x82 => (NOP, Immediate, 2, 2),
-    0x83 => (SAX, IndirectX, 2, 6),
-    0x87 => (SAX, ZeroPage, 2, 3),
-    0x89 => (NOP, Immediate, 2, 2),
-    0x8B => (XAA, Immediate, 2, 2), // Highly unstable
-    0x8F => (SAX, Absolute, 3, 4),
-    0x92 => (JAM, Implied, 1, 0),
-    0x93 => (AHX, IndirectY, 2, 6),
-    0x97 => (SAX, ZeroPageY, 2, 4),
-    0x9B => (TAS, AbsoluteY, 3, 5),
-    0x9C => (SHY, AbsoluteX, 3, 5),
-    0x9E => (SHX, AbsoluteY, 3, 5),
-    0x9F => (AHX, AbsoluteY, 3, 5),
-    0xA3 => (LAX, IndirectX, 2, 6),
-    0xA7 => (LAX, ZeroPage, 2, 3),
-    0xAB => (LAX, Immediate, 2, 2),
-    0xAF => (LAX, Absolute, 3, 4),
-    0xB2 => (JAM, Implied, 1, 0),
-    0xB3 => (LAX, IndirectY, 2, 5), // +1 if page crossed
-    0xB7 => (LAX, ZeroPageY, 2, 4),
-    0xBB => (LAS, AbsoluteY, 3, 4), // +1 if page crossed
-    0xBF => (LAX, AbsoluteY, 3, 4), // +1 if page crossed
-    0xC2 => (NOP, Immediate, 2, 2),
-    0xC3 => (DCP, IndirectX, 2, 8),
-    0xC7 => (DCP, ZeroPage, 2, 5),
-    0xCB => (AXS, Immediate, 2, 2),
-    0xCF => (DCP, Absolute, 3, 6),
-    0xD2 => (JAM, Implied, 1, 0),
-    0xD3 => (DCP, IndirectY, 2, 8),
-    0xD4 => (NOP, ZeroPageX, 2, 4),
-    0xD7 => (DCP, ZeroPageX, 2, 6),
-    0xDA => (NOP, Implied, 1, 2),
-    0xDB => (DCP, AbsoluteY, 3, 7),
-    0xDC => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0xDF => (DCP, AbsoluteX, 3, 7),
-    0xE2 => (NOP, Immediate, 2, 2),
-    0xE3 => (ISC, IndirectX, 2, 8),
-    0xE7 => (ISC, ZeroPage, 2, 5),
-    0xEB => (SBC, Immediate, 2, 2),
-    0xEF => (ISC, Absolute, 3, 6),
-    0xF2 => (JAM, Implied, 1, 0),
-    0xF3 => (ISC, IndirectY, 2, 8),
-    0xF4 => (NOP, ZeroPageX, 2, 4),
-    0xF7 => (ISC, ZeroPageX, 2, 6),
-    0xFA => (NOP, Implied, 1, 2),
-    0xFB => (ISC, AbsoluteY, 3, 7),
-    0xFC => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
-    0xFF => (ISC, AbsoluteX, 3, 7),
+    Unofficial {
+        0x02 => (JAM, Implied, 1, 0), // Halts CPU
+        0x03 => (SLO, IndirectX, 2, 8),
+        0x04 => (NOP, ZeroPage, 2, 3),
+        0x07 => (SLO, ZeroPage, 2, 5),
+        0x0B => (ANC, Immediate, 2, 2),
+        0x0C => (NOP, Absolute, 3, 4),
+        0x0F => (SLO, Absolute, 3, 6),
+        0x12 => (JAM, Implied, 1, 0),
+        0x13 => (SLO, IndirectY, 2, 8),
+        0x14 => (NOP, ZeroPageX, 2, 4),
+        0x17 => (SLO, ZeroPageX, 2, 6),
+        0x1A => (NOP, Implied, 1, 2),
+        0x1B => (SLO, AbsoluteY, 3, 7),
+        0x1C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0x1F => (SLO, AbsoluteX, 3, 7),
+        0x22 => (JAM, Implied, 1, 0),
+        0x23 => (RLA, IndirectX, 2, 8),
+        0x27 => (RLA, ZeroPage, 2, 5),
+        0x2B => (ANC, Immediate, 2, 2),
+        0x2F => (RLA, Absolute, 3, 6),
+        0x32 => (JAM, Implied, 1, 0),
+        0x33 => (RLA, IndirectY, 2, 8),
+        0x34 => (NOP, ZeroPageX, 2, 4),
+        0x37 => (RLA, ZeroPageX, 2, 6),
+        0x3A => (NOP, Implied, 1, 2),
+        0x3B => (RLA, AbsoluteY, 3, 7),
+        0x3C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0x3F => (RLA, AbsoluteX, 3, 7),
+        0x42 => (JAM, Implied, 1, 0),
+        0x43 => (SRE, IndirectX, 2, 8),
+        0x44 => (NOP, ZeroPage, 2, 3),
+        0x47 => (SRE, ZeroPage, 2, 5),
+        0x4B => (ALR, Immediate, 2, 2),
+        0x4F => (SRE, Absolute, 3, 6),
+        0x52 => (JAM, Implied, 1, 0),
+        0x53 => (SRE, IndirectY, 2, 8),
+        0x54 => (NOP, ZeroPageX, 2, 4),
+        0x57 => (SRE, ZeroPageX, 2, 6),
+        0x5A => (NOP, Implied, 1, 2),
+        0x5B => (SRE, AbsoluteY, 3, 7),
+        0x5C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0x5F => (SRE, AbsoluteX, 3, 7),
+        0x62 => (JAM, Implied, 1, 0),
+        0x63 => (RRA, IndirectX, 2, 8),
+        0x64 => (NOP, ZeroPage, 2, 3),
+        0x67 => (RRA, ZeroPage, 2, 5),
+        0x6B => (ARR, Immediate, 2, 2),
+        0x6F => (RRA, Absolute, 3, 6),
+        0x72 => (JAM, Implied, 1, 0),
+        0x73 => (RRA, IndirectY, 2, 8),
+        0x74 => (NOP, ZeroPageX, 2, 4),
+        0x77 => (RRA, ZeroPageX, 2, 6),
+        0x7A => (NOP, Implied, 1, 2),
+        0x7B => (RRA, AbsoluteY, 3, 7),
+        0x7C => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0x7F => (RRA, AbsoluteX, 3, 7),
+        0x80 => (NOP, Immediate, 2, 2),
+        0x82 => (NOP, Immediate, 2, 2),
+        0x83 => (SAX, IndirectX, 2, 6),
+        0x87 => (SAX, ZeroPage, 2, 3),
+        0x89 => (NOP, Immediate, 2, 2),
+        0x8B => (XAA, Immediate, 2, 2), // Highly unstable
+        0x8F => (SAX, Absolute, 3, 4),
+        0x92 => (JAM, Implied, 1, 0),
+        0x93 => (AHX, IndirectY, 2, 6),
+        0x97 => (SAX, ZeroPageY, 2, 4),
+        0x9B => (TAS, AbsoluteY, 3, 5),
+        0x9C => (SHY, AbsoluteX, 3, 5),
+        0x9E => (SHX, AbsoluteY, 3, 5),
+        0x9F => (AHX, AbsoluteY, 3, 5),
+        0xA3 => (LAX, IndirectX, 2, 6),
+        0xA7 => (LAX, ZeroPage, 2, 3),
+        0xAB => (LAX, Immediate, 2, 2),
+        0xAF => (LAX, Absolute, 3, 4),
+        0xB2 => (JAM, Implied, 1, 0),
+        0xB3 => (LAX, IndirectY, 2, 5), // +1 if page crossed
+        0xB7 => (LAX, ZeroPageY, 2, 4),
+        0xBB => (LAS, AbsoluteY, 3, 4), // +1 if page crossed
+        0xBF => (LAX, AbsoluteY, 3, 4), // +1 if page crossed
+        0xC2 => (NOP, Immediate, 2, 2),
+        0xC3 => (DCP, IndirectX, 2, 8),
+        0xC7 => (DCP, ZeroPage, 2, 5),
+        0xCB => (AXS, Immediate, 2, 2),
+        0xCF => (DCP, Absolute, 3, 6),
+        0xD2 => (JAM, Implied, 1, 0),
+        0xD3 => (DCP, IndirectY, 2, 8),
+        0xD4 => (NOP, ZeroPageX, 2, 4),
+        0xD7 => (DCP, ZeroPageX, 2, 6),
+        0xDA => (NOP, Implied, 1, 2),
+        0xDB => (DCP, AbsoluteY, 3, 7),
+        0xDC => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0xDF => (DCP, AbsoluteX, 3, 7),
+        0xE2 => (NOP, Immediate, 2, 2),
+        0xE3 => (ISC, IndirectX, 2, 8),
+        0xE7 => (ISC, ZeroPage, 2, 5),
+        0xEB => (SBC, Immediate, 2, 2),
+        0xEF => (ISC, Absolute, 3, 6),
+        0xF2 => (JAM, Implied, 1, 0),
+        0xF3 => (ISC, IndirectY, 2, 8),
+        0xF4 => (NOP, ZeroPageX, 2, 4),
+        0xF7 => (ISC, ZeroPageX, 2, 6),
+        0xFA => (NOP, Implied, 1, 2),
+        0xFB => (ISC, AbsoluteY, 3, 7),
+        0xFC => (NOP, AbsoluteX, 3, 4), // +1 if page crossed
+        0xFF => (ISC, AbsoluteX, 3, 7),
+    }
 };
 
-pub struct DecodedInstruction<'a> {
-    pub info: &'a InstructionInfo,
-    pub operand: Option<u16>,
+/// A complete instruction read from memory.
+pub struct DecodedInstruction {
+    pub info: &'static InstructionInfo,
+    pub operand: u16,  // Zero for one byte instructions
+    pub address: u16,  // Where the instruction starts
+    raw: [u8; 3],      // Opcode and operand bytes as they appear in memory
+}
+
+impl DecodedInstruction {
+    pub fn bytes(&self) -> &[u8] {
+        &self.raw[..self.info.bytes as usize]
+    }
+
+    /// Address of the next instruction in memory.
+    pub fn next_address(&self) -> u16 {
+        self.address.wrapping_add(self.info.bytes as u16)
+    }
+
+    /// Destination of a branch, resolved against the instruction's address.
+    pub fn branch_target(&self) -> Option<u16> {
+        match self.info.mode {
+            AddressMode::Relative => Some(self.next_address().wrapping_add(self.operand as u8 as i8 as u16)),
+            _ => None,
+        }
+    }
+}
+
+/// Looks up a name for an address, for instructions rendered with symbols.
+pub type Names<'n> = dyn Fn(u16) -> Option<String> + 'n;
+
+/// An instruction displayed with operand addresses replaced by names.
+pub struct Named<'i, 'n> {
+    instruction: &'i DecodedInstruction,
+    names: &'n Names<'n>,
+}
+
+impl fmt::Display for Named<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.instruction.render(f, Some(self.names))
+    }
+}
+
+impl fmt::Display for DecodedInstruction {
+    /// Renders standard 6502 syntax, e.g. `LDA ($20),Y`. The alternate form (`{:#}`)
+    /// marks unofficial opcodes with a `*`, as Nintendulator-style trace logs do.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.render(f, None)
+    }
+}
+
+impl DecodedInstruction {
+    /// Displays like the instruction itself, but shows `names(addr)` instead of
+    /// any address operand that has a name.
+    pub fn with_names<'n>(&self, names: &'n Names<'n>) -> Named<'_, 'n> {
+        Named { instruction: self, names }
+    }
+
+    fn render(&self, f: &mut fmt::Formatter, names: Option<&Names>) -> fmt::Result {
+        let operand = self.operand;
+        let marker = if f.alternate() && !self.info.is_official() { "*" } else { "" };
+        write!(f, "{}{:?}", marker, self.info.mnemonic)?;
+        let name = |addr: u16| names.and_then(|names| names(addr));
+        let zero_page = name(operand).unwrap_or_else(|| format!("${:02X}", operand));
+        let absolute = name(operand).unwrap_or_else(|| format!("${:04X}", operand));
+        match self.info.mode {
+            AddressMode::Implied => Ok(()),
+            AddressMode::Accumulator => write!(f, " A"),
+            AddressMode::Immediate => write!(f, " #${:02X}", operand),
+            AddressMode::ZeroPage => write!(f, " {}", zero_page),
+            AddressMode::ZeroPageX => write!(f, " {},X", zero_page),
+            AddressMode::ZeroPageY => write!(f, " {},Y", zero_page),
+            AddressMode::Relative => {
+                let target = self.branch_target().unwrap_or(0);
+                write!(f, " {}", name(target).unwrap_or_else(|| format!("${:04X}", target)))
+            }
+            AddressMode::Absolute => write!(f, " {}", absolute),
+            AddressMode::AbsoluteX => write!(f, " {},X", absolute),
+            AddressMode::AbsoluteY => write!(f, " {},Y", absolute),
+            AddressMode::Indirect => write!(f, " ({})", absolute),
+            AddressMode::IndirectX => write!(f, " ({},X)", zero_page),
+            AddressMode::IndirectY => write!(f, " ({}),Y", zero_page),
+        }
+    }
+}
+
+/// What the disassembler found at an address.
+pub enum Decoded {
+    Instruction(DecodedInstruction),
+    /// Memory ended partway through an instruction; holds the bytes that were there.
+    Truncated { address: u16, bytes: Vec<u8> },
+    /// A byte with no entry in `OPCODE_MAP`.
+    Unknown { address: u16, opcode: u8 },
+}
+
+impl Decoded {
+    pub fn address(&self) -> u16 {
+        match self {
+            Decoded::Instruction(instruction) => instruction.address,
+            Decoded::Truncated { address, .. } | Decoded::Unknown { address, .. } => *address,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Decoded::Instruction(instruction) => instruction.bytes(),
+            Decoded::Truncated { bytes, .. } => bytes,
+            Decoded::Unknown { opcode, .. } => std::slice::from_ref(opcode),
+        }
+    }
+}
+
+impl fmt::Display for Decoded {
+    /// Instructions as `DecodedInstruction` shows them; anything else as `.byte` data.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoded::Instruction(instruction) => fmt::Display::fmt(instruction, f),
+            _ => {
+                let bytes: Vec<String> = self.bytes().iter().map(|b| format!("${:02X}", b)).collect();
+                write!(f, ".byte {}", bytes.join(", "))
+            }
+        }
+    }
+}
+
+/// Somewhere to disassemble from, such as a ROM bank or live memory through the
+/// console's bus. `None` means there's nothing at that address.
+pub trait MemoryReader {
+    fn read(&self, addr: u16) -> Option<u8>;
+}
+
+impl<R: MemoryReader + ?Sized> MemoryReader for &R {
+    fn read(&self, addr: u16) -> Option<u8> {
+        (**self).read(addr)
+    }
 }
 
-/// Disassembles a bytecode stream into a vector of instructions.
-/// This new function correctly handles operands and uses the efficient OPCODE_MAP for lookups.
-pub fn disassemble(bytecode: &[u8]) -> Vec<DecodedInstruction> {
-    let mut instructions = Vec::new();
-    let mut pc = 0;
-    while pc < bytecode.len() {
-        let opcode = bytecode[pc];
-        if let Some(info) = &OPCODE_MAP[opcode as usize] {
-            let operand = match info.bytes {
-                2 => {
-                    if pc + 1 < bytecode.len() {
-                        Some(bytecode[pc + 1] as u16)
-                    } else { None }
-                },
-                3 => {
-                    if pc + 2 < bytecode.len() {
-                        Some(u16::from_le_bytes([bytecode[pc + 1], bytecode[pc + 2]]))
-                    } else { None }
-                },
-                _ => None,
-            };
-
-            instructions.push(DecodedInstruction { info, operand });
-            pc += info.bytes as usize;
-        } else {
-            // Handle illegal/unknown opcode, just advance past it
-            pc += 1;
+/// Decodes the instruction at `address`. Returns `None` if the reader has no byte there.
+pub fn decode(memory: &impl MemoryReader, address: u16) -> Option<Decoded> {
+    let opcode = memory.read(address)?;
+    let Some(info) = OPCODE_MAP[opcode as usize].as_ref() else {
+        return Some(Decoded::Unknown { address, opcode });
+    };
+    let mut raw = [opcode, 0, 0];
+    for i in 1..info.bytes as usize {
+        match memory.read(address.wrapping_add(i as u16)) {
+            Some(byte) => raw[i] = byte,
+            None => return Some(Decoded::Truncated { address, bytes: raw[..i].to_vec() }),
         }
     }
-    instructions
-}
\ No newline at end of file
+    let operand = match info.bytes {
+        2 => raw[1] as u16,
+        3 => u16::from_le_bytes([raw[1], raw[2]]),
+        _ => 0,
+    };
+    Some(Decoded::Instruction(DecodedInstruction { info, operand, address, raw }))
+}
+
+/// Lazily disassembles memory in a linear sweep. Created by `disassemble`.
+pub struct Disassembler<R> {
+    memory: R,
+    address: Option<u16>, // None once the sweep has run out of memory or address space
+}
+
+impl<R: MemoryReader> Iterator for Disassembler<R> {
+    type Item = Decoded;
+
+    fn next(&mut self) -> Option<Decoded> {
+        let decoded = decode(&self.memory, self.address?);
+        self.address = match &decoded {
+            Some(decoded) => decoded.address().checked_add(decoded.bytes().len() as u16),
+            None => None,
+        };
+        decoded
+    }
+}
+
+/// Disassembles from `base` until the reader runs out of bytes or the sweep reaches
+/// the end of the address space. Unknown opcodes and a final partial instruction
+/// come out as `Decoded::Unknown` and `Decoded::Truncated`.
+pub fn disassemble<R: MemoryReader>(memory: R, base: u16) -> Disassembler<R> {
+    Disassembler { memory, address: Some(base) }
+}
@@ -0,0 +1,270 @@
+use crate::cartridge::Cartridge;
+use crate::decoder::{self, AddressMode, Decoded, DecodedInstruction, Flow, MemoryReader, Mnemonic};
+use crate::mapper::Mapper;
+use crate::symbols::{Location, Symbols};
+use std::collections::HashMap;
+
+/// One PRG ROM bank and the CPU address it runs at.
+pub struct Bank<'a> {
+    pub index: usize,
+    pub origin: u16,
+    pub offset: usize, // Position in PRG ROM
+    pub data: &'a [u8],
+}
+
+impl Bank<'_> {
+    pub fn end(&self) -> u16 {
+        self.origin.wrapping_add(self.data.len() as u16 - 1)
+    }
+
+    /// The whole instruction starting at `offset`, unless it runs past the end of the bank.
+    pub fn instruction_at(&self, offset: usize) -> Option<DecodedInstruction> {
+        match decoder::decode(self, self.origin.wrapping_add(offset as u16))? {
+            Decoded::Instruction(instruction) => Some(instruction),
+            _ => None,
+        }
+    }
+}
+
+impl MemoryReader for Bank<'_> {
+    fn read(&self, addr: u16) -> Option<u8> {
+        self.data.get(addr.wrapping_sub(self.origin) as usize).copied()
+    }
+}
+
+/// Splits PRG ROM into banks placed at their mapped origins. Without a mapper
+/// (unsupported boards) the usual 16KB layout with a fixed last bank is assumed.
+pub fn prg_banks<'a>(cartridge: &'a Cartridge, mapper: Option<&dyn Mapper>) -> Result<Vec<Bank<'a>>, String> {
+    if cartridge.prg_rom.is_empty() {
+        return Err("ROM has no PRG ROM to disassemble.".to_string());
+    }
+    let bank_size = mapper.map_or(0x4000, |m| m.prg_bank_size()).min(cartridge.prg_rom.len());
+    let chunks: Vec<&[u8]> = cartridge.prg_rom.chunks(bank_size).collect();
+    let count = chunks.len();
+    Ok(chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            let origin = match mapper {
+                Some(mapper) => mapper.prg_bank_origin(index, count),
+                None if index + 1 == count => (0x10000 - bank_size) as u16,
+                None => 0x8000,
+            };
+            Bank { index, origin, offset: index * bank_size, data }
+        })
+        .collect())
+}
+
+/// The NMI, RESET and IRQ vectors as the CPU sees them at power-on.
+pub fn vectors(cartridge: &Cartridge, mapper: Option<&dyn Mapper>) -> Result<[u16; 3], String> {
+    let prg = &cartridge.prg_rom;
+    if mapper.is_none() && prg.len() < 6 {
+        return Err("PRG ROM is too small to hold the vectors.".to_string());
+    }
+    let read = |addr: u16| match mapper {
+        Some(mapper) => mapper.cpu_peek(addr),
+        None => prg[prg.len() - (0x10000 - addr as usize)],
+    };
+    Ok([0xFFFA, 0xFFFC, 0xFFFE].map(|addr| u16::from_le_bytes([read(addr), read(addr + 1)])))
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ByteKind {
+    Data,    // Never reached by the trace
+    Opcode,  // First byte of an instruction
+    Operand, // Rest of an instruction
+}
+
+/// Code/data classification of every PRG byte, found by following control flow.
+pub struct CodeMap {
+    kinds: Vec<Vec<ByteKind>>, // Per bank, per byte
+}
+
+impl CodeMap {
+    pub fn kind(&self, bank: usize, offset: usize) -> ByteKind {
+        self.kinds[bank][offset]
+    }
+}
+
+// Longest jump table the JMP (ind) heuristic will walk
+const MAX_TABLE_ENTRIES: usize = 128;
+
+/// Finds the bank an address runs from: `current` if it covers the address,
+/// otherwise the only bank mapped there. Switchable windows with several
+/// candidates can't be resolved statically.
+pub fn resolve(banks: &[Bank], current: Option<usize>, addr: u16) -> Option<(usize, usize)> {
+    let covers = |bank: &Bank| addr >= bank.origin && addr <= bank.end();
+    if let Some(current) = current.filter(|&i| covers(&banks[i])) {
+        return Some((current, (addr - banks[current].origin) as usize));
+    }
+    let mut candidates = banks.iter().filter(|bank| covers(bank));
+    match (candidates.next(), candidates.next()) {
+        (Some(bank), None) => Some((bank.index, (addr - bank.origin) as usize)),
+        _ => None,
+    }
+}
+
+fn read_rom(banks: &[Bank], current: usize, addr: u16) -> Option<u8> {
+    resolve(banks, Some(current), addr).map(|(bank, offset)| banks[bank].data[offset])
+}
+
+/// Finds the bank holding a PRG ROM offset.
+fn locate(banks: &[Bank], prg_offset: usize) -> Option<(usize, usize)> {
+    let bank = banks.iter().find(|bank| (bank.offset..bank.offset + bank.data.len()).contains(&prg_offset))?;
+    Some((bank.index, prg_offset - bank.offset))
+}
+
+/// Recursive-traversal disassembly: starting from `entry_points` (usually the
+/// vectors) and from PRG offsets a Code/Data Log saw executed, follows jumps, calls
+/// and both sides of branches, stopping at RTS, RTI, BRK, JAM and unconditional
+/// jumps. Everything never reached is data.
+pub fn trace(banks: &[Bank], entry_points: &[u16], logged_code: &[usize]) -> CodeMap {
+    let mut map = CodeMap { kinds: banks.iter().map(|bank| vec![ByteKind::Data; bank.data.len()]).collect() };
+    let mut pending: Vec<(usize, usize)> = entry_points.iter().filter_map(|&addr| resolve(banks, None, addr)).collect();
+    pending.extend(logged_code.iter().filter_map(|&offset| locate(banks, offset)));
+
+    while let Some((bank, mut offset)) = pending.pop() {
+        // Table addresses loaded and stored in this run, to recognize jump table dispatch
+        let mut loaded = None;
+        let mut pointers: HashMap<u16, u16> = HashMap::new();
+
+        loop {
+            if map.kinds[bank][offset] != ByteKind::Data {
+                break; // Already traced, or jumps into the middle of an instruction
+            }
+            let addr = banks[bank].origin.wrapping_add(offset as u16);
+            let Some(instruction) = banks[bank].instruction_at(offset) else {
+                break; // Runs off the end of the bank
+            };
+            let len = instruction.info.bytes as usize;
+            if map.kinds[bank][offset + 1..offset + len].iter().any(|&k| k != ByteKind::Data) {
+                break; // Overlaps other code
+            }
+            map.kinds[bank][offset] = ByteKind::Opcode;
+            map.kinds[bank][offset + 1..offset + len].fill(ByteKind::Operand);
+
+            let mut follow = |target: u16| {
+                if let Some(location) = resolve(banks, Some(bank), target) {
+                    pending.push(location);
+                }
+            };
+            let operand = instruction.operand;
+            match (instruction.info.flow, instruction.info.mnemonic, instruction.info.mode) {
+                (Flow::Return | Flow::Interrupt | Flow::Halt, _, _) => break,
+                (Flow::Jump, _, AddressMode::Absolute) => {
+                    follow(operand);
+                    break;
+                }
+                (Flow::Jump, _, _) => {
+                    for target in indirect_targets(banks, bank, operand, &pointers) {
+                        follow(target);
+                    }
+                    break;
+                }
+                (Flow::Call, _, _) => follow(operand),
+                (Flow::Branch, _, _) => follow(instruction.branch_target().unwrap_or(0)),
+                (_, Mnemonic::LDA, AddressMode::Absolute | AddressMode::AbsoluteX | AddressMode::AbsoluteY) => {
+                    loaded = Some(operand);
+                }
+                (_, Mnemonic::STA, AddressMode::ZeroPage | AddressMode::Absolute) => {
+                    if let Some(table) = loaded {
+                        pointers.insert(operand, table);
+                    }
+                }
+                _ => {}
+            }
+
+            offset += len;
+            if offset >= banks[bank].data.len() {
+                // Falls through into whatever is mapped next
+                follow(addr.wrapping_add(len as u16));
+                break;
+            }
+        }
+    }
+    map
+}
+
+/// Guesses where a `JMP (pointer)` can go. A pointer in ROM is read directly.
+/// A pointer in RAM is matched against the `LDA table,X / STA pointer` pairs seen
+/// just before it, and the table is read until an entry leaves ROM.
+fn indirect_targets(banks: &[Bank], bank: usize, pointer: u16, pointers: &HashMap<u16, u16>) -> Vec<u16> {
+    // JMP ($xxFF) takes its high byte from $xx00, as on the real CPU
+    let pointer_high = (pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF);
+    if let (Some(lo), Some(hi)) = (read_rom(banks, bank, pointer), read_rom(banks, bank, pointer_high)) {
+        return vec![u16::from_le_bytes([lo, hi])];
+    }
+
+    let (Some(&lo_table), Some(&hi_table)) = (pointers.get(&pointer), pointers.get(&pointer_high)) else {
+        return Vec::new();
+    };
+    // Either interleaved words, or separate low and high byte tables
+    let (lo_table, hi_table, stride) = if hi_table == lo_table.wrapping_add(1) { (lo_table, hi_table, 2) } else { (lo_table, hi_table, 1) };
+    let mut targets = Vec::new();
+    for entry in 0..MAX_TABLE_ENTRIES as u16 {
+        let lo_addr = lo_table.wrapping_add(entry * stride);
+        if stride == 1 && entry > 0 && lo_addr == hi_table {
+            break; // Split tables are usually back to back
+        }
+        let (Some(lo), Some(hi)) = (read_rom(banks, bank, lo_addr), read_rom(banks, bank, hi_table.wrapping_add(entry * stride))) else { break };
+        let target = u16::from_le_bytes([lo, hi]);
+        if resolve(banks, Some(bank), target).is_none() {
+            break;
+        }
+        targets.push(target);
+    }
+    targets
+}
+
+/// Prints the part of a bank between `start` and `end` (inclusive CPU addresses),
+/// as instructions where the trace found code and `.byte` rows elsewhere.
+/// Operands, labels and comments come from `symbols` where it has them.
+pub fn print_bank(banks: &[Bank], bank: &Bank, map: &CodeMap, symbols: &Symbols, start: u16, end: u16) {
+    let first = start.max(bank.origin);
+    let last = end.min(bank.end());
+    if first > last {
+        return;
+    }
+    let names = |addr: u16| {
+        let prg_offset = resolve(banks, Some(bank.index), addr).map(|(bank, offset)| banks[bank].offset + offset);
+        symbols.name(addr, prg_offset)
+    };
+    let label = |offset: usize| symbols.get(Location::Prg(bank.offset + offset)).filter(|&(_, index)| index == 0);
+
+    println!("; Bank {} (${:04X}-${:04X})", bank.index, bank.origin, bank.end());
+    let mut offset = (first - bank.origin) as usize;
+    let end = (last - bank.origin) as usize + 1;
+    while offset < end {
+        let addr = bank.origin.wrapping_add(offset as u16);
+        if let Some((symbol, _)) = label(offset) {
+            println!("{}:", symbol.name);
+        }
+        let comment = symbols.comment(Location::Prg(bank.offset + offset)).map(|c| format!("  ; {}", c)).unwrap_or_default();
+        let instruction = match map.kinds[bank.index][offset] {
+            ByteKind::Opcode => bank.instruction_at(offset),
+            _ => None,
+        };
+        match instruction {
+            Some(instruction) => {
+                let raw: Vec<String> = instruction.bytes().iter().map(|b| format!("{:02X}", b)).collect();
+                println!("{:04X}  {:<8}  {:#}{}", addr, raw.join(" "), instruction.with_names(&names), comment);
+                offset += instruction.bytes().len();
+            }
+            None => {
+                // Up to eight data bytes per row, breaking at the next instruction or label
+                let mut len = 1;
+                while len < 8
+                    && offset + len < end
+                    && map.kinds[bank.index][offset + len] == ByteKind::Data
+                    && label(offset + len).is_none()
+                {
+                    len += 1;
+                }
+                let bytes: Vec<String> = bank.data[offset..offset + len].iter().map(|b| format!("${:02X}", b)).collect();
+                println!("{:04X}  {:<8}  .byte {}{}", addr, "", bytes.join(", "), comment);
+                offset += len;
+            }
+        }
+    }
+    println!();
+}
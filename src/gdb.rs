@@ -0,0 +1,512 @@
+use crate::console::Console;
+use crate::debugger::{Breakpoints, Stop, WatchKind, Watchpoint};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+
+// GDB has no 6502 target, so we describe our own register file. Clients that don't
+// read target descriptions see the same layout in the `g` packet: A, X, Y, P and SP
+// as single bytes, then PC as a little-endian word.
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.samnes.6502">
+    <reg name="a" bitsize="8" regnum="0"/>
+    <reg name="x" bitsize="8"/>
+    <reg name="y" bitsize="8"/>
+    <reg name="p" bitsize="8"/>
+    <reg name="sp" bitsize="8"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+  </feature>
+</target>
+"#;
+
+// How often a running target checks for a Ctrl-C from the client.
+const INTERRUPT_POLL_INSTRUCTIONS: u32 = 10_000;
+
+/// A client connection, over TCP or a Unix socket.
+pub trait Connection: Read + Write {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        TcpStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+impl Connection for UnixStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        UnixStream::set_nonblocking(self, nonblocking)
+    }
+}
+
+enum Packet {
+    Command(String),
+    Interrupt, // Ctrl-C (0x03) sent outside a packet
+}
+
+/// GDB Remote Serial Protocol server for a single client session.
+pub struct GdbStub<C: Connection> {
+    connection: C,
+    pending: VecDeque<u8>, // Bytes that arrived while polling for Ctrl-C, not yet read as packets
+    breakpoints: Breakpoints,
+    no_ack: bool,
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(connection: C) -> Self {
+        GdbStub { connection, pending: VecDeque::new(), breakpoints: Breakpoints::new(), no_ack: false }
+    }
+
+    /// Serves requests until the client detaches, kills the target or disconnects.
+    pub fn serve(&mut self, console: &mut Console) -> io::Result<()> {
+        loop {
+            let command = match self.read_packet()? {
+                Some(Packet::Command(command)) => command,
+                Some(Packet::Interrupt) => {
+                    self.send("S02")?;
+                    continue;
+                }
+                None => return Ok(()), // Connection closed
+            };
+            match self.handle(console, &command)? {
+                Some(reply) => self.send(&reply)?,
+                None => return Ok(()),
+            }
+            // The OK itself is still acknowledged; everything after it isn't
+            if command.starts_with("QStartNoAckMode") {
+                self.no_ack = true;
+            }
+        }
+    }
+
+    /// Handles one command. Returns the reply, or None when the session ends.
+    fn handle(&mut self, console: &mut Console, command: &str) -> io::Result<Option<String>> {
+        let reply = match command.as_bytes().first() {
+            Some(b'?') => "S05".to_string(),
+            Some(b'g') => {
+                let cpu = &console.cpu;
+                let pc = cpu.pc.to_le_bytes();
+                hex(&[cpu.ac, cpu.idx, cpu.idy, cpu.status, cpu.sp, pc[0], pc[1]])
+            }
+            Some(b'G') => match unhex(&command[1..]) {
+                Some(bytes) if bytes.len() == 7 => {
+                    let cpu = &mut console.cpu;
+                    cpu.ac = bytes[0];
+                    cpu.idx = bytes[1];
+                    cpu.idy = bytes[2];
+                    cpu.status = bytes[3];
+                    cpu.sp = bytes[4];
+                    cpu.pc = u16::from_le_bytes([bytes[5], bytes[6]]);
+                    "OK".to_string()
+                }
+                _ => "E01".to_string(),
+            },
+            Some(b'p') => match usize::from_str_radix(&command[1..], 16) {
+                Ok(5) => hex(&console.cpu.pc.to_le_bytes()),
+                Ok(n) if n < 5 => hex(&[register(console, n)]),
+                _ => "E01".to_string(),
+            },
+            Some(b'P') => self.write_register(console, &command[1..]).unwrap_or_else(|| "E01".to_string()),
+            Some(b'm') => match parse_pair(&command[1..], ',') {
+                // Reads go through the bus without side effects, so inspecting PPU registers is harmless
+                Some((addr, len)) => {
+                    let bytes: Vec<u8> = (0..len.min(0x800)).map(|i| console.peek(addr.wrapping_add(i) as u16)).collect();
+                    hex(&bytes)
+                }
+                None => "E01".to_string(),
+            },
+            Some(b'M') => {
+                let parsed = command[1..]
+                    .split_once(':')
+                    .and_then(|(range, data)| Some((parse_pair(range, ',')?, unhex(data)?)));
+                match parsed {
+                    Some(((addr, len), data)) if data.len() == len as usize => {
+                        for (i, byte) in data.into_iter().enumerate() {
+                            console.write(addr.wrapping_add(i as u32) as u16, byte);
+                        }
+                        "OK".to_string()
+                    }
+                    _ => "E01".to_string(),
+                }
+            }
+            Some(b'c') => self.resume(console, &command[1..], false)?,
+            Some(b's') => self.resume(console, &command[1..], true)?,
+            Some(b'Z') | Some(b'z') => self.set_breakpoint(command).unwrap_or_else(|| "E01".to_string()),
+            Some(b'k') => return Ok(None),
+            Some(b'D') => {
+                self.send("OK")?;
+                return Ok(None);
+            }
+            Some(b'H') => "OK".to_string(), // Only one thread
+            Some(b'T') => "OK".to_string(),
+            _ if command.starts_with("vCont?") => "vCont;c;s".to_string(),
+            _ if command.starts_with("vCont;c") => self.resume(console, "", false)?,
+            _ if command.starts_with("vCont;s") => self.resume(console, "", true)?,
+            _ if command.starts_with("qSupported") => "PacketSize=4000;qXfer:features:read+;QStartNoAckMode+".to_string(),
+            _ if command.starts_with("QStartNoAckMode") => "OK".to_string(),
+            _ if command.starts_with("qXfer:features:read:target.xml:") => {
+                let range = &command["qXfer:features:read:target.xml:".len()..];
+                match parse_pair(range, ',') {
+                    Some((offset, len)) => {
+                        let data = TARGET_XML.get(offset as usize..).unwrap_or("");
+                        let chunk = &data[..data.len().min(len as usize)];
+                        let prefix = if chunk.len() < data.len() { 'm' } else { 'l' };
+                        format!("{}{}", prefix, chunk)
+                    }
+                    None => "E01".to_string(),
+                }
+            }
+            _ if command.starts_with("qAttached") => "1".to_string(),
+            _ if command.starts_with("qC") => "QC1".to_string(),
+            _ if command.starts_with("qfThreadInfo") => "m1".to_string(),
+            _ if command.starts_with("qsThreadInfo") => "l".to_string(),
+            _ => String::new(), // Unsupported packets get an empty reply
+        };
+        Ok(Some(reply))
+    }
+
+    fn write_register(&mut self, console: &mut Console, args: &str) -> Option<String> {
+        let (n, value) = args.split_once('=')?;
+        let n = usize::from_str_radix(n, 16).ok()?;
+        let value = unhex(value)?;
+        let cpu = &mut console.cpu;
+        match (n, value.as_slice()) {
+            (0, &[v]) => cpu.ac = v,
+            (1, &[v]) => cpu.idx = v,
+            (2, &[v]) => cpu.idy = v,
+            (3, &[v]) => cpu.status = v,
+            (4, &[v]) => cpu.sp = v,
+            (5, &[lo, hi]) => cpu.pc = u16::from_le_bytes([lo, hi]),
+            _ => return None,
+        }
+        Some("OK".to_string())
+    }
+
+    /// Handles Z/z packets: 0/1 are execution breakpoints, 2/3/4 write/read/access watchpoints.
+    fn set_breakpoint(&mut self, command: &str) -> Option<String> {
+        let insert = command.starts_with('Z');
+        let mut fields = command[1..].split(',');
+        let kind = fields.next()?;
+        let addr = u16::from_str_radix(fields.next()?, 16).ok()?;
+        let len = u16::from_str_radix(fields.next()?.split(';').next()?, 16).ok()?.max(1);
+        let watch_kind = match kind {
+            "0" | "1" => {
+                if insert {
+                    self.breakpoints.pc.insert(addr);
+                } else {
+                    self.breakpoints.pc.remove(&addr);
+                }
+                return Some("OK".to_string());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::ReadWrite,
+            _ => return Some(String::new()),
+        };
+        let end = addr.saturating_add(len - 1);
+        if insert {
+            self.breakpoints.watch.push(Watchpoint { start: addr, end, kind: watch_kind });
+        } else {
+            self.breakpoints.watch.retain(|w| !(w.start == addr && w.end == end && w.kind == watch_kind));
+        }
+        Some("OK".to_string())
+    }
+
+    /// Continues or single-steps, optionally from a new address, and returns the stop reply.
+    fn resume(&mut self, console: &mut Console, addr: &str, single_step: bool) -> io::Result<String> {
+        if let Ok(addr) = u16::from_str_radix(addr, 16) {
+            console.cpu.pc = addr;
+        }
+
+        let mut interrupted = false;
+        let mut polled = 0;
+        let connection = &mut self.connection;
+        let pending = &mut self.pending;
+        let stop = self.breakpoints.run_until(console, |_| {
+            if single_step {
+                return true;
+            }
+            polled += 1;
+            if polled == INTERRUPT_POLL_INSTRUCTIONS {
+                polled = 0;
+                interrupted = poll_interrupt(connection, pending);
+            }
+            interrupted
+        });
+
+        Ok(match stop {
+            Stop::Done if interrupted => "S02".to_string(), // SIGINT
+            Stop::Done | Stop::Breakpoint(_) => "S05".to_string(),
+            Stop::Watchpoint { addr, kind, .. } => {
+                let kind = match kind {
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Write => "watch",
+                    WatchKind::ReadWrite => "awatch",
+                };
+                format!("T05{}:{:04x};", kind, addr)
+            }
+            Stop::Jam => "S04".to_string(), // SIGILL
+        })
+    }
+
+    /// Reads the next packet, acknowledging it. Returns None on end of stream.
+    fn read_packet(&mut self) -> io::Result<Option<Packet>> {
+        loop {
+            let Some(byte) = self.read_byte()? else { return Ok(None) };
+            match byte {
+                b'$' => break,
+                0x03 => return Ok(Some(Packet::Interrupt)),
+                _ => {} // Acks and noise between packets
+            }
+        }
+        let mut data = Vec::new();
+        loop {
+            match self.read_byte()? {
+                Some(b'#') => break,
+                Some(byte) => data.push(byte),
+                None => return Ok(None),
+            }
+        }
+        let mut checksum = [0; 2];
+        for digit in &mut checksum {
+            *digit = self.read_byte()?.unwrap_or(0);
+        }
+
+        let expected = std::str::from_utf8(&checksum).ok().and_then(|s| u8::from_str_radix(s, 16).ok());
+        if !self.no_ack {
+            if expected != Some(checksum_of(&data)) {
+                self.connection.write_all(b"-")?;
+                return self.read_packet();
+            }
+            self.connection.write_all(b"+")?;
+        }
+        Ok(Some(Packet::Command(String::from_utf8_lossy(&data).into_owned())))
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        if let Some(byte) = self.pending.pop_front() {
+            return Ok(Some(byte));
+        }
+        let mut byte = [0];
+        match self.connection.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    fn send(&mut self, payload: &str) -> io::Result<()> {
+        let packet = format!("${}#{:02x}", payload, checksum_of(payload.as_bytes()));
+        loop {
+            self.connection.write_all(packet.as_bytes())?;
+            self.connection.flush()?;
+            if self.no_ack {
+                return Ok(());
+            }
+            // A packet sent while the target ran can arrive ahead of the ack; keep it for later
+            let mut early = Vec::new();
+            let ack = loop {
+                match self.read_byte()? {
+                    Some(byte @ (b'+' | b'-')) if between_packets(&early) => break Some(byte),
+                    Some(byte) => early.push(byte),
+                    None => break None,
+                }
+            };
+            for byte in early.into_iter().rev() {
+                self.pending.push_front(byte);
+            }
+            match ack {
+                Some(b'-') => continue, // Client asked for a retransmit
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Checks, without blocking, whether the client sent a Ctrl-C. Anything else that has
+/// arrived is kept in `pending`, so a packet sent while the target runs isn't lost.
+fn poll_interrupt(connection: &mut impl Connection, pending: &mut VecDeque<u8>) -> bool {
+    if connection.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let mut byte = [0];
+    let mut interrupted = false;
+    while let Ok(1) = connection.read(&mut byte) {
+        if byte[0] == 0x03 && between_packets(pending.make_contiguous()) {
+            interrupted = true;
+            break;
+        }
+        pending.push_back(byte[0]);
+    }
+    connection.set_nonblocking(false).ok();
+    interrupted
+}
+
+/// Whether `received` ends outside a packet, where a byte is an ack or a Ctrl-C rather than data.
+fn between_packets(received: &[u8]) -> bool {
+    match received.iter().rposition(|&b| b == b'$') {
+        None => true,
+        Some(start) => received[start..].iter().position(|&b| b == b'#').is_some_and(|end| start + end + 3 <= received.len()),
+    }
+}
+
+fn register(console: &Console, n: usize) -> u8 {
+    let cpu = &console.cpu;
+    [cpu.ac, cpu.idx, cpu.idy, cpu.status, cpu.sp][n]
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Parses `<hex><sep><hex>`, as used by `m`, `M` and `qXfer` packets.
+fn parse_pair(text: &str, separator: char) -> Option<(u32, u32)> {
+    let (a, b) = text.split_once(separator)?;
+    Some((u32::from_str_radix(a, 16).ok()?, u32::from_str_radix(b, 16).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::cartridge::Cartridge;
+    use crate::console::Region;
+    use std::thread;
+
+    const PROGRAM: &str = "
+        reset:
+            lda #$42
+            sta $10
+        loop:
+            inc $11
+            jmp loop
+            .org $FFFA
+            .word reset, reset, reset
+    ";
+
+    fn console() -> Console {
+        let mut prg = vec![0xFF; 0x4000];
+        for (start, bytes) in assembler::assemble(PROGRAM, 0xC000, false).unwrap() {
+            let offset = start as usize - 0xC000;
+            prg[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        let mut console = Console::new(&Cartridge::for_test(0, 0, prg, Vec::new()), Region::Ntsc).unwrap();
+        console.reset();
+        console
+    }
+
+    /// Sends a packet and returns the reply, acknowledging both ways.
+    fn exchange(stream: &mut UnixStream, payload: &str) -> String {
+        send_packet(stream, payload);
+        read_reply(stream)
+    }
+
+    fn send_packet(stream: &mut UnixStream, payload: &str) {
+        write!(stream, "${}#{:02x}", payload, checksum_of(payload.as_bytes())).unwrap();
+        let mut byte = [0];
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'+', "packet {} wasn't acknowledged", payload);
+    }
+
+    fn read_reply(stream: &mut UnixStream) -> String {
+        let mut byte = [0];
+        let mut reply = Vec::new();
+        stream.read_exact(&mut byte).unwrap();
+        assert_eq!(byte[0], b'$');
+        loop {
+            stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+        let mut checksum = [0; 2];
+        stream.read_exact(&mut checksum).unwrap();
+        assert_eq!(std::str::from_utf8(&checksum).unwrap(), format!("{:02x}", checksum_of(&reply)));
+        stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    #[test]
+    fn serves_a_debugging_session() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let session = thread::spawn(move || {
+            let mut replies = Vec::new();
+            let packets = [
+                "?", "g", "s", "g", "mFFFFFFFF,2", "M10,2:abcd", "m10,2", "Z0,c006,1", "c", "g", "z0,c006,1", "Z2,11,1", "c",
+                "z2,11,1", "M10,2:ab",
+            ];
+            for packet in packets {
+                replies.push(exchange(&mut client, packet));
+            }
+            // Killing the target gets no reply, just the acknowledgement
+            write!(client, "$k#{:02x}", checksum_of(b"k")).unwrap();
+            let mut ack = [0];
+            client.read_exact(&mut ack).unwrap();
+            replies
+        });
+
+        let mut console = console();
+        GdbStub::new(server).serve(&mut console).unwrap();
+        let replies = session.join().unwrap();
+
+        assert_eq!(replies[0], "S05");
+        assert!(replies[1].ends_with("00c0"), "PC after reset in {}", replies[1]);
+        assert_eq!(replies[2], "S05");
+        assert!(replies[3].starts_with("42") && replies[3].ends_with("02c0"), "registers after LDA in {}", replies[3]);
+        // The read wraps from $FFFF (the IRQ vector's high byte) to $0000
+        assert_eq!(replies[4], "c000");
+        assert_eq!(replies[5], "OK");
+        assert_eq!(replies[6], "abcd");
+        assert_eq!(replies[7], "OK");
+        assert_eq!(replies[8], "S05");
+        assert!(replies[9].ends_with("06c0"), "PC at the breakpoint in {}", replies[9]);
+        assert_eq!(replies[10], "OK");
+        // INC reads $11 too, but only a write watchpoint is set
+        assert_eq!(replies[12], "T05watch:0011;");
+        assert_eq!(replies[13], "OK");
+        // Two bytes declared, one sent
+        assert_eq!(replies[14], "E01");
+        assert_eq!(console.cpu.pc, 0xC006);
+        assert_eq!(console.peek(0x0010), 0x42);
+    }
+
+    #[test]
+    fn keeps_packets_sent_while_the_target_runs() {
+        let (server, mut client) = UnixStream::pair().unwrap();
+        let session = thread::spawn(move || {
+            send_packet(&mut client, "c");
+            // A packet pipelined behind the continue, then a Ctrl-C to stop the target
+            write!(client, "$?#{:02x}", checksum_of(b"?")).unwrap();
+            client.write_all(&[0x03]).unwrap();
+            let stopped = read_reply(&mut client);
+            let mut ack = [0];
+            client.read_exact(&mut ack).unwrap();
+            assert_eq!(ack[0], b'+', "pipelined packet wasn't acknowledged");
+            let status = read_reply(&mut client);
+            write!(client, "$k#{:02x}", checksum_of(b"k")).unwrap();
+            client.read_exact(&mut ack).unwrap();
+            (stopped, status)
+        });
+
+        let mut console = console();
+        GdbStub::new(server).serve(&mut console).unwrap();
+        assert_eq!(session.join().unwrap(), ("S02".to_string(), "S05".to_string()));
+    }
+}
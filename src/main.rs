@@ -1,58 +1,435 @@
 use std::env;
 use std::fs;
+use std::net::TcpListener;
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
 
+mod apu;
+mod assembler;
+mod battery;
+mod bus;
+mod ca65;
+mod cartridge;
+mod cdl;
+mod console;
+mod controller;
 mod cpu;
+mod debugger;
 mod decoder;
+mod disasm;
+mod gdb;
+mod mapper;
+mod nsf;
 mod ppu;
-mod apu; // We can keep the module, even if it's unused for now
+mod rewind;
+mod savestate;
+mod symbols;
+mod unif;
+mod wav;
+
+use battery::BatteryFile;
+use cartridge::Cartridge;
+use cdl::CodeDataLog;
+use console::{Console, Region};
+use debugger::Debugger;
+use gdb::GdbStub;
+use symbols::Symbols;
 
 fn main() {
-    let file_path = env::args().nth(1).expect("Please provide a ROM file path.");
-    let rom_bytes = fs::read(file_path).expect("Failed to read ROM file.");
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("debug") => {
+            args.remove(0);
+            debug(args);
+        }
+        Some("gdb") => {
+            args.remove(0);
+            gdb_server(args);
+        }
+        Some("disasm") => {
+            args.remove(0);
+            disassemble(args);
+        }
+        Some("play") => {
+            args.remove(0);
+            play(args);
+        }
+        _ => run(args),
+    }
+}
+
+/// Options shared by every subcommand that boots a console.
+struct Options {
+    rom_path: String,
+    region: Option<Region>,
+    load_state: Option<String>,
+    save_state: Option<String>,
+    cdl: Option<String>,
+    clean_mix: bool,
+    bios: Option<String>,
+}
+
+/// Removes `flag <value>` from the arguments, returning the value.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == flag)?;
+    args.remove(index);
+    if index < args.len() {
+        Some(args.remove(index))
+    } else {
+        panic!("{} requires a value.", flag);
+    }
+}
 
+fn parse_options(args: Vec<String>) -> Options {
+    let mut rom_path = None;
+    let mut options = Options { rom_path: String::new(), region: None, load_state: None, save_state: None, cdl: None, clean_mix: false, bios: None };
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--region" => {
+                let name = args.next().expect("--region requires ntsc, pal or dendy.");
+                options.region = Some(name.parse::<Region>().unwrap_or_else(|e| panic!("{}", e)));
+            }
+            "--load-state" => options.load_state = Some(args.next().expect("--load-state requires a file path.")),
+            "--save-state" => options.save_state = Some(args.next().expect("--save-state requires a file path.")),
+            "--cdl" => options.cdl = Some(args.next().expect("--cdl requires a file path.")),
+            "--clean-mix" => options.clean_mix = true,
+            "--bios" => options.bios = Some(args.next().expect("--bios requires a file path.")),
+            _ => rom_path = Some(arg),
+        }
+    }
+    options.rom_path = rom_path.expect("Please provide a ROM file path.");
+    options
+}
+
+/// Reads a ROM or disk image. Disk images also need the FDS BIOS, which is read from
+/// `bios_path` or, by default, `disksys.rom` next to the image.
+fn load_cartridge(rom_path: &str, bios_path: Option<&str>) -> Result<Cartridge, String> {
+    let rom_bytes = fs::read(rom_path).expect("Failed to read ROM file.");
+    let mut cartridge = Cartridge::load(&rom_bytes)?;
+    if !cartridge.disk_sides.is_empty() {
+        let path = bios_path.map_or_else(|| Path::new(rom_path).with_file_name("disksys.rom"), PathBuf::from);
+        let bios = fs::read(&path)
+            .map_err(|e| format!("Failed to read the FDS BIOS from {} ({}); pass it with --bios FILE.", path.display(), e))?;
+        if bios.len() != 0x2000 {
+            return Err(format!("{} is not an 8KB FDS BIOS image.", path.display()));
+        }
+        cartridge.prg_rom = bios;
+    }
+    Ok(cartridge)
+}
+
+/// Loads the ROM, resets the console and restores battery RAM and any requested save state.
+fn boot(options: &Options) -> Option<(Console, Option<BatteryFile>)> {
     // --- Load the ROM ---
-    if rom_bytes.len() < 16 || &rom_bytes[0..4] != b"NES\x1A" {
-        panic!("Invalid iNES header.");
+    let cartridge = load_cartridge(&options.rom_path, options.bios.as_deref()).unwrap_or_else(|e| panic!("{}", e));
+    let region = options.region.or(cartridge.region).unwrap_or(Region::Ntsc);
+
+    let mut console = match Console::new(&cartridge, region) {
+        Ok(console) => console,
+        Err(e) => {
+            println!("Error: {}", e);
+            return None;
+        }
+    };
+
+    // --- Expansion audio: mix time-multiplexed channels cleanly if asked ---
+    if options.clean_mix {
+        console.bus.mapper.set_audio_multiplexing(false);
     }
-    let prg_rom_size = rom_bytes[4] as usize * 16384;
 
-    if prg_rom_size > 32768 {
-        let mapper_num = (rom_bytes[7] & 0xF0) | (rom_bytes[6] >> 4);
-        println!("Error: ROM requires Mapper {}, which is not supported.", mapper_num);
-        return;
+    // --- Battery-backed RAM ---
+    let mut battery = if cartridge.battery { Some(BatteryFile::for_rom(Path::new(&options.rom_path))) } else { None };
+    if let Some(battery) = &mut battery {
+        match battery.load(&mut *console.bus.mapper) {
+            Ok(true) => println!("Loaded battery save from {}.", battery.path().display()),
+            Ok(false) => {}
+            Err(e) => println!("Warning: Failed to read {}: {}", battery.path().display(), e),
+        }
     }
 
-    let prg_rom_start = 16;
-    let prg_rom_end = prg_rom_start + prg_rom_size;
-    let prg_rom = &rom_bytes[prg_rom_start..prg_rom_end];
+    // --- Code/Data Log, added to across runs ---
+    if let Some(path) = &options.cdl {
+        match CodeDataLog::open(Path::new(path), cartridge.prg_rom.len(), cartridge.chr_rom.len()) {
+            Ok(log) => console.enable_code_data_log(log),
+            Err(e) => {
+                println!("Error: {}", e);
+                return None;
+            }
+        }
+    }
 
-    let mut cpu = cpu::CPU::new();
-    cpu.load(prg_rom, 0x8000);
-    if prg_rom_size == 16384 { // NROM-128
-        cpu.load(prg_rom, 0xC000);
+    console.reset();
+    if let Some(path) = &options.load_state {
+        let state = fs::read(path).expect("Failed to read save state.");
+        if let Err(e) = console.load_state(&state) {
+            println!("Error: Failed to load {}: {}", path, e);
+            return None;
+        }
+        println!("Loaded save state from {}.", path);
     }
+    Some((console, battery))
+}
 
-    // --- Run the CPU ---
-    cpu.reset();
+/// Runs the ROM headless, a frame at a time, for a fixed number of cycles.
+fn run(args: Vec<String>) {
+    let options = parse_options(args);
+    let Some((mut console, mut battery)) = boot(&options) else { return };
 
-    let mut total_cycles: u64 = 0;
     const MAX_CYCLES: u64 = 20_000_000;
-    println!("Starting CPU execution for {} cycles...", MAX_CYCLES);
+    const BATTERY_FLUSH_FRAMES: u64 = 300; // About every five seconds
+    let mut last_flush_frame = console.frames();
+    println!("Starting {:?} emulation for {} CPU cycles...", console.region(), MAX_CYCLES);
 
     loop {
-        let cycles = cpu.step();
-        total_cycles += cycles as u64;
-
-        if cycles == 0 { // JAM
-            println!("CPU halted after {} cycles.", total_cycles);
+        if !console.run_frame() { // JAM
+            println!("CPU halted after {} cycles.", console.cycles());
             break;
         }
 
-        if total_cycles > MAX_CYCLES {
-            println!("Emulation finished after {} cycles.", total_cycles);
+        if console.cycles() > MAX_CYCLES {
+            println!("Emulation finished after {} cycles.", console.cycles());
             break;
         }
+
+        if console.frames() >= last_flush_frame + BATTERY_FLUSH_FRAMES {
+            last_flush_frame = console.frames();
+            flush_battery(&mut battery, &console);
+        }
     }
+    flush_battery(&mut battery, &console);
     println!("\nFinal CPU State:");
-    println!("{}", cpu);
-}
\ No newline at end of file
+    println!("{}", console.cpu);
+    write_save_state(&options, &console);
+    write_code_data_log(&options, &console);
+}
+
+/// `samnes debug <rom> [--symbols FILE]...`: interactive debugger.
+fn debug(mut args: Vec<String>) {
+    let symbols = load_symbols(&mut args);
+    let options = parse_options(args);
+    let Some((console, mut battery)) = boot(&options) else { return };
+    let mut debugger = Debugger::new(console, symbols);
+    debugger.run();
+    flush_battery(&mut battery, debugger.console());
+    write_save_state(&options, debugger.console());
+    write_code_data_log(&options, debugger.console());
+}
+
+/// `samnes gdb <rom> [--port N | --unix PATH]`: serves one GDB remote protocol client.
+fn gdb_server(mut args: Vec<String>) {
+    let port = take_option(&mut args, "--port");
+    let socket_path = take_option(&mut args, "--unix");
+    let options = parse_options(args);
+    let Some((mut console, mut battery)) = boot(&options) else { return };
+
+    let result = match socket_path {
+        Some(path) => {
+            let listener = UnixListener::bind(&path).expect("Failed to bind Unix socket.");
+            println!("Waiting for GDB on {}...", path);
+            let served = listener.accept().and_then(|(stream, _)| GdbStub::new(stream).serve(&mut console));
+            fs::remove_file(&path).ok();
+            served
+        }
+        None => {
+            let port = port.map_or(1234, |p| p.parse::<u16>().expect("--port requires a port number."));
+            let listener = TcpListener::bind(("127.0.0.1", port)).expect("Failed to bind TCP port.");
+            println!("Waiting for GDB on 127.0.0.1:{}...", port);
+            listener.accept().and_then(|(stream, _)| {
+                stream.set_nodelay(true)?;
+                GdbStub::new(stream).serve(&mut console)
+            })
+        }
+    };
+    if let Err(e) = result {
+        println!("Error: GDB connection failed: {}", e);
+    }
+    flush_battery(&mut battery, &console);
+    write_save_state(&options, &console);
+    write_code_data_log(&options, &console);
+}
+
+/// `samnes disasm <rom> [--bios FILE] [--bank N] [--start ADDR] [--end ADDR] [--symbols FILE]... [--cdl FILE] [--ca65 FILE.s]`:
+/// lists PRG ROM at its mapped addresses, or exports all of it as ca65 source with a linker config
+/// alongside. A Code/Data Log from a previous run adds the code it saw executed to what tracing finds.
+fn disassemble(mut args: Vec<String>) {
+    let parse_address = |flag: &str, text: String| {
+        u16::from_str_radix(text.trim_start_matches('$').trim_start_matches("0x"), 16)
+            .unwrap_or_else(|_| panic!("{} requires a hex address.", flag))
+    };
+    let bank = take_option(&mut args, "--bank").map(|n| n.parse::<usize>().expect("--bank requires a bank number."));
+    let start = take_option(&mut args, "--start").map_or(0x0000, |a| parse_address("--start", a));
+    let end = take_option(&mut args, "--end").map_or(0xFFFF, |a| parse_address("--end", a));
+    let export_path = take_option(&mut args, "--ca65");
+    let cdl_path = take_option(&mut args, "--cdl");
+    let symbols = load_symbols(&mut args);
+    let bios_path = take_option(&mut args, "--bios");
+    let rom_path = args.pop().expect("Please provide a ROM file path.");
+
+    let cartridge = load_cartridge(&rom_path, bios_path.as_deref()).unwrap_or_else(|e| panic!("{}", e));
+    let mapper = match mapper::create(&cartridge) {
+        Ok(mapper) => Some(mapper),
+        Err(e) => {
+            println!("; Warning: {} Assuming 16KB banks.", e);
+            None
+        }
+    };
+    let mapper = mapper.as_deref();
+
+    let (banks, [nmi, reset, irq]) = match (disasm::prg_banks(&cartridge, mapper), disasm::vectors(&cartridge, mapper)) {
+        (Ok(banks), Ok(vectors)) => (banks, vectors),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    if bank.is_some_and(|bank| bank >= banks.len()) {
+        println!("Error: ROM only has {} PRG bank(s).", banks.len());
+        return;
+    }
+    let logged_code = match cdl_path {
+        Some(path) => match CodeDataLog::open(Path::new(&path), cartridge.prg_rom.len(), cartridge.chr_rom.len()) {
+            Ok(log) => log.code_starts(),
+            Err(e) => {
+                println!("Error: {}", e);
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+    let code = disasm::trace(&banks, &[reset, nmi, irq], &logged_code);
+    if let Some(path) = export_path {
+        let path = Path::new(&path);
+        let name = path.file_stem().map_or("game".into(), |stem| stem.to_string_lossy());
+        let (source, config) = ca65::export(&banks, &code, [nmi, reset, irq], &name);
+        let config_path = path.with_extension("cfg");
+        fs::write(path, source).expect("Failed to write ca65 source.");
+        fs::write(&config_path, config).expect("Failed to write linker config.");
+        println!("Wrote {} and {}.", path.display(), config_path.display());
+        return;
+    }
+    for b in banks.iter().filter(|b| bank.is_none_or(|n| n == b.index)) {
+        disasm::print_bank(&banks, b, &code, &symbols, start, end);
+    }
+
+    println!("; Vectors");
+    println!("; NMI   ${:04X}", nmi);
+    println!("; RESET ${:04X}", reset);
+    println!("; IRQ   ${:04X}", irq);
+}
+
+/// `samnes play <tune.nsf> [--track N] [--seconds S] [--rate HZ] [--out FILE.wav]`: renders a
+/// track of an NSF or NSFe file to a WAV file, by default for as long as the file says the
+/// track lasts, or two minutes.
+fn play(mut args: Vec<String>) {
+    const DEFAULT_SECONDS: f64 = 120.0;
+    let track = take_option(&mut args, "--track").map(|n| n.parse::<u8>().expect("--track requires a track number."));
+    let seconds = take_option(&mut args, "--seconds").map(|s| s.parse::<f64>().expect("--seconds requires a number of seconds."));
+    let sample_rate = take_option(&mut args, "--rate").map_or(44100, |r| r.parse::<u32>().expect("--rate requires a sample rate in Hz."));
+    let out_path = take_option(&mut args, "--out");
+    let options = parse_options(args);
+
+    let bytes = fs::read(&options.rom_path).expect("Failed to read NSF file.");
+    let tune = match nsf::parse(&bytes) {
+        Ok(tune) => tune,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    let region = options.region.or(tune.region).unwrap_or(Region::Ntsc);
+    let chips = tune.chip_names();
+    for (label, value) in [("Title", &tune.title), ("Artist", &tune.artist), ("Copyright", &tune.copyright), ("Ripper", &tune.ripper)] {
+        if !value.is_empty() {
+            println!("{:<10} {}", format!("{}:", label), value);
+        }
+    }
+    println!("Tracks:    {} (starting at {})", tune.songs, tune.start_song);
+    println!("Region:    {}", tune.region.map_or("NTSC or PAL".to_string(), |r| format!("{:?}", r).to_uppercase()));
+    println!("Chips:     {}", if chips.is_empty() { "none".to_string() } else { chips.join(", ") });
+    for n in 1..=tune.songs {
+        let label = tune.track_label(n);
+        let length = tune.track_length(n);
+        if label.is_some() || length.is_some() {
+            let time = length.map_or(String::new(), |(ms, _)| format!(" ({}:{:02})", ms / 60000, ms / 1000 % 60));
+            println!("  {:>3}. {}{}", n, label.unwrap_or(""), time);
+        }
+    }
+
+    let track = track.unwrap_or(tune.start_song.min(tune.songs));
+    if track == 0 || track > tune.songs {
+        println!("Error: Track {} is out of range; the file has {} track(s).", track, tune.songs);
+        return;
+    }
+    let (seconds, fade_seconds) = match (seconds, tune.track_length(track)) {
+        (Some(seconds), _) => (seconds, 0.0),
+        (None, Some((ms, fade_ms))) => ((ms + fade_ms) as f64 / 1000.0, fade_ms as f64 / 1000.0),
+        (None, None) => (DEFAULT_SECONDS, 0.0),
+    };
+    let out_path = out_path.map_or_else(
+        || {
+            let stem = Path::new(&options.rom_path).file_stem().map_or("tune".into(), |stem| stem.to_string_lossy());
+            Path::new(&options.rom_path).with_file_name(format!("{}-{:02}.wav", stem, track))
+        },
+        PathBuf::from,
+    );
+
+    println!("Rendering track {} for {:.1}s on {:?}...", track, seconds, region);
+    let mut player = nsf::Player::new(&tune, track, region, sample_rate);
+    if options.clean_mix {
+        player.set_audio_multiplexing(false);
+    }
+    let mut samples = match player.render((seconds * sample_rate as f64) as usize) {
+        Ok(samples) => samples,
+        Err(e) => {
+            println!("Error: {}", e);
+            return;
+        }
+    };
+    // Fade out over the end, as NSFe times include
+    let fade_len = ((fade_seconds * sample_rate as f64) as usize).min(samples.len());
+    let fade_start = samples.len() - fade_len;
+    for (i, sample) in samples[fade_start..].iter_mut().enumerate() {
+        *sample *= 1.0 - i as f32 / fade_len as f32;
+    }
+    match wav::write(&out_path, sample_rate, &samples) {
+        Ok(()) => println!("Wrote {}.", out_path.display()),
+        Err(e) => println!("Error: Failed to write {}: {}", out_path.display(), e),
+    }
+}
+
+/// Loads every `--symbols <file>` given.
+fn load_symbols(args: &mut Vec<String>) -> Symbols {
+    let mut symbols = Symbols::new();
+    while let Some(path) = take_option(args, "--symbols") {
+        match symbols.load(Path::new(&path)) {
+            Ok(count) => println!("Loaded {} symbols from {}.", count, path),
+            Err(e) => println!("Warning: {}", e),
+        }
+    }
+    symbols
+}
+
+fn write_save_state(options: &Options, console: &Console) {
+    if let Some(path) = &options.save_state {
+        fs::write(path, console.save_state()).expect("Failed to write save state.");
+        println!("Saved state to {}.", path);
+    }
+}
+
+fn write_code_data_log(options: &Options, console: &Console) {
+    if let (Some(path), Some(log)) = (&options.cdl, console.code_data_log()) {
+        match log.save(Path::new(path)) {
+            Ok(()) => println!("Saved Code/Data Log to {}.", path),
+            Err(e) => println!("Warning: {}", e),
+        }
+    }
+}
+
+fn flush_battery(battery: &mut Option<BatteryFile>, console: &Console) {
+    if let Some(battery) = battery
+        && let Err(e) = battery.flush(&*console.bus.mapper)
+    {
+        println!("Warning: Failed to write {}: {}", battery.path().display(), e);
+    }
+}
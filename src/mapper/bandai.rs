@@ -0,0 +1,257 @@
+use super::eeprom::{Eeprom, Model};
+use super::{Mapper, read_mirroring, write_mirroring};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Bandai's FCG-1/FCG-2 and LZ93D50 boards (mappers 16, 153 and 159): eight 1KB CHR
+/// banks, a 16KB PRG bank at $8000 with the last fixed at $C000, and a 16-bit CPU cycle
+/// IRQ counter.
+///
+/// The FCG chips decode their registers at $6000-$7FFF and load the counter directly;
+/// the LZ93D50 decodes them at $8000-$FFFF and loads a latch that's copied into the
+/// counter when the IRQ is enabled. Without a submapper both are decoded and both
+/// loaded, which suits either. Mapper 16 LZ93D50 boards save to a 24C02 EEPROM, mapper
+/// 159 to a 24C01; mapper 153 has 8KB of battery SRAM instead and uses bit 0 of the CHR
+/// registers to pick a 256KB half of its PRG ROM.
+pub struct Bandai {
+    fcg_registers: bool,     // Registers at $6000-$7FFF, loading the counter
+    lz93d50_registers: bool, // Registers at $8000-$FFFF, loading the latch
+    outer_prg: bool,         // Mapper 153
+
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // Mapper 153 only
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    eeprom: Option<Eeprom>,
+    battery: bool,
+
+    chr_banks: [u8; 8],   // $x0-$x7
+    prg_bank: u8,         // $x8
+    mirroring: Mirroring, // $x9
+    ram_enable: bool,     // $xD bit 5 on mapper 153
+    irq_enabled: bool,    // $xA
+    irq_latch: u16,       // $xB-$xC
+    irq_counter: u16,
+    irq_pending: bool,
+}
+
+impl Bandai {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let (fcg_registers, lz93d50_registers) = match (cartridge.mapper, cartridge.submapper) {
+            (16, 4) => (true, false),
+            (16, 0) => (true, true),
+            _ => (false, true),
+        };
+        let eeprom = match (cartridge.mapper, cartridge.submapper) {
+            (159, _) => Some(Eeprom::new(Model::X24C01)),
+            (16, 4) => None,
+            (16, _) => Some(Eeprom::new(Model::X24C02)),
+            _ => None,
+        };
+        let outer_prg = cartridge.mapper == 153;
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Bandai {
+            fcg_registers,
+            lz93d50_registers,
+            outer_prg,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: if outer_prg { vec![0; 0x2000] } else { Vec::new() },
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            eeprom,
+            battery: cartridge.battery,
+            chr_banks: [0; 8],
+            prg_bank: 0,
+            mirroring: cartridge.mirroring,
+            ram_enable: false,
+            irq_enabled: false,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x4000;
+        let outer = if self.outer_prg { (self.chr_banks.iter().fold(0, |acc, b| acc | b) & 0x01) << 4 } else { 0 };
+        let bank = match addr {
+            0x8000..=0xBFFF => outer | (self.prg_bank & 0x0F),
+            _ if self.outer_prg => outer | 0x0F,
+            _ => (bank_count - 1) as u8,
+        };
+        (bank as usize % bank_count) * 0x4000 + (addr & 0x3FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        // Mapper 153's CHR RAM isn't banked; its CHR registers only hold the outer PRG bank
+        if self.outer_prg {
+            return (addr & 0x1FFF) as usize % self.chr.len();
+        }
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+
+    fn write_register(&mut self, register: u16, data: u8) {
+        match register {
+            0x0..=0x7 => self.chr_banks[register as usize] = data,
+            0x8 => self.prg_bank = data,
+            0x9 => {
+                self.mirroring = match data & 0x03 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0xA => {
+                self.irq_enabled = (data & 0x01) != 0;
+                self.irq_pending = false;
+                if self.lz93d50_registers {
+                    self.irq_counter = self.irq_latch;
+                }
+            }
+            0xB => {
+                self.irq_latch = (self.irq_latch & 0xFF00) | data as u16;
+                if self.fcg_registers {
+                    self.irq_counter = (self.irq_counter & 0xFF00) | data as u16;
+                }
+            }
+            0xC => {
+                self.irq_latch = (self.irq_latch & 0x00FF) | ((data as u16) << 8);
+                if self.fcg_registers {
+                    self.irq_counter = (self.irq_counter & 0x00FF) | ((data as u16) << 8);
+                }
+            }
+            0xD => {
+                self.ram_enable = (data & 0x20) != 0;
+                if let Some(eeprom) = &mut self.eeprom {
+                    eeprom.write_lines((data & 0x20) != 0, (data & 0x40) != 0);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Bandai {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.outer_prg && self.ram_enable => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x6000..=0x7FFF => match &self.eeprom {
+                Some(eeprom) => (eeprom.output() as u8) << 4,
+                None => 0,
+            },
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.outer_prg && self.ram_enable => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x6000..=0x7FFF if self.fcg_registers => self.write_register(addr & 0x0F, data),
+            0x8000..=0xFFFF if self.lz93d50_registers => self.write_register(addr & 0x0F, data),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_tick(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = self.irq_counter.wrapping_sub(1);
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        // Mapper 153 fixes the last bank of each 256KB half
+        let last = if self.outer_prg { bank % 16 == 15 } else { bank + 1 == bank_count };
+        if last { 0xC000 } else { 0x8000 }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if !self.battery {
+            return None;
+        }
+        match &self.eeprom {
+            Some(eeprom) => Some(eeprom.data()),
+            None if !self.prg_ram.is_empty() => Some(&self.prg_ram),
+            None => None,
+        }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load_data(data);
+        } else {
+            let len = data.len().min(self.prg_ram.len());
+            self.prg_ram[..len].copy_from_slice(&data[..len]);
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        if let Some(eeprom) = &self.eeprom {
+            eeprom.save_state(w);
+        }
+        w.write_bytes(&self.chr_banks);
+        w.write_u8(self.prg_bank);
+        write_mirroring(w, self.mirroring);
+        w.write_bool(self.ram_enable);
+        w.write_bool(self.irq_enabled);
+        w.write_u16(self.irq_latch);
+        w.write_u16(self.irq_counter);
+        w.write_bool(self.irq_pending);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        if let Some(eeprom) = &mut self.eeprom {
+            eeprom.load_state(r)?;
+        }
+        r.read_bytes(&mut self.chr_banks)?;
+        self.prg_bank = r.read_u8()?;
+        self.mirroring = read_mirroring(r)?;
+        self.ram_enable = r.read_bool()?;
+        self.irq_enabled = r.read_bool()?;
+        self.irq_latch = r.read_u16()?;
+        self.irq_counter = r.read_u16()?;
+        self.irq_pending = r.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use super::{Mapper, check_submapper, read_mirroring, write_mirroring};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Board {
+    Gxrom,       // Mapper 66: 32KB PRG and 8KB CHR from one latch
+    ColorDreams, // Mapper 11: the same, with the fields the other way round
+    Bnrom,       // Mapper 34, submapper 2: 32KB PRG, CHR RAM
+    Nina001,     // Mapper 34, submapper 1: 32KB PRG and two 4KB CHR banks, registers at $7FFD-$7FFF
+    Camerica,    // Mapper 71: 16KB PRG; Fire Hawk (submapper 1) also picks single-screen mirroring
+    Irem78,      // Mapper 78: 16KB PRG and 8KB CHR with switchable mirroring
+    Jaleco87,    // Mapper 87: fixed PRG, 8KB CHR with the two bank bits swapped
+}
+
+/// Boards built from a latch or two of discrete logic. They all reduce to two 16KB PRG
+/// windows, two 4KB CHR windows and the mirroring, which each board's register sets in
+/// its own way.
+pub struct Discrete {
+    board: Board,
+    bus_conflicts: bool, // Writes to ROM are ANDed with the byte the ROM drives onto the bus
+    holy_diver: bool,    // Mapper 78: horizontal/vertical mirroring instead of single-screen
+    fire_hawk: bool,     // Mapper 71, submapper 1: mirroring control at $9000-$9FFF
+
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // NINA-001 only
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 2], // 16KB units at $8000 and $C000
+    chr_banks: [u8; 2], // 4KB units at $0000 and $1000
+    mirroring: Mirroring,
+}
+
+impl Discrete {
+    pub fn new(cartridge: &Cartridge) -> Result<Self, String> {
+        let known: &[u8] = match cartridge.mapper {
+            34 => &[1, 2],
+            71 => &[1],
+            78 => &[1, 3],
+            _ => &[],
+        };
+        check_submapper(cartridge, known)?;
+        let board = match (cartridge.mapper, cartridge.submapper) {
+            (11, _) => Board::ColorDreams,
+            (34, 1) => Board::Nina001,
+            (34, 2) => Board::Bnrom,
+            // Without a submapper, only NINA-001 has CHR ROM to switch
+            (34, _) if cartridge.chr_rom.len() > 0x2000 => Board::Nina001,
+            (34, _) => Board::Bnrom,
+            (71, _) => Board::Camerica,
+            (78, _) => Board::Irem78,
+            (87, _) => Board::Jaleco87,
+            (_, _) => Board::Gxrom,
+        };
+        // Holy Diver is submapper 3; older dumps mark it with the four-screen bit
+        let holy_diver = board == Board::Irem78
+            && (cartridge.submapper == 3 || cartridge.mirroring == Mirroring::FourScreen);
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        let last_bank = (cartridge.prg_rom.len() / 0x4000).saturating_sub(1) as u8;
+        let fixed_last = matches!(board, Board::Camerica | Board::Irem78);
+        Ok(Discrete {
+            board,
+            bus_conflicts: matches!(board, Board::Gxrom | Board::ColorDreams | Board::Bnrom),
+            holy_diver,
+            fire_hawk: board == Board::Camerica && cartridge.submapper == 1,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: if board == Board::Nina001 { vec![0; 0x2000] } else { Vec::new() },
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: if fixed_last { [0, last_bank] } else { [0, 1] },
+            chr_banks: [0, 1],
+            mirroring: if cartridge.mirroring == Mirroring::FourScreen {
+                Mirroring::Horizontal
+            } else {
+                cartridge.mirroring
+            },
+        })
+    }
+
+    fn select_prg_32k(&mut self, bank: u8) {
+        self.prg_banks = [bank.wrapping_mul(2), bank.wrapping_mul(2).wrapping_add(1)];
+    }
+
+    fn select_chr_8k(&mut self, bank: u8) {
+        self.chr_banks = [bank.wrapping_mul(2), bank.wrapping_mul(2).wrapping_add(1)];
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x4000;
+        let bank = self.prg_banks[(addr >> 14) as usize & 1] as usize;
+        (bank % bank_count) * 0x4000 + (addr & 0x3FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 12) as usize & 1] as usize;
+        (bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Discrete {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let data = if self.bus_conflicts && addr >= 0x8000 { data & self.cpu_peek(addr) } else { data };
+        if (0x6000..=0x7FFF).contains(&addr) && !self.prg_ram.is_empty() {
+            self.prg_ram[(addr & 0x1FFF) as usize] = data;
+        }
+        match (self.board, addr) {
+            (Board::Gxrom, 0x8000..=0xFFFF) => {
+                self.select_prg_32k((data >> 4) & 0x03);
+                self.select_chr_8k(data & 0x03);
+            }
+            (Board::ColorDreams, 0x8000..=0xFFFF) => {
+                self.select_prg_32k(data & 0x03);
+                self.select_chr_8k(data >> 4);
+            }
+            (Board::Bnrom, 0x8000..=0xFFFF) => self.select_prg_32k(data),
+            (Board::Nina001, 0x7FFD) => self.select_prg_32k(data & 0x01),
+            (Board::Nina001, 0x7FFE) => self.chr_banks[0] = data & 0x0F,
+            (Board::Nina001, 0x7FFF) => self.chr_banks[1] = data & 0x0F,
+            (Board::Camerica, 0x9000..=0x9FFF) if self.fire_hawk => {
+                self.mirroring = if (data & 0x10) == 0 { Mirroring::SingleScreenLower } else { Mirroring::SingleScreenUpper };
+            }
+            (Board::Camerica, 0xC000..=0xFFFF) => self.prg_banks[0] = data,
+            (Board::Irem78, 0x8000..=0xFFFF) => {
+                self.prg_banks[0] = data & 0x07;
+                self.select_chr_8k(data >> 4);
+                let bit = (data & 0x08) != 0;
+                self.mirroring = match (self.holy_diver, bit) {
+                    (true, false) => Mirroring::Horizontal,
+                    (true, true) => Mirroring::Vertical,
+                    (false, false) => Mirroring::SingleScreenLower,
+                    (false, true) => Mirroring::SingleScreenUpper,
+                };
+            }
+            (Board::Jaleco87, 0x6000..=0x7FFF) => self.select_chr_8k(((data & 0x01) << 1) | ((data >> 1) & 0x01)),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        match self.board {
+            Board::Camerica | Board::Irem78 | Board::Jaleco87 => 0x4000,
+            _ => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery && !self.prg_ram.is_empty() { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_banks);
+        write_mirroring(w, self.mirroring);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_banks)?;
+        self.mirroring = read_mirroring(r)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Model {
+    X24C01, // 128 bytes, LSB first, addressed directly after the start condition
+    X24C02, // 256 bytes, MSB first, behind a 1010xxxR device byte
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    Idle,
+    Device,
+    Address,
+    Write,
+    Read,
+}
+
+/// Serial EEPROM on an I2C-style two-wire bus, bit-banged by the CPU through a mapper
+/// register. SDA falling while SCL is high starts a transfer and SDA rising stops it.
+/// Bits are sampled as SCL rises; the chip drives its acknowledge and data bits as SCL
+/// falls, and a master that doesn't acknowledge a read byte ends the read.
+pub struct Eeprom {
+    model: Model,
+    data: Vec<u8>,
+
+    phase: Phase,
+    scl: bool,
+    sda: bool,
+    shift: u8,
+    bit: u8,      // SCL rises seen in this byte: 8 data bits, then the acknowledge
+    address: u8,
+    output: bool, // Level the chip drives onto SDA; high is released
+}
+
+impl Eeprom {
+    pub fn new(model: Model) -> Self {
+        Eeprom {
+            model,
+            data: vec![0; if model == Model::X24C01 { 128 } else { 256 }],
+            phase: Phase::Idle,
+            scl: false,
+            sda: false,
+            shift: 0,
+            bit: 0,
+            address: 0,
+            output: true,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.data.len());
+        self.data[..len].copy_from_slice(&data[..len]);
+    }
+
+    pub fn output(&self) -> bool {
+        self.output
+    }
+
+    /// Takes the levels the CPU just wrote to the clock and data lines.
+    pub fn write_lines(&mut self, scl: bool, sda: bool) {
+        if self.scl && scl && sda != self.sda {
+            if sda {
+                self.phase = Phase::Idle;
+            } else {
+                self.phase = if self.model == Model::X24C01 { Phase::Address } else { Phase::Device };
+                self.bit = 0;
+                self.shift = 0;
+            }
+            self.output = true;
+        } else if !self.scl && scl {
+            self.clock_rise(sda);
+        } else if self.scl && !scl {
+            self.clock_fall();
+        }
+        self.scl = scl;
+        self.sda = sda;
+    }
+
+    fn clock_rise(&mut self, sda: bool) {
+        match self.phase {
+            Phase::Idle => return,
+            // The master acknowledges each byte it reads; letting SDA float high ends the read.
+            // While the chip is driving its own acknowledge it doesn't listen.
+            Phase::Read if self.bit == 8 && self.output && sda => {
+                self.phase = Phase::Idle;
+                return;
+            }
+            Phase::Read => {}
+            _ if self.bit < 8 => {
+                self.shift = match self.model {
+                    Model::X24C01 => (self.shift >> 1) | ((sda as u8) << 7),
+                    Model::X24C02 => (self.shift << 1) | sda as u8,
+                };
+            }
+            _ => {}
+        }
+        self.bit += 1;
+    }
+
+    fn clock_fall(&mut self) {
+        match self.bit {
+            _ if self.phase == Phase::Idle => {}
+            8 if self.phase == Phase::Read => {
+                self.output = true;
+                self.address = self.address.wrapping_add(1) & self.address_mask();
+            }
+            8 => self.output = !self.receive(),
+            9 => {
+                self.bit = 0;
+                self.shift = 0;
+                self.output = self.phase != Phase::Read || self.data_bit(0);
+            }
+            1..=7 if self.phase == Phase::Read => self.output = self.data_bit(self.bit),
+            _ => {}
+        }
+    }
+
+    /// Acts on a received byte, and returns whether the chip acknowledges it.
+    fn receive(&mut self) -> bool {
+        let byte = self.shift;
+        match (self.phase, self.model) {
+            (Phase::Device, _) if (byte & 0xF0) != 0xA0 => {
+                self.phase = Phase::Idle;
+                return false;
+            }
+            (Phase::Device, _) => {
+                self.phase = if (byte & 0x01) != 0 { Phase::Read } else { Phase::Address };
+            }
+            (Phase::Address, Model::X24C01) => {
+                self.address = byte & 0x7F;
+                self.phase = if (byte & 0x80) != 0 { Phase::Read } else { Phase::Write };
+            }
+            (Phase::Address, Model::X24C02) => {
+                self.address = byte;
+                self.phase = Phase::Write;
+            }
+            (Phase::Write, _) => {
+                self.data[self.address as usize] = byte;
+                self.address = self.address.wrapping_add(1) & self.address_mask();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn data_bit(&self, bit: u8) -> bool {
+        let byte = self.data[self.address as usize];
+        match self.model {
+            Model::X24C01 => (byte >> bit) & 0x01 != 0,
+            Model::X24C02 => (byte >> (7 - bit)) & 0x01 != 0,
+        }
+    }
+
+    fn address_mask(&self) -> u8 {
+        (self.data.len() - 1) as u8
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.data);
+        w.write_u8(self.phase as u8);
+        w.write_bool(self.scl);
+        w.write_bool(self.sda);
+        w.write_u8(self.shift);
+        w.write_u8(self.bit);
+        w.write_u8(self.address);
+        w.write_bool(self.output);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.data)?;
+        self.phase = match r.read_u8()? {
+            0 => Phase::Idle,
+            1 => Phase::Device,
+            2 => Phase::Address,
+            3 => Phase::Write,
+            4 => Phase::Read,
+            _ => return Err(StateError::Invalid("EEPROM phase")),
+        };
+        self.scl = r.read_bool()?;
+        self.sda = r.read_bool()?;
+        self.shift = r.read_u8()?;
+        self.bit = r.read_u8()?.min(9);
+        self.address = r.read_u8()? & self.address_mask();
+        self.output = r.read_bool()?;
+        Ok(())
+    }
+}
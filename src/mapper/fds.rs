@@ -0,0 +1,490 @@
+use super::Mapper;
+use super::fds_audio::FdsAudio;
+use crate::cartridge::{Cartridge, FDS_SIDE_SIZE, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// Track layout around the blocks an .fds image stores back to back: a long lead-in gap
+// before the first block, a gap after each block, a $80 mark before each, and two CRC
+// bytes after. CRCs aren't emulated (reads always pass the check), so placeholder bytes
+// stand in for them.
+const LEAD_IN_GAP: usize = 28300 / 8;
+const BLOCK_GAP: usize = 976 / 8;
+const BLOCK_MARK: u8 = 0x80;
+const CRC_PLACEHOLDER: [u8; 2] = [0x4D, 0x62];
+
+// The drive moves a byte every 150 CPU cycles (about 96kbit/s), and takes a while to
+// bring the head back to the start of the track.
+const BYTE_CYCLES: u32 = 150;
+const REWIND_CYCLES: u32 = 50000;
+
+// $4025
+const CONTROL_MOTOR: u8 = 0x01;
+const CONTROL_TRANSFER_RESET: u8 = 0x02;
+const CONTROL_READ: u8 = 0x04;
+const CONTROL_HORIZONTAL: u8 = 0x08;
+const CONTROL_CRC: u8 = 0x10;
+const CONTROL_READY: u8 = 0x40; // Past the gap: start reading or writing data
+const CONTROL_IRQ: u8 = 0x80;
+
+/// The Famicom Disk System: the RAM adapter's 32KB of PRG RAM, 8KB of CHR RAM, the BIOS
+/// at $E000, its timer IRQ and sound channel, and the disk drive.
+///
+/// Each disk side is expanded into a track of gaps and marked blocks that the drive
+/// streams a byte at a time, as the BIOS expects. Writes land on the track and are
+/// folded back into an .fds layout image, which `save_data` hands out once anything
+/// has been written, so the original file is never touched.
+pub struct Fds {
+    bios: Vec<u8>,
+    ram: Vec<u8>,
+    chr: Vec<u8>,
+
+    image: Vec<u8>,       // Every side in .fds layout, as last written back
+    tracks: Vec<Vec<u8>>, // Every side as the drive sees it
+    side: Option<usize>,  // None when ejected
+    modified: bool,       // The image differs from the file it came from
+    tracks_written: bool, // The tracks hold writes not yet folded into the image
+
+    irq_reload: u16,     // $4020-$4021
+    irq_counter: u16,
+    irq_repeat: bool,    // $4022 bit 0
+    irq_enabled: bool,   // $4022 bit 1
+    timer_irq: bool,
+    disk_enabled: bool,  // $4023 bit 0
+    sound_enabled: bool, // $4023 bit 1
+    write_data: u8,      // $4024
+    control: u8,         // $4025
+    external: u8,        // $4026
+
+    read_data: u8,       // $4031
+    transfer_done: bool, // $4030 bit 1: a byte was read or written
+    disk_irq: bool,
+    position: usize,
+    delay: u32,
+    scanning: bool,      // The head is moving over the track
+    end_of_head: bool,   // The head has to go back to the start
+    gap_ended: bool,     // Reading has found the mark after a gap
+
+    audio: FdsAudio,
+}
+
+impl Fds {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let image: Vec<u8> = cartridge.disk_sides.concat();
+        Fds {
+            bios: cartridge.prg_rom.clone(),
+            ram: vec![0; 0x8000],
+            chr: vec![0; 0x2000],
+            tracks: cartridge.disk_sides.iter().map(|side| build_track(side)).collect(),
+            image,
+            side: if cartridge.disk_sides.is_empty() { None } else { Some(0) },
+            modified: false,
+            tracks_written: false,
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_repeat: false,
+            irq_enabled: false,
+            timer_irq: false,
+            disk_enabled: false,
+            sound_enabled: false,
+            write_data: 0,
+            control: 0,
+            external: 0,
+            read_data: 0,
+            transfer_done: false,
+            disk_irq: false,
+            position: 0,
+            delay: 0,
+            scanning: false,
+            end_of_head: true,
+            gap_ended: false,
+            audio: FdsAudio::new(),
+        }
+    }
+
+    fn status(&self) -> u8 {
+        self.timer_irq as u8 | ((self.transfer_done as u8) << 1)
+    }
+
+    fn drive_status(&self) -> u8 {
+        let ejected = self.side.is_none();
+        // Bit 0: no disk, bit 1: not ready, bit 2: write protected (or no disk)
+        0x40 | ejected as u8 | (((ejected || !self.scanning) as u8) << 1) | ((ejected as u8) << 2)
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.irq_enabled {
+            return;
+        }
+        if self.irq_counter == 0 {
+            self.timer_irq = true;
+            self.irq_counter = self.irq_reload;
+            self.irq_enabled = self.irq_repeat;
+        } else {
+            self.irq_counter -= 1;
+        }
+    }
+
+    fn clock_drive(&mut self) {
+        let Some(side) = self.side else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if (self.control & CONTROL_MOTOR) == 0 {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if (self.control & CONTROL_TRANSFER_RESET) != 0 && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.delay = REWIND_CYCLES;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        self.transfer_byte(side);
+        self.position += 1;
+        if self.position >= self.tracks[side].len() {
+            // Off the end of the track: the head is parked back at the start and the
+            // drive stops until the motor is restarted
+            self.control &= !CONTROL_MOTOR;
+            self.end_of_head = true;
+            self.scanning = false;
+            self.position = 0;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+
+    fn transfer_byte(&mut self, side: usize) {
+        let ready = (self.control & CONTROL_READY) != 0;
+        let mut irq = (self.control & CONTROL_IRQ) != 0;
+        if (self.control & CONTROL_READ) != 0 {
+            let data = self.tracks[side][self.position];
+            if !ready {
+                self.gap_ended = false;
+            } else if data != 0 && !self.gap_ended {
+                // The block mark ends the gap; it comes through without an IRQ
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.read_data = data;
+                self.transfer_done = true;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let data = if (self.control & CONTROL_CRC) != 0 {
+                0 // CRC, which nothing checks
+            } else {
+                self.transfer_done = true;
+                self.disk_irq |= irq;
+                self.write_data
+            };
+            // Until the data starts, the drive writes gap
+            self.tracks[side][self.position] = if ready { data } else { 0 };
+            self.tracks_written = true;
+            self.gap_ended = false;
+        }
+    }
+
+    /// Folds writes on the tracks back into the image.
+    fn write_back(&mut self) {
+        if !self.tracks_written {
+            return;
+        }
+        for (track, side) in self.tracks.iter().zip(self.image.chunks_mut(FDS_SIDE_SIZE)) {
+            read_track(track, side);
+        }
+        self.tracks_written = false;
+        self.modified = true;
+    }
+}
+
+/// Length of a block from its type byte, or None past the last block. File data blocks
+/// take their size from the file header block before them.
+fn block_length(block_type: u8, file_size: usize) -> Option<usize> {
+    match block_type {
+        1 => Some(56), // Disk info
+        2 => Some(2),  // File count
+        3 => Some(16), // File header
+        4 => Some(1 + file_size),
+        _ => None,
+    }
+}
+
+fn header_file_size(header: &[u8]) -> usize {
+    u16::from_le_bytes([header[13], header[14]]) as usize
+}
+
+/// Lays a side's blocks out on a track, leaving its unused space as blank track
+/// for files the game adds.
+fn build_track(side: &[u8]) -> Vec<u8> {
+    let mut track = vec![0; LEAD_IN_GAP];
+    let mut offset = 0;
+    let mut file_size = 0;
+    while let Some(len) = side.get(offset).and_then(|&t| block_length(t, file_size)) {
+        let Some(block) = side.get(offset..offset + len) else { break };
+        if block[0] == 3 {
+            file_size = header_file_size(block);
+        }
+        track.push(BLOCK_MARK);
+        track.extend_from_slice(block);
+        track.extend_from_slice(&CRC_PLACEHOLDER);
+        track.resize(track.len() + BLOCK_GAP, 0);
+        offset += len;
+    }
+    track.resize(track.len() + (side.len() - offset), 0);
+    track
+}
+
+/// Collects the blocks on a track back into a side in .fds layout.
+fn read_track(track: &[u8], side: &mut [u8]) {
+    side.fill(0);
+    let mut offset = 0;
+    let mut file_size = 0;
+    let mut pos = 1;
+    while pos < track.len() {
+        // A block starts with a mark right after gap
+        if track[pos] != BLOCK_MARK || track[pos - 1] != 0 {
+            pos += 1;
+            continue;
+        }
+        let Some(len) = track.get(pos + 1).and_then(|&t| block_length(t, file_size)) else { break };
+        let (Some(block), Some(out)) = (track.get(pos + 1..pos + 1 + len), side.get_mut(offset..offset + len)) else {
+            break;
+        };
+        out.copy_from_slice(block);
+        if block[0] == 3 {
+            file_size = header_file_size(block);
+        }
+        offset += len;
+        pos += 1 + len + CRC_PLACEHOLDER.len();
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let data = self.cpu_peek(addr);
+        match addr {
+            0x4030 => {
+                self.timer_irq = false;
+                self.transfer_done = false;
+                self.disk_irq = false;
+            }
+            0x4031 => {
+                self.transfer_done = false;
+                self.disk_irq = false;
+            }
+            _ => {}
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4030 => self.status(),
+            0x4031 => self.read_data,
+            0x4032 => self.drive_status(),
+            0x4033 => 0x80, // Battery good
+            0x4040..=0x407F | 0x4090 | 0x4092 => self.audio.read(addr),
+            0x6000..=0xDFFF => self.ram[addr as usize - 0x6000],
+            0xE000..=0xFFFF => self.bios[(addr & 0x1FFF) as usize],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4020 => self.irq_reload = (self.irq_reload & 0xFF00) | data as u16,
+            0x4021 => self.irq_reload = (self.irq_reload & 0x00FF) | ((data as u16) << 8),
+            0x4022 => {
+                self.irq_repeat = (data & 0x01) != 0;
+                self.irq_enabled = (data & 0x02) != 0 && self.disk_enabled;
+                if self.irq_enabled {
+                    self.irq_counter = self.irq_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_enabled = (data & 0x01) != 0;
+                self.sound_enabled = (data & 0x02) != 0;
+                if !self.disk_enabled {
+                    self.irq_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024..=0x4026 if !self.disk_enabled => {}
+            0x4024 => {
+                self.write_data = data;
+                self.transfer_done = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.control = data;
+                self.disk_irq = false;
+                if (data & CONTROL_READ) != 0 || (data & CONTROL_MOTOR) == 0 {
+                    self.write_back();
+                }
+            }
+            0x4026 => self.external = data,
+            0x4040..=0x408A if self.sound_enabled => self.audio.write(addr, data),
+            0x6000..=0xDFFF => self.ram[addr as usize - 0x6000] = data,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[(addr & 0x1FFF) as usize]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        self.chr[(addr & 0x1FFF) as usize] = data;
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if (self.control & CONTROL_HORIZONTAL) != 0 { Mirroring::Horizontal } else { Mirroring::Vertical }
+    }
+
+    fn cpu_tick(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn disk_sides(&self) -> usize {
+        self.tracks.len()
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.side
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.write_back();
+        self.side = side.filter(|&side| side < self.tracks.len());
+        self.scanning = false;
+        self.end_of_head = true;
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0xE000 { Some((addr & 0x1FFF) as usize) } else { None }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.modified { Some(&self.image) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        if data.len() != self.image.len() {
+            return;
+        }
+        self.image.copy_from_slice(data);
+        self.tracks = self.image.chunks(FDS_SIDE_SIZE).map(build_track).collect();
+        self.modified = true;
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.ram);
+        w.write_bytes(&self.chr);
+        for track in &self.tracks {
+            w.write_u32(track.len() as u32);
+            w.write_bytes(track);
+        }
+        w.write_u8(self.side.map_or(0xFF, |side| side as u8));
+        w.write_bool(self.modified);
+        w.write_bool(self.tracks_written);
+        w.write_u16(self.irq_reload);
+        w.write_u16(self.irq_counter);
+        w.write_bool(self.irq_repeat);
+        w.write_bool(self.irq_enabled);
+        w.write_bool(self.timer_irq);
+        w.write_bool(self.disk_enabled);
+        w.write_bool(self.sound_enabled);
+        w.write_u8(self.write_data);
+        w.write_u8(self.control);
+        w.write_u8(self.external);
+        w.write_u8(self.read_data);
+        w.write_bool(self.transfer_done);
+        w.write_bool(self.disk_irq);
+        w.write_u32(self.position as u32);
+        w.write_u32(self.delay);
+        w.write_bool(self.scanning);
+        w.write_bool(self.end_of_head);
+        w.write_bool(self.gap_ended);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.ram)?;
+        r.read_bytes(&mut self.chr)?;
+        for track in &mut self.tracks {
+            let len = r.read_u32()? as usize;
+            // Tracks only change length when a save file lays a side out differently;
+            // this just bounds what a corrupt state can make us allocate
+            if len > FDS_SIDE_SIZE * 16 {
+                return Err(StateError::Invalid("FDS track length"));
+            }
+            track.resize(len, 0);
+            r.read_bytes(track)?;
+        }
+        self.side = match r.read_u8()? {
+            0xFF => None,
+            side if (side as usize) < self.tracks.len() => Some(side as usize),
+            _ => return Err(StateError::Invalid("FDS disk side")),
+        };
+        self.modified = r.read_bool()?;
+        self.tracks_written = r.read_bool()?;
+        self.irq_reload = r.read_u16()?;
+        self.irq_counter = r.read_u16()?;
+        self.irq_repeat = r.read_bool()?;
+        self.irq_enabled = r.read_bool()?;
+        self.timer_irq = r.read_bool()?;
+        self.disk_enabled = r.read_bool()?;
+        self.sound_enabled = r.read_bool()?;
+        self.write_data = r.read_u8()?;
+        self.control = r.read_u8()?;
+        self.external = r.read_u8()?;
+        self.read_data = r.read_u8()?;
+        self.transfer_done = r.read_bool()?;
+        self.disk_irq = r.read_bool()?;
+        self.position = r.read_u32()? as usize;
+        if self.side.is_some_and(|side| self.position >= self.tracks[side].len()) {
+            return Err(StateError::Invalid("FDS head position"));
+        }
+        self.delay = r.read_u32()?;
+        self.scanning = r.read_bool()?;
+        self.end_of_head = r.read_bool()?;
+        self.gap_ended = r.read_bool()?;
+        self.audio.load_state(r)?;
+        // Bring the image in line with the restored tracks
+        if self.modified {
+            self.tracks_written = true;
+            self.write_back();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+use crate::apu;
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// Master volume ($4089 bits 0-1: 2/2, 2/3, 2/4, 2/5), scaled so that the loudest
+// wave sample at full gain comes out at 63
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14];
+
+// Modulation table entries: how far each step moves the sweep counter (4 resets it)
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+
+/// Volume or sweep envelope: a gain that ramps one step per period, or is set directly.
+#[derive(Default)]
+struct Envelope {
+    speed: u8, // Bits 0-5; the gain itself when the envelope is off
+    increase: bool,
+    off: bool,
+    gain: u8,
+    timer: u32,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8, master_speed: u8) {
+        self.speed = data & 0x3F;
+        self.increase = (data & 0x40) != 0;
+        self.off = (data & 0x80) != 0;
+        if self.off {
+            self.gain = self.speed;
+        }
+        self.reset_timer(master_speed);
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (self.speed as u32 + 1) * master_speed as u32;
+    }
+
+    /// Counts down a CPU cycle. Returns whether the gain stepped.
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.off || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < 32 {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.speed);
+        w.write_bool(self.increase);
+        w.write_bool(self.off);
+        w.write_u8(self.gain);
+        w.write_u32(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.speed = r.read_u8()? & 0x3F;
+        self.increase = r.read_bool()?;
+        self.off = r.read_bool()?;
+        self.gain = r.read_u8()? & 0x3F;
+        self.timer = r.read_u32()?;
+        Ok(())
+    }
+}
+
+/// The RAM adapter's sound channel: a 64-step, 6-bit wavetable whose pitch is swept by
+/// a second, 64-step modulation table, each with its own gain envelope. Both phase
+/// accumulators are 16 bits and step their table when they overflow.
+pub struct FdsAudio {
+    wave: [u8; 64],       // $4040-$407F
+    wave_write: bool,     // $4089 bit 7: the table is writable, and playback holds
+    wave_position: u8,
+    wave_accumulator: u16,
+    frequency: u16,       // $4082-$4083
+    wave_halt: bool,      // $4083 bit 7
+    envelopes_halt: bool, // $4083 bit 6
+    master_volume: u8,    // $4089 bits 0-1
+    master_speed: u8,     // $408A
+    volume: Envelope,     // $4080
+    output: u8,
+
+    sweep: Envelope,     // $4084
+    mod_counter: i8,     // $4085, 7-bit signed
+    mod_frequency: u16,  // $4086-$4087
+    mod_halt: bool,      // $4087 bit 7
+    mod_table: [u8; 64], // $4088, each entry written twice
+    mod_position: u8,
+    mod_accumulator: u16,
+    mod_pitch: i32,      // Frequency offset from the sweep
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        FdsAudio {
+            wave: [0; 64],
+            wave_write: false,
+            wave_position: 0,
+            wave_accumulator: 0,
+            frequency: 0,
+            wave_halt: true,
+            envelopes_halt: false,
+            master_volume: 0,
+            master_speed: 0xE8,
+            volume: Envelope::default(),
+            output: 0,
+            sweep: Envelope::default(),
+            mod_counter: 0,
+            mod_frequency: 0,
+            mod_halt: true,
+            mod_table: [0; 64],
+            mod_position: 0,
+            mod_accumulator: 0,
+            mod_pitch: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave[(addr & 0x3F) as usize] | 0x40,
+            0x4090 => self.volume.gain | 0x40,
+            0x4092 => self.sweep.gain | 0x40,
+            _ => 0x40,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => self.wave[(addr & 0x3F) as usize] = data & 0x3F,
+            0x4080 => self.volume.write(data, self.master_speed),
+            0x4082 => self.frequency = (self.frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.frequency = (self.frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.wave_halt = (data & 0x80) != 0;
+                self.envelopes_halt = (data & 0x40) != 0;
+                if self.wave_halt {
+                    self.wave_position = 0;
+                    self.wave_accumulator = 0;
+                }
+                if self.envelopes_halt {
+                    self.volume.reset_timer(self.master_speed);
+                    self.sweep.reset_timer(self.master_speed);
+                }
+            }
+            0x4084 => self.sweep.write(data, self.master_speed),
+            0x4085 => {
+                self.mod_counter = sign_extend_7(data);
+                self.update_mod_pitch();
+            }
+            0x4086 => self.mod_frequency = (self.mod_frequency & 0x0F00) | data as u16,
+            0x4087 => {
+                self.mod_frequency = (self.mod_frequency & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.mod_halt = (data & 0x80) != 0;
+                if self.mod_halt {
+                    self.mod_accumulator = 0;
+                }
+            }
+            0x4088 if self.mod_halt => {
+                // The table is 32 entries, each played twice
+                self.mod_table[self.mod_position as usize] = data & 0x07;
+                self.mod_table[(self.mod_position as usize + 1) & 0x3F] = data & 0x07;
+                self.mod_position = (self.mod_position + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.master_volume = data & 0x03;
+                self.wave_write = (data & 0x80) != 0;
+            }
+            0x408A => {
+                self.master_speed = data;
+                self.volume.reset_timer(data);
+                self.sweep.reset_timer(data);
+            }
+            _ => {}
+        }
+    }
+
+    /// Advances the channel by one CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.wave_halt && !self.envelopes_halt {
+            self.volume.clock(self.master_speed);
+            if self.sweep.clock(self.master_speed) {
+                self.update_mod_pitch();
+            }
+        }
+
+        if !self.mod_halt && self.mod_frequency > 0 {
+            let (sum, overflow) = self.mod_accumulator.overflowing_add(self.mod_frequency);
+            self.mod_accumulator = sum;
+            if overflow {
+                match self.mod_table[self.mod_position as usize] {
+                    4 => self.mod_counter = 0,
+                    step => self.mod_counter = sign_extend_7(self.mod_counter.wrapping_add(MOD_STEPS[step as usize]) as u8),
+                }
+                self.mod_position = (self.mod_position + 1) & 0x3F;
+                self.update_mod_pitch();
+            }
+        }
+
+        // The output only follows the table while it's playing; writes hold the last sample
+        if !self.wave_write {
+            let level = self.volume.gain.min(32) as u32 * MASTER_VOLUMES[self.master_volume as usize];
+            self.output = (self.wave[self.wave_position as usize] as u32 * level / 1152) as u8;
+        }
+        let pitch = self.frequency as i32 + self.mod_pitch;
+        if !self.wave_halt && !self.wave_write && pitch > 0 {
+            let (sum, overflow) = self.wave_accumulator.overflowing_add(pitch as u16);
+            self.wave_accumulator = sum;
+            if overflow {
+                self.wave_position = (self.wave_position + 1) & 0x3F;
+            }
+        }
+    }
+
+    /// Works out the sweep's pitch offset the way the hardware does, rounding quirks included.
+    fn update_mod_pitch(&mut self) {
+        let mut temp = self.mod_counter as i32 * self.sweep.gain as i32;
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && (temp & 0x80) == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= self.frequency as i32;
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_pitch = temp;
+    }
+
+    /// The channel's level in the APU's mix. The DAC is 6 bits, so full volume is 63.
+    pub fn output(&self) -> f32 {
+        self.output as f32 * (apu::PULSE_FULL_VOLUME / 63.0)
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.wave);
+        w.write_bool(self.wave_write);
+        w.write_u8(self.wave_position);
+        w.write_u16(self.wave_accumulator);
+        w.write_u16(self.frequency);
+        w.write_bool(self.wave_halt);
+        w.write_bool(self.envelopes_halt);
+        w.write_u8(self.master_volume);
+        w.write_u8(self.master_speed);
+        self.volume.save_state(w);
+        w.write_u8(self.output);
+        self.sweep.save_state(w);
+        w.write_u8(self.mod_counter as u8);
+        w.write_u16(self.mod_frequency);
+        w.write_bool(self.mod_halt);
+        w.write_bytes(&self.mod_table);
+        w.write_u8(self.mod_position);
+        w.write_u16(self.mod_accumulator);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.wave)?;
+        self.wave_write = r.read_bool()?;
+        self.wave_position = r.read_u8()? & 0x3F;
+        self.wave_accumulator = r.read_u16()?;
+        self.frequency = r.read_u16()? & 0x0FFF;
+        self.wave_halt = r.read_bool()?;
+        self.envelopes_halt = r.read_bool()?;
+        self.master_volume = r.read_u8()? & 0x03;
+        self.master_speed = r.read_u8()?;
+        self.volume.load_state(r)?;
+        self.output = r.read_u8()?;
+        self.sweep.load_state(r)?;
+        self.mod_counter = sign_extend_7(r.read_u8()?);
+        self.mod_frequency = r.read_u16()? & 0x0FFF;
+        self.mod_halt = r.read_bool()?;
+        r.read_bytes(&mut self.mod_table)?;
+        if self.wave.iter().any(|&s| s > 0x3F) || self.mod_table.iter().any(|&m| m > 0x07) {
+            return Err(StateError::Invalid("FDS audio tables"));
+        }
+        self.mod_position = r.read_u8()? & 0x3F;
+        self.mod_accumulator = r.read_u16()?;
+        self.update_mod_pitch();
+        Ok(())
+    }
+}
+
+/// Wraps a value into the sweep counter's 7-bit signed range.
+fn sign_extend_7(value: u8) -> i8 {
+    ((value << 1) as i8) >> 1
+}
@@ -0,0 +1,439 @@
+use super::Mapper;
+use crate::apu;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// The 5B's tone, noise and envelope generators advance once every 16 CPU cycles
+const AUDIO_PRESCALER: u8 = 16;
+
+/// The Sunsoft 5B's sound unit, a YM2149F (AY-3-8910 family): three square wave channels,
+/// a noise generator that can be mixed into any of them and a shared envelope generator.
+/// Volume is logarithmic, 3dB per step of the channel volumes and 1.5dB per envelope step.
+struct Sunsoft5b {
+    address: u8,            // $C000
+    tone_periods: [u16; 3], // R0-R5
+    noise_period: u8,       // R6
+    mixer: u8,              // R7: bits 0-2 disable tone, 3-5 disable noise, per channel
+    volumes: [u8; 3],       // R8-RA: bits 0-3 volume, bit 4 use the envelope instead
+    envelope_period: u16,   // RB-RC
+    envelope_shape: u8,     // RD: bit 0 hold, 1 alternate, 2 attack, 3 continue
+
+    prescaler: u8,
+    tone_counters: [u16; 3],
+    tone_levels: [bool; 3],
+    noise_counter: u16,
+    noise_shift: u32, // 17-bit LFSR
+    envelope_counter: u16,
+    envelope_step: u8, // 0-31
+    envelope_attack: bool,
+    envelope_holding: bool,
+    envelope_held: u8,
+
+    levels: [f32; 32], // Linear amplitude per 5-bit level
+}
+
+impl Sunsoft5b {
+    fn new() -> Self {
+        let mut levels = [0.0; 32];
+        for (level, amplitude) in levels.iter_mut().enumerate().skip(1) {
+            *amplitude = 10f32.powf((level as f32 - 31.0) * 1.5 / 20.0);
+        }
+        Sunsoft5b {
+            address: 0,
+            tone_periods: [0; 3],
+            noise_period: 0,
+            mixer: 0,
+            volumes: [0; 3],
+            envelope_period: 0,
+            envelope_shape: 0,
+            prescaler: 0,
+            tone_counters: [0; 3],
+            tone_levels: [false; 3],
+            noise_counter: 0,
+            noise_shift: 1,
+            envelope_counter: 0,
+            envelope_step: 0,
+            envelope_attack: false,
+            envelope_holding: true,
+            envelope_held: 0,
+            levels,
+        }
+    }
+
+    fn write(&mut self, data: u8) {
+        match self.address {
+            0x00..=0x05 => {
+                let channel = (self.address >> 1) as usize;
+                let period = &mut self.tone_periods[channel];
+                *period = if (self.address & 1) == 0 {
+                    (*period & 0x0F00) | data as u16
+                } else {
+                    (*period & 0x00FF) | ((data as u16 & 0x0F) << 8)
+                };
+            }
+            0x06 => self.noise_period = data & 0x1F,
+            0x07 => self.mixer = data,
+            0x08..=0x0A => self.volumes[(self.address - 0x08) as usize] = data & 0x1F,
+            0x0B => self.envelope_period = (self.envelope_period & 0xFF00) | data as u16,
+            0x0C => self.envelope_period = (self.envelope_period & 0x00FF) | (data as u16) << 8,
+            0x0D => {
+                // Writing the shape restarts the envelope
+                self.envelope_shape = data & 0x0F;
+                self.envelope_attack = (data & 0x04) != 0;
+                self.envelope_step = 0;
+                self.envelope_counter = 0;
+                self.envelope_holding = false;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock(&mut self) {
+        self.prescaler += 1;
+        if self.prescaler < AUDIO_PRESCALER {
+            return;
+        }
+        self.prescaler = 0;
+
+        for channel in 0..3 {
+            self.tone_counters[channel] += 1;
+            if self.tone_counters[channel] >= self.tone_periods[channel].max(1) {
+                self.tone_counters[channel] = 0;
+                self.tone_levels[channel] = !self.tone_levels[channel];
+            }
+        }
+
+        // Noise runs at half the rate of the tones
+        self.noise_counter += 1;
+        if self.noise_counter >= (self.noise_period.max(1) as u16) * 2 {
+            self.noise_counter = 0;
+            let feedback = (self.noise_shift ^ (self.noise_shift >> 3)) & 1;
+            self.noise_shift = (self.noise_shift >> 1) | (feedback << 16);
+        }
+
+        self.envelope_counter += 1;
+        if self.envelope_counter >= self.envelope_period.max(1) {
+            self.envelope_counter = 0;
+            self.step_envelope();
+        }
+    }
+
+    fn step_envelope(&mut self) {
+        if self.envelope_holding {
+            return;
+        }
+        self.envelope_step += 1;
+        if self.envelope_step < 32 {
+            return;
+        }
+        self.envelope_step = 0;
+        let hold = (self.envelope_shape & 0x01) != 0;
+        let alternate = (self.envelope_shape & 0x02) != 0;
+        let continue_ = (self.envelope_shape & 0x08) != 0;
+        if !continue_ {
+            self.envelope_holding = true;
+            self.envelope_held = 0;
+        } else {
+            if alternate {
+                self.envelope_attack = !self.envelope_attack;
+            }
+            if hold {
+                self.envelope_holding = true;
+                self.envelope_held = if self.envelope_attack { 31 } else { 0 };
+            }
+        }
+    }
+
+    fn envelope_level(&self) -> u8 {
+        if self.envelope_holding {
+            self.envelope_held
+        } else if self.envelope_attack {
+            self.envelope_step
+        } else {
+            31 - self.envelope_step
+        }
+    }
+
+    /// Sum of the three channels, where a channel at full volume is 1.0.
+    fn output(&self) -> f32 {
+        let noise = (self.noise_shift & 1) != 0;
+        let mut sum = 0.0;
+        for channel in 0..3 {
+            let tone_on = self.tone_levels[channel] || (self.mixer & (1 << channel)) != 0;
+            let noise_on = noise || (self.mixer & (8 << channel)) != 0;
+            if tone_on && noise_on {
+                let volume = self.volumes[channel];
+                let level = if (volume & 0x10) != 0 {
+                    self.envelope_level()
+                } else if volume & 0x0F == 0 {
+                    0
+                } else {
+                    (volume & 0x0F) * 2 + 1
+                };
+                sum += self.levels[level as usize];
+            }
+        }
+        sum
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.address);
+        for &period in &self.tone_periods {
+            w.write_u16(period);
+        }
+        w.write_u8(self.noise_period);
+        w.write_u8(self.mixer);
+        w.write_bytes(&self.volumes);
+        w.write_u16(self.envelope_period);
+        w.write_u8(self.envelope_shape);
+        w.write_u8(self.prescaler);
+        for channel in 0..3 {
+            w.write_u16(self.tone_counters[channel]);
+            w.write_bool(self.tone_levels[channel]);
+        }
+        w.write_u16(self.noise_counter);
+        w.write_u32(self.noise_shift);
+        w.write_u16(self.envelope_counter);
+        w.write_u8(self.envelope_step);
+        w.write_bool(self.envelope_attack);
+        w.write_bool(self.envelope_holding);
+        w.write_u8(self.envelope_held);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.address = r.read_u8()?;
+        for period in &mut self.tone_periods {
+            *period = r.read_u16()? & 0x0FFF;
+        }
+        self.noise_period = r.read_u8()? & 0x1F;
+        self.mixer = r.read_u8()?;
+        r.read_bytes(&mut self.volumes)?;
+        for volume in &mut self.volumes {
+            *volume &= 0x1F;
+        }
+        self.envelope_period = r.read_u16()?;
+        self.envelope_shape = r.read_u8()? & 0x0F;
+        self.prescaler = r.read_u8()?;
+        for channel in 0..3 {
+            self.tone_counters[channel] = r.read_u16()?;
+            self.tone_levels[channel] = r.read_bool()?;
+        }
+        self.noise_counter = r.read_u16()?;
+        self.noise_shift = r.read_u32()? & 0x1FFFF;
+        self.envelope_counter = r.read_u16()?;
+        self.envelope_step = r.read_u8()?;
+        self.envelope_attack = r.read_bool()?;
+        self.envelope_holding = r.read_bool()?;
+        self.envelope_held = r.read_u8()?;
+        if self.prescaler >= AUDIO_PRESCALER || self.envelope_step >= 32 || self.envelope_held >= 32 {
+            return Err(StateError::Invalid("5B audio timing"));
+        }
+        Ok(())
+    }
+}
+
+/// Mapper 69 (Sunsoft FME-7, and the 5B, which adds sound): four switchable 8KB PRG banks,
+/// one of them at $6000 where it can be swapped for RAM, eight 1KB CHR banks and a 16-bit
+/// CPU cycle IRQ counter. Registers are written through a command port at $8000 and a
+/// parameter port at $A000.
+pub struct Fme7 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    command: u8,           // $8000
+    chr_banks: [u8; 8],    // Commands 0-7
+    ram_bank: u8,          // Command 8: bits 0-5 ROM bank, bit 6 select RAM, bit 7 enable RAM
+    prg_banks: [u8; 3],    // Commands 9-B
+    mirroring: u8,         // Command C
+    irq_enabled: bool,     // Command D bit 0
+    counter_enabled: bool, // Command D bit 7
+    irq_counter: u16,      // Commands E-F
+    irq_pending: bool,
+
+    audio: Sunsoft5b,
+}
+
+impl Fme7 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Fme7 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            command: 0,
+            chr_banks: [0; 8],
+            ram_bank: 0,
+            prg_banks: [0; 3],
+            mirroring: if cartridge.mirroring == Mirroring::Horizontal { 1 } else { 0 },
+            irq_enabled: false,
+            counter_enabled: false,
+            irq_counter: 0,
+            irq_pending: false,
+            audio: Sunsoft5b::new(),
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x6000..=0x7FFF => (self.ram_bank & 0x3F) as usize,
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+
+    fn ram_selected(&self) -> bool {
+        (self.ram_bank & 0x40) != 0
+    }
+
+    fn ram_enabled(&self) -> bool {
+        (self.ram_bank & 0xC0) == 0xC0
+    }
+}
+
+impl Mapper for Fme7 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.ram_enabled() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x6000..=0x7FFF if self.ram_selected() => 0,
+            0x6000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if self.ram_enabled() => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x8000..=0x9FFF => self.command = data & 0x0F,
+            0xA000..=0xBFFF => match self.command {
+                0x0..=0x7 => self.chr_banks[self.command as usize] = data,
+                0x8 => self.ram_bank = data,
+                0x9..=0xB => self.prg_banks[(self.command - 0x9) as usize] = data & 0x3F,
+                0xC => self.mirroring = data & 0x03,
+                0xD => {
+                    self.irq_enabled = (data & 0x01) != 0;
+                    self.counter_enabled = (data & 0x80) != 0;
+                    self.irq_pending = false;
+                }
+                0xE => self.irq_counter = (self.irq_counter & 0xFF00) | data as u16,
+                _ => self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8,
+            },
+            0xC000..=0xDFFF => self.audio.address = data & 0x0F,
+            0xE000..=0xFFFF => self.audio.write(data),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        if self.counter_enabled {
+            self.irq_counter = self.irq_counter.wrapping_sub(1);
+            if self.irq_counter == 0xFFFF && self.irq_enabled {
+                self.irq_pending = true;
+            }
+        }
+        self.audio.clock();
+    }
+
+    fn audio_output(&self) -> f32 {
+        // The 5B's channels already come out as 0.0-1.0 each
+        self.audio.output() * apu::PULSE_FULL_VOLUME
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count { 0xE000 } else { 0x8000 }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match addr {
+            0x6000..=0x7FFF if self.ram_selected() => None,
+            0x6000..=0xFFFF => Some(self.prg_index(addr)),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_u8(self.command);
+        w.write_bytes(&self.chr_banks);
+        w.write_u8(self.ram_bank);
+        w.write_bytes(&self.prg_banks);
+        w.write_u8(self.mirroring);
+        w.write_bool(self.irq_enabled);
+        w.write_bool(self.counter_enabled);
+        w.write_u16(self.irq_counter);
+        w.write_bool(self.irq_pending);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        self.command = r.read_u8()? & 0x0F;
+        r.read_bytes(&mut self.chr_banks)?;
+        self.ram_bank = r.read_u8()?;
+        r.read_bytes(&mut self.prg_banks)?;
+        self.mirroring = r.read_u8()? & 0x03;
+        self.irq_enabled = r.read_bool()?;
+        self.counter_enabled = r.read_bool()?;
+        self.irq_counter = r.read_u16()?;
+        self.irq_pending = r.read_bool()?;
+        self.audio.load_state(r)
+    }
+}
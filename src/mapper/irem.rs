@@ -0,0 +1,153 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Mapper 32 (Irem G-101): two switchable 8KB PRG banks, with a mode that swaps the
+/// $8000 and $C000 windows, and eight 1KB CHR banks. Major League (submapper 1) wires
+/// the nametables for single-screen mirroring and has no mode bit.
+pub struct IremG101 {
+    major_league: bool,
+
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 2], // $8000, $A000
+    prg_swap: bool,     // $9000 bit 1
+    chr_banks: [u8; 8], // $B000-$B007
+    mirroring: Mirroring,
+}
+
+impl IremG101 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let major_league = cartridge.submapper == 1;
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        IremG101 {
+            major_league,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: [0, 1],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: if major_league { Mirroring::SingleScreenLower } else { cartridge.mirroring },
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let second_last = bank_count.saturating_sub(2);
+        let bank = match addr {
+            0x8000..=0x9FFF if self.prg_swap => second_last,
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF if self.prg_swap => self.prg_banks[0] as usize,
+            0xC000..=0xDFFF => second_last,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for IremG101 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x8000..=0x8FFF => self.prg_banks[0] = data & 0x1F,
+            0x9000..=0x9FFF if !self.major_league => {
+                self.mirroring = if (data & 0x01) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+                self.prg_swap = (data & 0x02) != 0;
+            }
+            0xA000..=0xAFFF => self.prg_banks[1] = data & 0x1F,
+            0xB000..=0xBFFF => self.chr_banks[(addr & 0x07) as usize] = data,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        match bank_count - bank {
+            1 => 0xE000,
+            2 => 0xC000,
+            _ => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bool(self.prg_swap);
+        w.write_bytes(&self.chr_banks);
+        w.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        self.prg_swap = r.read_bool()?;
+        r.read_bytes(&mut self.chr_banks)?;
+        let horizontal = r.read_bool()?;
+        if !self.major_league {
+            self.mirroring = if horizontal { Mirroring::Horizontal } else { Mirroring::Vertical };
+        }
+        Ok(())
+    }
+}
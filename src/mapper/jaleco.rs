@@ -0,0 +1,205 @@
+use super::{Mapper, check_submapper};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Mapper 18 (Jaleco SS88006): three switchable 8KB PRG banks, eight 1KB CHR banks, all
+/// written a nibble at a time, and a CPU cycle IRQ counter that can be cut down to its
+/// low 12, 8 or 4 bits. The speech chip on some boards isn't emulated. NES 2.0 defines
+/// no submappers for it.
+pub struct JalecoSs88006 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 3], // $8000-$9001
+    ram_control: u8,    // $9002: bit 0 enable, bit 1 allow writes
+    chr_banks: [u8; 8], // $A000-$D003
+    irq_reload: u16,    // $E000-$E003
+    irq_counter: u16,
+    irq_control: u8,    // $F001: bit 0 enable, bits 1-3 counter size
+    irq_pending: bool,
+    mirroring: u8,      // $F002
+}
+
+impl JalecoSs88006 {
+    pub fn new(cartridge: &Cartridge) -> Result<Self, String> {
+        check_submapper(cartridge, &[])?;
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Ok(JalecoSs88006 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: [0, 1, 2],
+            ram_control: 0,
+            chr_banks: [0; 8],
+            irq_reload: 0,
+            irq_counter: 0,
+            irq_control: 0,
+            irq_pending: false,
+            mirroring: if cartridge.mirroring == Mirroring::Vertical { 1 } else { 0 },
+        })
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+
+    fn irq_mask(&self) -> u16 {
+        match self.irq_control {
+            c if (c & 0x08) != 0 => 0x000F,
+            c if (c & 0x04) != 0 => 0x00FF,
+            c if (c & 0x02) != 0 => 0x0FFF,
+            _ => 0xFFFF,
+        }
+    }
+}
+
+/// Sets one nibble of a bank register: the even register the low nibble, the odd one the high.
+fn write_nibble(bank: &mut u8, high: bool, data: u8) {
+    *bank = if high { (*bank & 0x0F) | (data << 4) } else { (*bank & 0xF0) | (data & 0x0F) };
+}
+
+impl Mapper for JalecoSs88006 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if (self.ram_control & 0x01) != 0 => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        let high = (addr & 0x01) != 0;
+        match addr & 0xF003 {
+            0x6000..=0x7FFF if (self.ram_control & 0x03) == 0x03 => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x8000..=0x8003 => write_nibble(&mut self.prg_banks[(addr as usize >> 1) & 1], high, data & 0x3F),
+            0x9000..=0x9001 => write_nibble(&mut self.prg_banks[2], high, data & 0x3F),
+            0x9002 => self.ram_control = data & 0x03,
+            0xA000..=0xD003 => {
+                let bank = (((addr as usize - 0xA000) >> 11) & 0x06) | ((addr as usize >> 1) & 1);
+                write_nibble(&mut self.chr_banks[bank], high, data);
+            }
+            0xE000..=0xE003 => {
+                let shift = (addr & 0x03) * 4;
+                self.irq_reload = (self.irq_reload & !(0x000F << shift)) | ((data as u16 & 0x0F) << shift);
+            }
+            0xF000 => {
+                self.irq_counter = self.irq_reload;
+                self.irq_pending = false;
+            }
+            0xF001 => {
+                self.irq_control = data & 0x0F;
+                self.irq_pending = false;
+            }
+            0xF002 => self.mirroring = data & 0x03,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Horizontal,
+            1 => Mirroring::Vertical,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        if (self.irq_control & 0x01) == 0 {
+            return;
+        }
+        // Only the selected low bits count; the IRQ fires when they underflow
+        let mask = self.irq_mask();
+        let count = self.irq_counter & mask;
+        if count == 0 {
+            self.irq_pending = true;
+        }
+        self.irq_counter = (self.irq_counter & !mask) | (count.wrapping_sub(1) & mask);
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count { 0xE000 } else { 0x8000 }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_u8(self.ram_control);
+        w.write_bytes(&self.chr_banks);
+        w.write_u16(self.irq_reload);
+        w.write_u16(self.irq_counter);
+        w.write_u8(self.irq_control);
+        w.write_bool(self.irq_pending);
+        w.write_u8(self.mirroring);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        self.ram_control = r.read_u8()? & 0x03;
+        r.read_bytes(&mut self.chr_banks)?;
+        self.irq_reload = r.read_u16()?;
+        self.irq_counter = r.read_u16()?;
+        self.irq_control = r.read_u8()? & 0x0F;
+        self.irq_pending = r.read_bool()?;
+        self.mirroring = r.read_u8()? & 0x03;
+        Ok(())
+    }
+}
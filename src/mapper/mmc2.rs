@@ -0,0 +1,178 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Chip {
+    Mmc2, // Mapper 9: Punch-Out!!
+    Mmc4, // Mapper 10: Fire Emblem, Famicom Wars
+}
+
+/// Mappers 9 and 10: each 4KB CHR window has two banks, picked by a latch that flips
+/// when the PPU fetches tile $FD or $FE from it. Games put those tiles at the edge of
+/// a region of the screen to switch graphics mid-frame without an IRQ.
+pub struct Mmc2 {
+    chip: Chip,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // MMC4 only
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_bank: u8,
+    chr_banks: [[u8; 2]; 2], // Per window, the bank for latch $FD and for latch $FE
+    latches: [bool; 2],      // Per window, true once tile $FE was fetched
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(cartridge: &Cartridge, chip: Chip) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Mmc2 {
+            chip,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: if chip == Chip::Mmc4 { vec![0; 0x2000] } else { Vec::new() },
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [false; 2],
+            mirroring: cartridge.mirroring,
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_size = self.prg_bank_size();
+        let bank_count = self.prg_rom.len() / bank_size;
+        let bank = match (self.chip, addr) {
+            // MMC2 switches 8KB at $8000 and fixes the last three banks above it
+            (Chip::Mmc2, 0x8000..=0x9FFF) => self.prg_bank as usize,
+            (Chip::Mmc2, _) => (bank_count + (addr as usize - 0x8000) / bank_size).saturating_sub(4),
+            // MMC4 switches 16KB at $8000 and fixes the last bank at $C000
+            (Chip::Mmc4, 0x8000..=0xBFFF) => self.prg_bank as usize,
+            (Chip::Mmc4, _) => bank_count - 1,
+        };
+        (bank % bank_count) * bank_size + (addr as usize % bank_size)
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let window = (addr >> 12) as usize & 1;
+        let bank = self.chr_banks[window][self.latches[window] as usize] as usize;
+        (bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0xA000..=0xAFFF => self.prg_bank = data & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = data & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = data & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = data & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = data & 0x1F,
+            0xF000..=0xFFFF => {
+                self.mirroring = if (data & 1) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        let data = self.chr[self.chr_index(addr)];
+        // The latch flips after the fetch, so the triggering tile still comes from the old bank.
+        // MMC2's left window only reacts to the first row of the tile; everything else to any row.
+        let window = (addr >> 12) as usize & 1;
+        let exact = self.chip == Chip::Mmc2 && window == 0;
+        match addr & 0x0FF8 {
+            0x0FD8 if !exact || addr == 0x0FD8 => self.latches[window] = false,
+            0x0FE8 if !exact || addr == 0x0FE8 => self.latches[window] = true,
+            _ => {}
+        }
+        data
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        match self.chip {
+            Chip::Mmc2 => 0x2000,
+            Chip::Mmc4 => 0x4000,
+        }
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        match self.chip {
+            Chip::Mmc2 if bank + 3 >= bank_count => (0x10000 - (bank_count - bank) * 0x2000) as u16,
+            Chip::Mmc2 => 0x8000,
+            Chip::Mmc4 if bank + 1 == bank_count => 0xC000,
+            Chip::Mmc4 => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery && !self.prg_ram.is_empty() { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_u8(self.prg_bank);
+        for banks in &self.chr_banks {
+            w.write_bytes(banks);
+        }
+        for &latch in &self.latches {
+            w.write_bool(latch);
+        }
+        w.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        self.prg_bank = r.read_u8()?;
+        for banks in &mut self.chr_banks {
+            r.read_bytes(banks)?;
+        }
+        for latch in &mut self.latches {
+            *latch = r.read_bool()?;
+        }
+        self.mirroring = if r.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+        Ok(())
+    }
+}
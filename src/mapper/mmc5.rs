@@ -0,0 +1,606 @@
+use super::Mapper;
+use crate::apu::{self, Pulse};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// PPU reads per scanline, counted from the first nametable fetch after a scanline is
+// detected. That fetch is for tile 2, since tiles 0 and 1 were fetched at the end of
+// the previous line.
+const BACKGROUND_FETCHES: u16 = 128; // Tiles 2-33, four reads each
+const SPRITE_FETCHES_END: u16 = 160; // Eight sprites, four reads each
+const PREFETCH_END: u16 = 168; // Tiles 0 and 1 of the next line
+
+// CPU cycles between the MMC5's audio frame clocks (240Hz)
+const AUDIO_FRAME_PERIOD: u16 = 7457;
+
+/// What a PPU read is for, as far as the MMC5 can tell from counting them.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Fetch {
+    Other, // Outside rendering, or a dummy fetch
+    Background { column: u16, next_line: bool, phase: u16 },
+    Sprite,
+}
+
+/// Mapper 5: four PRG banking modes over ROM and up to 64KB of RAM, four CHR modes with
+/// separate sprite and background banks, 1KB of ExRAM usable as a nametable, extended
+/// attributes or plain RAM, a vertical split, fill-mode nametables, a multiplier, a
+/// scanline IRQ and expansion audio. Like the real chip it works out what the PPU is doing
+/// by watching its reads: three reads of the same nametable address mark a new scanline.
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    exram: [u8; 1024],
+    battery: bool,
+
+    // Configuration
+    prg_mode: u8,            // $5100
+    chr_mode: u8,            // $5101
+    prg_ram_protect: [u8; 2], // $5102, $5103: writes need 2 and 1
+    exram_mode: u8,          // $5104
+    nametable_mapping: u8,   // $5105: two bits per quadrant
+    fill_tile: u8,           // $5106
+    fill_attribute: u8,      // $5107
+    prg_banks: [u8; 5],      // $5113-$5117
+    chr_banks: [u16; 12],    // $5120-$512B, with the upper bits from $5130
+    chr_upper: u8,           // $5130
+    last_set_b: bool,        // Whether $5128-$512B were written last
+    split_control: u8,       // $5200
+    split_scroll: u8,        // $5201
+    split_bank: u8,          // $5202
+    irq_scanline: u8,        // $5203
+    irq_enabled: bool,
+    multiplicand: u8,
+    multiplier: u8,
+
+    // Snooped from the PPU registers
+    large_sprites: bool,
+    ppu_rendering: bool,
+
+    // Scanline detection
+    last_nametable_addr: u16,
+    matches: u8,
+    idle_cycles: u8, // CPU cycles without a PPU read
+    in_frame: bool,
+    scanline: u8,
+    irq_pending: bool,
+    reads: u16, // PPU reads since the scanline was detected
+    split_y: u8,
+    tile_latch: u8, // ExRAM byte, or split tile number, for the tile being fetched
+    tile_in_split: bool,
+
+    // Audio
+    pulses: [Pulse; 2],
+    audio_frame: u16,
+    pcm_read_mode: bool,
+    pcm_irq_enabled: bool,
+    pcm_irq: bool,
+    pcm: u8,
+}
+
+impl Mmc5 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        let prg_ram_size = (cartridge.prg_ram_size + cartridge.prg_nvram_size).clamp(0x2000, 0x10000);
+        Mmc5 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: vec![0; prg_ram_size],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            exram: [0; 1024],
+            battery: cartridge.battery,
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametable_mapping: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0xFF; 5], // The last bank starts out at $E000, where the vectors are
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            last_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_scanline: 0,
+            irq_enabled: false,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            large_sprites: false,
+            ppu_rendering: false,
+            last_nametable_addr: 0,
+            matches: 0,
+            idle_cycles: 0,
+            in_frame: false,
+            scanline: 0,
+            irq_pending: false,
+            reads: 0,
+            split_y: 0,
+            tile_latch: 0,
+            tile_in_split: false,
+            pulses: [Pulse::new(false), Pulse::new(false)],
+            audio_frame: 0,
+            pcm_read_mode: false,
+            pcm_irq_enabled: false,
+            pcm_irq: false,
+            pcm: 0,
+        }
+    }
+
+    /// Where an 8KB window of $6000-$FFFF points: (is ROM, offset into ROM or RAM).
+    fn prg_target(&self, addr: u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, self.prg_ram_index(self.prg_banks[0], addr));
+        }
+        let slot = (addr as usize - 0x8000) / 0x2000;
+        // Register per 8KB slot ($5114-$5117), and the low bits that the slot supplies itself
+        let (register, mask) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0x03),
+            (1, 0..=1) | (2, 0..=1) => (2, 0x01),
+            (1, _) => (4, 0x01),
+            (2, 2) => (3, 0),
+            (2, _) => (4, 0),
+            (_, slot) => (slot + 1, 0),
+        };
+        let value = self.prg_banks[register];
+        let bank = (value as usize & 0x7F & !mask) | (slot & mask);
+        // $5117 always maps ROM; the others pick with bit 7
+        if register == 4 || (value & 0x80) != 0 {
+            let offset = bank * 0x2000 + (addr as usize & 0x1FFF);
+            (true, offset % self.prg_rom.len())
+        } else {
+            (false, self.prg_ram_index(bank as u8, addr))
+        }
+    }
+
+    fn prg_ram_index(&self, bank: u8, addr: u16) -> usize {
+        (bank as usize * 0x2000 + (addr as usize & 0x1FFF)) % self.prg_ram.len()
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect == [0x02, 0x01]
+    }
+
+    fn rendering(&self) -> bool {
+        self.in_frame && self.ppu_rendering
+    }
+
+    /// Classifies the PPU read at a position in the scanline.
+    fn fetch(&self, position: u16) -> Fetch {
+        if !self.rendering() {
+            return Fetch::Other;
+        }
+        match position {
+            0..BACKGROUND_FETCHES => Fetch::Background { column: position / 4 + 2, next_line: false, phase: position % 4 },
+            BACKGROUND_FETCHES..SPRITE_FETCHES_END => Fetch::Sprite,
+            SPRITE_FETCHES_END..PREFETCH_END => {
+                let position = position - SPRITE_FETCHES_END;
+                Fetch::Background { column: position / 4, next_line: true, phase: position % 4 }
+            }
+            _ => Fetch::Other,
+        }
+    }
+
+    fn in_split(&self, column: u16) -> bool {
+        if (self.split_control & 0x80) == 0 || self.exram_mode > 1 {
+            return false;
+        }
+        let threshold = (self.split_control & 0x1F) as u16;
+        let column = column & 0x1F;
+        if (self.split_control & 0x40) != 0 { column >= threshold } else { column < threshold }
+    }
+
+    /// Line within the 240-line split region that a background fetch is for.
+    fn split_line(&self, next_line: bool) -> u16 {
+        (self.split_y as u16 + next_line as u16) % 240
+    }
+
+    /// Maps a pattern table address to CHR, given what the PPU is fetching.
+    fn chr_index(&self, addr: u16, fetch: Fetch) -> usize {
+        let addr = addr & 0x1FFF;
+        if let Fetch::Background { next_line, .. } = fetch {
+            if self.tile_in_split {
+                let row = self.split_line(next_line) & 0x07;
+                let offset = ((self.tile_latch as usize) << 4) | (addr as usize & 0x08) | row as usize;
+                return (self.split_bank as usize * 0x1000 + offset) % self.chr.len();
+            }
+            if self.exram_mode == 1 {
+                // Extended attributes: each tile picks its own 4KB bank
+                let bank = (self.tile_latch as usize & 0x3F) | ((self.chr_upper as usize & 0x03) << 6);
+                return (bank * 0x1000 + (addr as usize & 0x0FFF)) % self.chr.len();
+            }
+        }
+        // 8x16 sprites come from set A and the background from set B; otherwise whichever was written last
+        let set_b = match fetch {
+            Fetch::Sprite => false,
+            Fetch::Background { .. } if self.large_sprites => true,
+            _ if self.rendering() => false,
+            _ => self.last_set_b,
+        };
+        let size = 0x2000 >> self.chr_mode;
+        let addr = if set_b && self.chr_mode != 0 { addr & 0x0FFF } else { addr } as usize;
+        let slot = addr / size;
+        let register = (slot + 1) * (8 >> self.chr_mode) - 1;
+        let register = if set_b { 8 + (register & 0x03) } else { register };
+        (self.chr_banks[register] as usize * size + addr % size) % self.chr.len()
+    }
+
+    /// Bookkeeping for every PPU read: idle tracking and what it's for.
+    fn count_read(&mut self) -> Fetch {
+        self.idle_cycles = 0;
+        let position = self.reads;
+        self.reads = self.reads.saturating_add(1);
+        self.fetch(position)
+    }
+
+    /// Watches nametable reads for the three-in-a-row pattern that starts each scanline.
+    fn detect_scanline(&mut self, addr: u16) {
+        if addr == self.last_nametable_addr {
+            self.matches += 1;
+        } else {
+            self.last_nametable_addr = addr;
+            self.matches = 1;
+        }
+        if self.matches != 3 {
+            return;
+        }
+        self.reads = 0;
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+            self.split_y = ((self.split_y as u16 + 1) % 240) as u8;
+            if self.scanline == self.irq_scanline && self.irq_scanline != 0 {
+                self.irq_pending = true;
+            }
+        } else {
+            self.in_frame = true;
+            self.scanline = 0;
+            self.split_y = self.split_scroll % 240;
+        }
+    }
+
+    /// Where a nametable address lands with the current $5105 mapping, when it isn't overridden.
+    fn nametable_source(&self, addr: u16) -> u8 {
+        let quadrant = (addr >> 10) & 0x03;
+        (self.nametable_mapping >> (quadrant * 2)) & 0x03
+    }
+
+    fn write_exram(&mut self, addr: u16, data: u8) {
+        let index = (addr & 0x03FF) as usize;
+        match self.exram_mode {
+            // As nametable or attributes, the CPU can only write while the PPU is rendering
+            0 | 1 => self.exram[index] = if self.in_frame { data } else { 0 },
+            2 => self.exram[index] = data,
+            _ => {}
+        }
+    }
+
+    fn write_chr_bank(&mut self, register: usize, data: u8) {
+        self.chr_banks[register] = data as u16 | ((self.chr_upper as u16 & 0x03) << 8);
+        self.last_set_b = register >= 8;
+    }
+}
+
+/// Repeats a 2-bit palette number into all four quadrants of an attribute byte.
+fn attribute_byte(palette: u8) -> u8 {
+    (palette & 0x03) * 0x55
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x5010 => {
+                let status = ((self.pcm_irq && self.pcm_irq_enabled) as u8) << 7 | self.pcm_read_mode as u8;
+                self.pcm_irq = false;
+                status
+            }
+            0x5204 => {
+                let status = self.cpu_peek(addr);
+                self.irq_pending = false;
+                status
+            }
+            0x8000..=0xBFFF if self.pcm_read_mode => {
+                // PCM read mode plays whatever the CPU reads from $8000-$BFFF; zero raises the IRQ instead
+                let data = self.cpu_peek(addr);
+                if data == 0 {
+                    self.pcm_irq = true;
+                } else {
+                    self.pcm = data;
+                }
+                data
+            }
+            _ => self.cpu_peek(addr),
+        }
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x5015 => self.pulses[0].is_playing() as u8 | (self.pulses[1].is_playing() as u8) << 1,
+            0x5204 => (self.irq_pending as u8) << 7 | (self.in_frame as u8) << 6,
+            0x5205 => (self.multiplicand as u16 * self.multiplier as u16) as u8,
+            0x5206 => ((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8,
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => self.exram[(addr & 0x03FF) as usize],
+            0x6000..=0xFFFF => match self.prg_target(addr) {
+                (true, offset) => self.prg_rom[offset],
+                (false, offset) => self.prg_ram[offset],
+            },
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5000..=0x5003 => self.pulses[0].write(addr, data),
+            0x5004..=0x5007 => self.pulses[1].write(addr, data),
+            0x5010 => {
+                self.pcm_read_mode = (data & 0x01) != 0;
+                self.pcm_irq_enabled = (data & 0x80) != 0;
+            }
+            0x5011 if !self.pcm_read_mode && data != 0 => self.pcm = data,
+            0x5015 => {
+                self.pulses[0].set_enabled((data & 0x01) != 0);
+                self.pulses[1].set_enabled((data & 0x02) != 0);
+            }
+            0x5100 => self.prg_mode = data & 0x03,
+            0x5101 => self.chr_mode = data & 0x03,
+            0x5102 => self.prg_ram_protect[0] = data & 0x03,
+            0x5103 => self.prg_ram_protect[1] = data & 0x03,
+            0x5104 => self.exram_mode = data & 0x03,
+            0x5105 => self.nametable_mapping = data,
+            0x5106 => self.fill_tile = data,
+            0x5107 => self.fill_attribute = data & 0x03,
+            0x5113..=0x5117 => self.prg_banks[(addr - 0x5113) as usize] = data,
+            0x5120..=0x512B => self.write_chr_bank((addr - 0x5120) as usize, data),
+            0x5130 => self.chr_upper = data & 0x03,
+            0x5200 => self.split_control = data,
+            0x5201 => self.split_scroll = data,
+            0x5202 => self.split_bank = data,
+            0x5203 => self.irq_scanline = data,
+            0x5204 => self.irq_enabled = (data & 0x80) != 0,
+            0x5205 => self.multiplicand = data,
+            0x5206 => self.multiplier = data,
+            0x5C00..=0x5FFF => self.write_exram(addr, data),
+            0x6000..=0xFFFF if self.prg_ram_writable() => {
+                if let (false, offset) = self.prg_target(addr) {
+                    self.prg_ram[offset] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_register_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x2000 => self.large_sprites = (data & 0x20) != 0,
+            0x2001 => self.ppu_rendering = (data & 0x18) != 0,
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.matches = 0;
+        let fetch = self.count_read();
+        self.chr[self.chr_index(addr, fetch)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr, Fetch::Other);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.nametable_mapping {
+            0x00 => Mirroring::SingleScreenLower,
+            0x55 => Mirroring::SingleScreenUpper,
+            0x50 => Mirroring::Horizontal,
+            0x44 => Mirroring::Vertical,
+            _ => Mirroring::FourScreen,
+        }
+    }
+
+    fn nametable_read(&mut self, addr: u16, ciram: &[u8; 2048]) -> u8 {
+        self.detect_scanline(addr);
+        let fetch = self.count_read();
+        let offset = addr & 0x03FF;
+
+        if let Fetch::Background { column, next_line, phase } = fetch {
+            // The split region draws its own tiles from ExRAM, whatever the scroll says
+            if phase == 0 {
+                self.tile_in_split = self.in_split(column);
+            }
+            if self.tile_in_split {
+                let row = self.split_line(next_line) / 8;
+                let column = column & 0x1F;
+                if phase == 0 {
+                    self.tile_latch = self.exram[(row * 32 + column) as usize];
+                    return self.tile_latch;
+                }
+                let attribute = self.exram[(0x3C0 + (row / 4) * 8 + column / 4) as usize];
+                let shift = ((row & 0x02) << 1) | (column & 0x02);
+                return attribute_byte(attribute >> shift);
+            }
+            if self.exram_mode == 1 {
+                if phase == 0 {
+                    self.tile_latch = self.exram[offset as usize];
+                } else {
+                    return attribute_byte(self.tile_latch >> 6);
+                }
+            }
+        }
+
+        match self.nametable_source(addr) {
+            page @ (0 | 1) => ciram[page as usize * 0x400 + offset as usize],
+            2 if self.exram_mode <= 1 => self.exram[offset as usize],
+            2 => 0,
+            _ if offset >= 0x3C0 => attribute_byte(self.fill_attribute),
+            _ => self.fill_tile,
+        }
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8, ciram: &mut [u8; 2048]) {
+        let offset = (addr & 0x03FF) as usize;
+        match self.nametable_source(addr) {
+            page @ (0 | 1) => ciram[page as usize * 0x400 + offset] = data,
+            2 if self.exram_mode <= 1 => self.exram[offset] = data,
+            _ => {}
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        // The PPU stops reading when rendering is off or in VBlank, which ends the frame
+        if self.idle_cycles < 3 {
+            self.idle_cycles += 1;
+            if self.idle_cycles == 3 {
+                self.in_frame = false;
+                self.matches = 0;
+            }
+        }
+
+        self.audio_frame += 1;
+        if self.audio_frame == AUDIO_FRAME_PERIOD {
+            self.audio_frame = 0;
+            for pulse in &mut self.pulses {
+                pulse.clock_envelope();
+                pulse.clock_length_and_sweep();
+            }
+        }
+        // Pulse timers run at half the CPU clock, like the APU's
+        if self.audio_frame.is_multiple_of(2) {
+            for pulse in &mut self.pulses {
+                pulse.clock_timer();
+            }
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        let pulses = apu::mix_pulses(self.pulses[0].output(), self.pulses[1].output());
+        let pcm = if self.pcm == 0 { 0.0 } else { 159.79 / (22638.0 / (self.pcm as f32 / 2.0) + 100.0) };
+        pulses + pcm
+    }
+
+    fn irq(&self) -> bool {
+        (self.irq_pending && self.irq_enabled) || (self.pcm_irq && self.pcm_irq_enabled)
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        match self.prg_target(addr) {
+            (true, offset) if addr >= 0x6000 => Some(offset),
+            _ => None,
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr, self.fetch(self.reads))) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.exram);
+        for value in [self.prg_mode, self.chr_mode, self.prg_ram_protect[0], self.prg_ram_protect[1], self.exram_mode] {
+            w.write_u8(value);
+        }
+        for value in [self.nametable_mapping, self.fill_tile, self.fill_attribute, self.chr_upper] {
+            w.write_u8(value);
+        }
+        w.write_bytes(&self.prg_banks);
+        for &bank in &self.chr_banks {
+            w.write_u16(bank);
+        }
+        w.write_bool(self.last_set_b);
+        for value in [self.split_control, self.split_scroll, self.split_bank, self.irq_scanline, self.multiplicand, self.multiplier] {
+            w.write_u8(value);
+        }
+        w.write_bool(self.irq_enabled);
+        w.write_bool(self.large_sprites);
+        w.write_bool(self.ppu_rendering);
+        w.write_u16(self.last_nametable_addr);
+        w.write_u8(self.matches);
+        w.write_u8(self.idle_cycles);
+        w.write_bool(self.in_frame);
+        w.write_u8(self.scanline);
+        w.write_bool(self.irq_pending);
+        w.write_u16(self.reads);
+        w.write_u8(self.split_y);
+        w.write_u8(self.tile_latch);
+        w.write_bool(self.tile_in_split);
+        for pulse in &self.pulses {
+            pulse.save_state(w);
+        }
+        w.write_u16(self.audio_frame);
+        w.write_bool(self.pcm_read_mode);
+        w.write_bool(self.pcm_irq_enabled);
+        w.write_bool(self.pcm_irq);
+        w.write_u8(self.pcm);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.exram)?;
+        self.prg_mode = r.read_u8()? & 0x03;
+        self.chr_mode = r.read_u8()? & 0x03;
+        self.prg_ram_protect = [r.read_u8()?, r.read_u8()?];
+        self.exram_mode = r.read_u8()? & 0x03;
+        self.nametable_mapping = r.read_u8()?;
+        self.fill_tile = r.read_u8()?;
+        self.fill_attribute = r.read_u8()?;
+        self.chr_upper = r.read_u8()?;
+        r.read_bytes(&mut self.prg_banks)?;
+        for bank in &mut self.chr_banks {
+            *bank = r.read_u16()?;
+        }
+        self.last_set_b = r.read_bool()?;
+        self.split_control = r.read_u8()?;
+        self.split_scroll = r.read_u8()?;
+        self.split_bank = r.read_u8()?;
+        self.irq_scanline = r.read_u8()?;
+        self.multiplicand = r.read_u8()?;
+        self.multiplier = r.read_u8()?;
+        self.irq_enabled = r.read_bool()?;
+        self.large_sprites = r.read_bool()?;
+        self.ppu_rendering = r.read_bool()?;
+        self.last_nametable_addr = r.read_u16()?;
+        self.matches = r.read_u8()?;
+        self.idle_cycles = r.read_u8()?;
+        self.in_frame = r.read_bool()?;
+        self.scanline = r.read_u8()?;
+        self.irq_pending = r.read_bool()?;
+        self.reads = r.read_u16()?;
+        self.split_y = r.read_u8()?;
+        self.tile_latch = r.read_u8()?;
+        self.tile_in_split = r.read_bool()?;
+        for pulse in &mut self.pulses {
+            pulse.load_state(r)?;
+        }
+        self.audio_frame = r.read_u16()?;
+        if self.audio_frame >= AUDIO_FRAME_PERIOD {
+            return Err(StateError::Invalid("MMC5 audio frame counter"));
+        }
+        self.pcm_read_mode = r.read_bool()?;
+        self.pcm_irq_enabled = r.read_bool()?;
+        self.pcm_irq = r.read_bool()?;
+        self.pcm = r.read_u8()?;
+        Ok(())
+    }
+}
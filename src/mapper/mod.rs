@@ -0,0 +1,196 @@
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+mod bandai;
+mod discrete;
+mod eeprom;
+mod fds;
+mod fds_audio;
+mod fme7;
+mod irem;
+mod jaleco;
+mod mmc2;
+mod mmc5;
+mod multicart;
+mod namco163;
+mod nrom;
+mod nsf;
+mod opll;
+mod taito;
+#[cfg(test)]
+mod tests;
+mod vrc1;
+mod vrc4;
+mod vrc6;
+mod vrc7;
+mod vrc_irq;
+
+pub use bandai::Bandai;
+pub use discrete::Discrete;
+pub use fds::Fds;
+pub use fme7::Fme7;
+pub use irem::IremG101;
+pub use jaleco::JalecoSs88006;
+pub use mmc2::Mmc2;
+pub use mmc5::Mmc5;
+pub use multicart::Multicart;
+pub use namco163::Namco163;
+pub use nrom::Nrom;
+pub use nsf::NsfBoard;
+pub use taito::Taito;
+pub use vrc1::Vrc1;
+pub use vrc4::Vrc4;
+pub use vrc6::Vrc6;
+pub use vrc7::Vrc7;
+
+/// Cartridge hardware as seen from the CPU ($4020-$FFFF) and PPU ($0000-$3EFF) buses.
+pub trait Mapper {
+    /// Handles CPU reads in cartridge space.
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        self.cpu_peek(addr)
+    }
+
+    /// Reads cartridge space without side effects, for instruction prefetch and debuggers.
+    fn cpu_peek(&self, addr: u16) -> u8;
+
+    /// Handles CPU writes in cartridge space (PRG RAM and mapper registers).
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    /// Handles PPU reads from the pattern tables ($0000-$1FFF).
+    fn chr_read(&mut self, addr: u16) -> u8;
+
+    /// Handles PPU writes to the pattern tables, which only stick on CHR RAM boards.
+    fn chr_write(&mut self, addr: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    /// Handles PPU reads from the nametables ($2000-$3EFF).
+    /// By default they land in the console's CIRAM according to `mirroring`.
+    fn nametable_read(&mut self, addr: u16, ciram: &[u8; 2048]) -> u8 {
+        ciram[self.mirroring().ciram_index(addr)]
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8, ciram: &mut [u8; 2048]) {
+        ciram[self.mirroring().ciram_index(addr)] = data;
+    }
+
+    /// Clocked once per CPU cycle, for boards with cycle counters or audio.
+    fn cpu_tick(&mut self) {}
+
+    /// Sees CPU writes to the PPU registers ($2000-$2007), for boards that snoop the PPU's configuration.
+    fn ppu_register_write(&mut self, _addr: u16, _data: u8) {}
+
+    /// Expansion audio level, scaled like the APU's mixed output, which it's added to.
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
+
+    /// Chooses between the hardware's time-multiplexed output and a clean mix, for sound
+    /// chips that play their channels one at a time.
+    fn set_audio_multiplexing(&mut self, _enabled: bool) {}
+
+    /// Number of disk sides, for the Famicom Disk System; 0 for cartridges.
+    fn disk_sides(&self) -> usize {
+        0
+    }
+
+    /// Disk side in the drive, or None if it's empty.
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+
+    /// Puts a disk side in the drive, or ejects the disk with None.
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+
+    /// Level of the cartridge's /IRQ line.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    /// Granularity of PRG ROM banking, for tools that walk the ROM bank by bank.
+    fn prg_bank_size(&self) -> usize {
+        0x4000
+    }
+
+    /// CPU address a PRG bank is normally mapped at. By default the last bank is
+    /// fixed at the top of memory, where the vectors live, and the rest switch in at $8000.
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count { (0x10000 - self.prg_bank_size()) as u16 } else { 0x8000 }
+    }
+
+    /// PRG ROM offset currently mapped at a CPU address, if the address is in ROM.
+    fn prg_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    /// CHR ROM offset currently mapped at a pattern table address. None on CHR RAM boards.
+    fn chr_offset(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    /// Battery-backed memory (PRG RAM, EEPROM) to persist between runs, if the board has any.
+    fn save_data(&self) -> Option<&[u8]> {
+        None
+    }
+
+    /// Restores memory persisted from `save_data`.
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    /// Saves bank registers, counters and any on-board RAM.
+    fn save_state(&self, w: &mut StateWriter);
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+/// Builds the mapper for a cartridge from its iNES mapper number.
+pub fn create(cartridge: &Cartridge) -> Result<Box<dyn Mapper>, String> {
+    match cartridge.mapper {
+        0 => Ok(Box::new(Nrom::new(cartridge))),
+        5 => Ok(Box::new(Mmc5::new(cartridge))),
+        9 => Ok(Box::new(Mmc2::new(cartridge, mmc2::Chip::Mmc2))),
+        10 => Ok(Box::new(Mmc2::new(cartridge, mmc2::Chip::Mmc4))),
+        11 | 34 | 66 | 71 | 78 | 87 => Ok(Box::new(Discrete::new(cartridge)?)),
+        16 | 153 | 159 => Ok(Box::new(Bandai::new(cartridge))),
+        18 => Ok(Box::new(JalecoSs88006::new(cartridge)?)),
+        19 => Ok(Box::new(Namco163::new(cartridge))),
+        20 => Ok(Box::new(Fds::new(cartridge))),
+        21 | 22 | 23 | 25 => Ok(Box::new(Vrc4::new(cartridge))),
+        24 | 26 => Ok(Box::new(Vrc6::new(cartridge))),
+        32 => Ok(Box::new(IremG101::new(cartridge))),
+        33 => Ok(Box::new(Taito::new(cartridge, taito::Chip::Tc0190)?)),
+        48 => Ok(Box::new(Taito::new(cartridge, taito::Chip::Tc0690)?)),
+        58 | 200 | 201 | 202 | 203 | 212 | 225 => Ok(Box::new(Multicart::new(cartridge)?)),
+        69 => Ok(Box::new(Fme7::new(cartridge))),
+        75 => Ok(Box::new(Vrc1::new(cartridge))),
+        80 => Ok(Box::new(Taito::new(cartridge, taito::Chip::X1005)?)),
+        85 => Ok(Box::new(Vrc7::new(cartridge))),
+        n => Err(format!("ROM requires Mapper {}, which is not supported.", n)),
+    }
+}
+
+/// Checks that a board knows the cartridge's NES 2.0 submapper: one of `known`, or 0 when
+/// the header doesn't say. Other variants are wired differently, so running them as the
+/// board we do know would switch the wrong banks.
+fn check_submapper(cartridge: &Cartridge, known: &[u8]) -> Result<(), String> {
+    if cartridge.submapper == 0 || known.contains(&cartridge.submapper) {
+        Ok(())
+    } else {
+        Err(format!("ROM requires Mapper {} submapper {}, which is not supported.", cartridge.mapper, cartridge.submapper))
+    }
+}
+
+/// Saves a mirroring setting, for boards that switch between more than two.
+fn write_mirroring(w: &mut StateWriter, mirroring: Mirroring) {
+    w.write_u8(mirroring as u8);
+}
+
+fn read_mirroring(r: &mut StateReader) -> Result<Mirroring, StateError> {
+    match r.read_u8()? {
+        0 => Ok(Mirroring::Horizontal),
+        1 => Ok(Mirroring::Vertical),
+        2 => Ok(Mirroring::SingleScreenLower),
+        3 => Ok(Mirroring::SingleScreenUpper),
+        4 => Ok(Mirroring::FourScreen),
+        _ => Err(StateError::Invalid("mirroring")),
+    }
+}
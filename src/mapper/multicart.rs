@@ -0,0 +1,181 @@
+use super::{Mapper, check_submapper};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Board {
+    Mapper58,  // A~[1... .... MOCC CPPP]
+    Mapper200, // A~[1... .... .... MBBB]
+    Mapper201, // A~[1... .... BBBB BBBB]
+    Mapper202, // A~[1... .... .... OBBM]
+    Mapper203, // D~[PPPP PPCC]
+    Mapper212, // A~[1O.. .... .... MBBB]
+    Mapper225, // A~[1HMO PPPP PPCC CCCC]
+}
+
+/// Pirate multicarts that latch the address (or, on mapper 203, the data) of any write to
+/// $8000-$FFFF to pick a game: a 16KB PRG bank mirrored across both windows or a 32KB
+/// pair, an 8KB CHR bank, and usually the mirroring. NES 2.0 defines no submappers for
+/// any of them.
+pub struct Multicart {
+    board: Board,
+
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    ram: [u8; 4], // Mapper 225's four nibbles at $5800-$5FFF
+
+    prg_banks: [u8; 2], // 16KB units at $8000 and $C000
+    chr_bank: u8,
+    mirroring: Mirroring,
+}
+
+impl Multicart {
+    pub fn new(cartridge: &Cartridge) -> Result<Self, String> {
+        check_submapper(cartridge, &[])?;
+        let board = match cartridge.mapper {
+            58 => Board::Mapper58,
+            200 => Board::Mapper200,
+            201 => Board::Mapper201,
+            202 => Board::Mapper202,
+            203 => Board::Mapper203,
+            212 => Board::Mapper212,
+            _ => Board::Mapper225,
+        };
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        let mut multicart = Multicart {
+            board,
+            prg_rom: cartridge.prg_rom.clone(),
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            ram: [0; 4],
+            prg_banks: [0, 1],
+            chr_bank: 0,
+            mirroring: cartridge.mirroring,
+        };
+        // The latch powers up clear, which selects the first game
+        multicart.latch(0x8000, 0);
+        Ok(multicart)
+    }
+
+    fn select_prg(&mut self, bank: u8, prg_32k: bool) {
+        self.prg_banks = if prg_32k { [bank & !0x01, bank | 0x01] } else { [bank, bank] };
+    }
+
+    fn latch(&mut self, addr: u16, data: u8) {
+        let horizontal = match self.board {
+            Board::Mapper58 => {
+                self.select_prg((addr & 0x07) as u8, (addr & 0x40) == 0);
+                self.chr_bank = ((addr >> 3) & 0x07) as u8;
+                (addr & 0x80) != 0
+            }
+            Board::Mapper200 => {
+                self.select_prg((addr & 0x07) as u8, false);
+                self.chr_bank = (addr & 0x07) as u8;
+                (addr & 0x08) != 0
+            }
+            Board::Mapper201 => {
+                self.select_prg((addr as u8) << 1, true);
+                self.chr_bank = addr as u8;
+                self.mirroring == Mirroring::Horizontal
+            }
+            Board::Mapper202 => {
+                let bank = ((addr >> 1) & 0x07) as u8;
+                self.select_prg(bank, (addr & 0x09) == 0x09);
+                self.chr_bank = bank;
+                (addr & 0x01) != 0
+            }
+            Board::Mapper203 => {
+                self.select_prg(data >> 2, false);
+                self.chr_bank = data & 0x03;
+                self.mirroring == Mirroring::Horizontal
+            }
+            Board::Mapper212 => {
+                self.select_prg((addr & 0x07) as u8, (addr & 0x4000) != 0);
+                self.chr_bank = (addr & 0x07) as u8;
+                (addr & 0x08) != 0
+            }
+            Board::Mapper225 => {
+                let high = ((addr >> 8) & 0x40) as u8;
+                self.select_prg(high | ((addr >> 6) & 0x3F) as u8, (addr & 0x1000) == 0);
+                self.chr_bank = high | (addr & 0x3F) as u8;
+                (addr & 0x2000) != 0
+            }
+        };
+        self.mirroring = if horizontal { Mirroring::Horizontal } else { Mirroring::Vertical };
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x4000;
+        let bank = self.prg_banks[(addr >> 14) as usize & 1] as usize;
+        (bank % bank_count) * 0x4000 + (addr & 0x3FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        (self.chr_bank as usize * 0x2000 + (addr & 0x1FFF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Multicart {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match (self.board, addr) {
+            (Board::Mapper225, 0x5800..=0x5FFF) => self.ram[(addr & 0x03) as usize] & 0x0F,
+            // A protection check reads back bit 7 set unless A4 is
+            (Board::Mapper212, 0x6000..=0x7FFF) if (addr & 0x10) == 0 => 0x80,
+            (_, 0x8000..=0xFFFF) => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match (self.board, addr) {
+            (Board::Mapper225, 0x5800..=0x5FFF) => self.ram[(addr & 0x03) as usize] = data & 0x0F,
+            (_, 0x8000..=0xFFFF) => self.latch(addr, data),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.ram);
+        w.write_bytes(&self.prg_banks);
+        w.write_u8(self.chr_bank);
+        w.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.ram)?;
+        r.read_bytes(&mut self.prg_banks)?;
+        self.chr_bank = r.read_u8()?;
+        self.mirroring = if r.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+        Ok(())
+    }
+}
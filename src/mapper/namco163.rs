@@ -0,0 +1,314 @@
+use super::Mapper;
+use crate::apu;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// CPU cycles the sound unit spends on each channel before moving to the next
+const CHANNEL_PERIOD: u8 = 15;
+
+/// Mapper 19 (Namco 163): three switchable 8KB PRG banks, eight 1KB CHR banks, four
+/// nametables that can each come from CIRAM or CHR ROM, a 15-bit CPU cycle IRQ counter
+/// and up to eight wavetable channels.
+///
+/// The sound unit keeps its waveforms and channel registers in 128 bytes of internal RAM
+/// and updates one channel at a time, putting only that channel on its output. With
+/// many channels enabled this multiplexing is audible as a whine; the clean mix instead
+/// averages the channels' latest outputs, which is what the multiplexing sounds like
+/// from a distance.
+///
+/// CHR bank numbers $E0-$FF, which can map CIRAM into the pattern tables, are treated as
+/// ROM banks like the others.
+pub struct Namco163 {
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+    mirroring: Mirroring,
+
+    prg_banks: [u8; 3],       // $E000, $E800, $F000
+    chr_banks: [u8; 8],       // $8000-$BFFF
+    nametable_banks: [u8; 4], // $C000-$DFFF: $E0-$FF select CIRAM, anything else CHR ROM
+    ram_protect: u8,          // $F800: writes to PRG RAM need the top nibble to be $4
+    irq_counter: u16,         // $5000, $5800: 15 bits, plus the enable in bit 15
+    irq_pending: bool,
+
+    // Audio
+    ram: [u8; 128],      // $4800 through the address port
+    address: u8,         // $F800: bits 0-6 address, bit 7 auto-increment
+    sound_enabled: bool, // $E000 bit 6 clear
+    channel: u8,         // Channel being updated, counting down from 7
+    channel_cycles: u8,
+    outputs: [i16; 8],   // Each channel's latest output
+    multiplexed: bool,
+}
+
+impl Namco163 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Namco163 {
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            mirroring: cartridge.mirroring,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            nametable_banks: [0xE0, 0xE1, 0xE0, 0xE1],
+            ram_protect: 0,
+            irq_counter: 0,
+            irq_pending: false,
+            ram: [0; 128],
+            address: 0,
+            sound_enabled: true,
+            channel: 7,
+            channel_cycles: 0,
+            outputs: [0; 8],
+            multiplexed: true,
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+
+    fn prg_ram_writable(&self, addr: u16) -> bool {
+        let region = (addr as usize - 0x6000) >> 11;
+        (self.ram_protect & 0xF0) == 0x40 && (self.ram_protect & (1 << region)) == 0
+    }
+
+    fn channel_count(&self) -> u8 {
+        ((self.ram[0x7F] >> 4) & 0x07) + 1
+    }
+
+    /// Advances one channel's phase and works out its output.
+    fn clock_channel(&mut self, channel: usize) {
+        let base = 0x40 + channel * 8;
+        let registers = &self.ram[base..base + 8];
+        let frequency = registers[0] as u32 | (registers[2] as u32) << 8 | (registers[4] as u32 & 0x03) << 16;
+        let length = (256 - (registers[4] & 0xFC) as u32) << 16;
+        let mut phase = registers[1] as u32 | (registers[3] as u32) << 8 | (registers[5] as u32) << 16;
+        let offset = registers[6] as u32;
+        let volume = (registers[7] & 0x0F) as i16;
+
+        phase = (phase + frequency) % length;
+        let index = ((phase >> 16) + offset) & 0xFF;
+        let sample = (self.ram[index as usize >> 1] >> ((index & 1) * 4)) & 0x0F;
+        self.outputs[channel] = (sample as i16 - 8) * volume;
+
+        self.ram[base + 1] = phase as u8;
+        self.ram[base + 3] = (phase >> 8) as u8;
+        self.ram[base + 5] = (phase >> 16) as u8;
+    }
+}
+
+impl Mapper for Namco163 {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        let data = self.cpu_peek(addr);
+        if (0x4800..=0x4FFF).contains(&addr) && (self.address & 0x80) != 0 {
+            self.address = 0x80 | (self.address.wrapping_add(1) & 0x7F);
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4800..=0x4FFF => self.ram[(self.address & 0x7F) as usize],
+            0x5000..=0x57FF => self.irq_counter as u8,
+            0x5800..=0x5FFF => (self.irq_counter >> 8) as u8,
+            0x6000..=0x7FFF => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4800..=0x4FFF => {
+                self.ram[(self.address & 0x7F) as usize] = data;
+                if (self.address & 0x80) != 0 {
+                    self.address = 0x80 | (self.address.wrapping_add(1) & 0x7F);
+                }
+            }
+            0x5000..=0x57FF => {
+                self.irq_counter = (self.irq_counter & 0xFF00) | data as u16;
+                self.irq_pending = false;
+            }
+            0x5800..=0x5FFF => {
+                self.irq_counter = (self.irq_counter & 0x00FF) | (data as u16) << 8;
+                self.irq_pending = false;
+            }
+            0x6000..=0x7FFF if self.prg_ram_writable(addr) => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x8000..=0xBFFF => self.chr_banks[(addr as usize - 0x8000) >> 11] = data,
+            0xC000..=0xDFFF => self.nametable_banks[(addr as usize - 0xC000) >> 11] = data,
+            0xE000..=0xE7FF => {
+                self.prg_banks[0] = data & 0x3F;
+                self.sound_enabled = (data & 0x40) == 0;
+            }
+            0xE800..=0xEFFF => self.prg_banks[1] = data & 0x3F,
+            0xF000..=0xF7FF => self.prg_banks[2] = data & 0x3F,
+            0xF800..=0xFFFF => {
+                self.address = data;
+                self.ram_protect = data;
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn nametable_read(&mut self, addr: u16, ciram: &[u8; 2048]) -> u8 {
+        let bank = self.nametable_banks[(addr >> 10) as usize & 3] as usize;
+        let offset = (addr & 0x03FF) as usize;
+        if bank >= 0xE0 { ciram[(bank & 1) * 0x400 + offset] } else { self.chr[(bank * 0x400 + offset) % self.chr.len()] }
+    }
+
+    fn nametable_write(&mut self, addr: u16, data: u8, ciram: &mut [u8; 2048]) {
+        let bank = self.nametable_banks[(addr >> 10) as usize & 3] as usize;
+        let offset = (addr & 0x03FF) as usize;
+        if bank >= 0xE0 {
+            ciram[(bank & 1) * 0x400 + offset] = data;
+        } else if self.chr_is_ram {
+            let index = (bank * 0x400 + offset) % self.chr.len();
+            self.chr[index] = data;
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        if (self.irq_counter & 0x8000) != 0 && (self.irq_counter & 0x7FFF) != 0x7FFF {
+            self.irq_counter += 1;
+            if (self.irq_counter & 0x7FFF) == 0x7FFF {
+                self.irq_pending = true;
+            }
+        }
+
+        if !self.sound_enabled {
+            return;
+        }
+        self.channel_cycles += 1;
+        if self.channel_cycles == CHANNEL_PERIOD {
+            self.channel_cycles = 0;
+            let first = 8 - self.channel_count();
+            self.channel = if self.channel <= first { 7 } else { self.channel - 1 };
+            self.clock_channel(self.channel as usize);
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        if !self.sound_enabled {
+            return 0.0;
+        }
+        let level = if self.multiplexed {
+            self.outputs[self.channel as usize] as f32
+        } else {
+            let count = self.channel_count();
+            let sum: i16 = self.outputs[(8 - count) as usize..].iter().sum();
+            sum as f32 / count as f32
+        };
+        // A channel outputs its 4-bit sample times its 4-bit volume, so full scale is 15 * 15
+        level * (apu::PULSE_FULL_VOLUME / 225.0)
+    }
+
+    fn set_audio_multiplexing(&mut self, enabled: bool) {
+        self.multiplexed = enabled;
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count { 0xE000 } else { 0x8000 }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_banks);
+        w.write_bytes(&self.nametable_banks);
+        w.write_u8(self.ram_protect);
+        w.write_u16(self.irq_counter);
+        w.write_bool(self.irq_pending);
+        w.write_bytes(&self.ram);
+        w.write_u8(self.address);
+        w.write_bool(self.sound_enabled);
+        w.write_u8(self.channel);
+        w.write_u8(self.channel_cycles);
+        for &output in &self.outputs {
+            w.write_i16(output);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_banks)?;
+        r.read_bytes(&mut self.nametable_banks)?;
+        self.ram_protect = r.read_u8()?;
+        self.irq_counter = r.read_u16()?;
+        self.irq_pending = r.read_bool()?;
+        r.read_bytes(&mut self.ram)?;
+        self.address = r.read_u8()?;
+        self.sound_enabled = r.read_bool()?;
+        self.channel = r.read_u8()?;
+        self.channel_cycles = r.read_u8()?;
+        if self.channel > 7 || self.channel_cycles >= CHANNEL_PERIOD {
+            return Err(StateError::Invalid("N163 sound timing"));
+        }
+        for output in &mut self.outputs {
+            *output = r.read_i16()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+use super::fds_audio::FdsAudio;
+use super::{Fme7, Mapper, Mmc5, Namco163, Vrc6, Vrc7};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::nsf::{self, Nsf};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// The board an NSF player builds around a tune: its data in 4KB banks switched by
+/// $5FF8-$5FFF, 8KB of RAM at $6000, whichever expansion sound chips the tune declares,
+/// and a tiny idle loop for the player to park the CPU in between calls.
+///
+/// With FDS sound the tune runs from the RAM adapter's RAM instead: all of $6000-$FFFF
+/// is RAM, and a bank write ($5FF6-$5FFF) copies a 4KB bank into its slot. The other
+/// chips are the boards that carry them, fed only their sound registers.
+pub struct NsfBoard {
+    prg: Vec<u8>,    // The tune's data, padded so its banks line up on 4KB
+    ram: Vec<u8>,    // $6000-$7FFF, or $6000-$FFFF with FDS sound
+    banks: [u8; 10], // $5FF6-$5FFF: $6000-$FFFF in 4KB slots
+    fds_ram: bool,
+
+    vrc6: Option<Vrc6>,
+    vrc7: Option<Vrc7>,
+    fds: Option<FdsAudio>,
+    mmc5: Option<Mmc5>,
+    n163: Option<Namco163>,
+    sunsoft_5b: Option<Fme7>,
+}
+
+impl NsfBoard {
+    /// Where the player parks the CPU: `JMP` to itself.
+    pub const IDLE_LOOP: u16 = 0x4100;
+
+    pub fn new(tune: &Nsf) -> Self {
+        let fds_ram = (tune.chips & nsf::CHIP_FDS) != 0;
+        let mut banks = [0; 10];
+        // Without bankswitching the data is simply loaded at its address
+        let padding = match tune.banks {
+            Some(init) => {
+                banks[2..].copy_from_slice(&init);
+                banks[0] = init[6];
+                banks[1] = init[7];
+                (tune.load_addr & 0x0FFF) as usize
+            }
+            None if fds_ram => {
+                banks = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+                tune.load_addr as usize - 0x6000
+            }
+            None => {
+                banks[2..].copy_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7]);
+                tune.load_addr as usize - 0x8000
+            }
+        };
+        let mut prg = vec![0; padding];
+        prg.extend_from_slice(&tune.data);
+        prg.resize(prg.len().div_ceil(0x1000).max(1) * 0x1000, 0);
+
+        let mut mmc5 = (tune.chips & nsf::CHIP_MMC5 != 0).then(|| Mmc5::new(&sound_board(5)));
+        if let Some(mmc5) = &mut mmc5 {
+            mmc5.cpu_write(0x5104, 0x02); // ExRAM as plain RAM
+        }
+        let mut board = NsfBoard {
+            prg,
+            ram: vec![0; if fds_ram { 0xA000 } else { 0x2000 }],
+            banks,
+            fds_ram,
+            vrc6: (tune.chips & nsf::CHIP_VRC6 != 0).then(|| Vrc6::new(&sound_board(24))),
+            vrc7: (tune.chips & nsf::CHIP_VRC7 != 0).then(|| Vrc7::new(&sound_board(85))),
+            fds: fds_ram.then(FdsAudio::new),
+            mmc5,
+            n163: (tune.chips & nsf::CHIP_N163 != 0).then(|| Namco163::new(&sound_board(19))),
+            sunsoft_5b: (tune.chips & nsf::CHIP_5B != 0).then(|| Fme7::new(&sound_board(69))),
+        };
+        if fds_ram {
+            for slot in 0..10 {
+                board.load_ram_slot(slot);
+            }
+        }
+        board
+    }
+
+    fn bank_index(&self, slot: usize, addr: u16) -> usize {
+        let bank_count = self.prg.len() / 0x1000;
+        (self.banks[slot] as usize % bank_count) * 0x1000 + (addr & 0x0FFF) as usize
+    }
+
+    /// Copies the bank selected for a slot into the FDS RAM behind it.
+    fn load_ram_slot(&mut self, slot: usize) {
+        let start = self.bank_index(slot, 0);
+        self.ram[slot * 0x1000..(slot + 1) * 0x1000].copy_from_slice(&self.prg[start..start + 0x1000]);
+    }
+}
+
+/// A bare board of the given mapper, hosting one of the tune's sound chips.
+fn sound_board(mapper: u16) -> Cartridge {
+    Cartridge {
+        prg_rom: vec![0; 0x8000],
+        chr_rom: Vec::new(),
+        trainer: None,
+        mapper,
+        submapper: 0,
+        mirroring: Mirroring::Vertical,
+        battery: false,
+        prg_ram_size: 0x2000,
+        prg_nvram_size: 0,
+        chr_ram_size: 0x2000,
+        region: None,
+        disk_sides: Vec::new(),
+    }
+}
+
+fn is_mmc5_register(addr: u16) -> bool {
+    matches!(addr, 0x5000..=0x5015 | 0x5205..=0x5206 | 0x5C00..=0x5FF5)
+}
+
+impl Mapper for NsfBoard {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        if let Some(n163) = &mut self.n163
+            && (0x4800..=0x4FFF).contains(&addr)
+        {
+            return n163.cpu_read(addr);
+        }
+        if let Some(mmc5) = &mut self.mmc5
+            && is_mmc5_register(addr)
+        {
+            return mmc5.cpu_read(addr);
+        }
+        self.cpu_peek(addr)
+    }
+
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        if let Some(fds) = &self.fds
+            && matches!(addr, 0x4040..=0x407F | 0x4090 | 0x4092)
+        {
+            return fds.read(addr);
+        }
+        if let Some(n163) = &self.n163
+            && (0x4800..=0x4FFF).contains(&addr)
+        {
+            return n163.cpu_peek(addr);
+        }
+        if let Some(mmc5) = &self.mmc5
+            && is_mmc5_register(addr)
+        {
+            return mmc5.cpu_peek(addr);
+        }
+        match addr {
+            // JMP IDLE_LOOP
+            0x4100 => 0x4C,
+            0x4101 => Self::IDLE_LOOP as u8,
+            0x4102 => (Self::IDLE_LOOP >> 8) as u8,
+            0x6000..=0xFFFF if self.fds_ram => self.ram[addr as usize - 0x6000],
+            0x6000..=0x7FFF => self.ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => {
+                let slot = (addr >> 12) as usize - 6;
+                self.prg[self.bank_index(slot, addr)]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x5FF6..=0x5FF7 if !self.fds_ram => {}
+            0x5FF6..=0x5FFF => {
+                let slot = (addr - 0x5FF6) as usize;
+                self.banks[slot] = data;
+                if self.fds_ram {
+                    self.load_ram_slot(slot);
+                }
+            }
+            0x6000..=0xDFFF if self.fds_ram => self.ram[addr as usize - 0x6000] = data,
+            0x6000..=0x7FFF => self.ram[(addr & 0x1FFF) as usize] = data,
+            _ => {}
+        }
+
+        // Several chips can share an address; each sees the registers it decodes
+        if let Some(fds) = &mut self.fds
+            && (0x4040..=0x408A).contains(&addr)
+        {
+            fds.write(addr, data);
+        }
+        if let Some(n163) = &mut self.n163
+            && matches!(addr, 0x4800..=0x4FFF | 0xF800..=0xFFFF)
+        {
+            n163.cpu_write(addr, data);
+        }
+        if let Some(mmc5) = &mut self.mmc5
+            && is_mmc5_register(addr)
+        {
+            mmc5.cpu_write(addr, data);
+        }
+        if let Some(vrc6) = &mut self.vrc6
+            && matches!(addr, 0x9000..=0x9003 | 0xA000..=0xA002 | 0xB000..=0xB002)
+        {
+            vrc6.cpu_write(addr, data);
+        }
+        if let Some(vrc7) = &mut self.vrc7
+            && matches!(addr, 0x9010 | 0x9030)
+        {
+            vrc7.cpu_write(addr, data);
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b
+            && addr >= 0xC000
+        {
+            sunsoft_5b.cpu_write(addr, data);
+        }
+    }
+
+    fn chr_read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+
+    fn chr_write(&mut self, _addr: u16, _data: u8) {}
+
+    fn mirroring(&self) -> Mirroring {
+        Mirroring::Vertical
+    }
+
+    fn cpu_tick(&mut self) {
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.cpu_tick();
+        }
+        if let Some(vrc7) = &mut self.vrc7 {
+            vrc7.cpu_tick();
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.clock();
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.cpu_tick();
+        }
+        if let Some(n163) = &mut self.n163 {
+            n163.cpu_tick();
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+            sunsoft_5b.cpu_tick();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        self.vrc6.as_ref().map_or(0.0, |chip| chip.audio_output())
+            + self.vrc7.as_ref().map_or(0.0, |chip| chip.audio_output())
+            + self.fds.as_ref().map_or(0.0, |chip| chip.output())
+            + self.mmc5.as_ref().map_or(0.0, |chip| chip.audio_output())
+            + self.n163.as_ref().map_or(0.0, |chip| chip.audio_output())
+            + self.sunsoft_5b.as_ref().map_or(0.0, |chip| chip.audio_output())
+    }
+
+    fn set_audio_multiplexing(&mut self, enabled: bool) {
+        if let Some(n163) = &mut self.n163 {
+            n163.set_audio_multiplexing(enabled);
+        }
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x1000
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.ram);
+        w.write_bytes(&self.banks);
+        if let Some(vrc6) = &self.vrc6 {
+            vrc6.save_state(w);
+        }
+        if let Some(vrc7) = &self.vrc7 {
+            vrc7.save_state(w);
+        }
+        if let Some(fds) = &self.fds {
+            fds.save_state(w);
+        }
+        if let Some(mmc5) = &self.mmc5 {
+            mmc5.save_state(w);
+        }
+        if let Some(n163) = &self.n163 {
+            n163.save_state(w);
+        }
+        if let Some(sunsoft_5b) = &self.sunsoft_5b {
+            sunsoft_5b.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.ram)?;
+        r.read_bytes(&mut self.banks)?;
+        if let Some(vrc6) = &mut self.vrc6 {
+            vrc6.load_state(r)?;
+        }
+        if let Some(vrc7) = &mut self.vrc7 {
+            vrc7.load_state(r)?;
+        }
+        if let Some(fds) = &mut self.fds {
+            fds.load_state(r)?;
+        }
+        if let Some(mmc5) = &mut self.mmc5 {
+            mmc5.load_state(r)?;
+        }
+        if let Some(n163) = &mut self.n163 {
+            n163.load_state(r)?;
+        }
+        if let Some(sunsoft_5b) = &mut self.sunsoft_5b {
+            sunsoft_5b.load_state(r)?;
+        }
+        Ok(())
+    }
+}
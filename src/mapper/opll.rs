@@ -0,0 +1,451 @@
+use std::f32::consts::PI;
+
+use crate::apu;
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// The VRC7 runs its synthesizer off a 3.58MHz crystal, twice the NTSC CPU clock,
+// and produces one sample every 72 of those cycles (about 49.7kHz).
+const CPU_CYCLES_PER_SAMPLE: u8 = 36;
+
+// Built-in instruments 1-15, in the custom instrument's register layout ($00-$07).
+// Instrument 0 is the custom one.
+const PATCHES: [[u8; 8]; 15] = [
+    [0x03, 0x21, 0x05, 0x06, 0xE8, 0x81, 0x42, 0x27], // Buzzy bell
+    [0x13, 0x41, 0x14, 0x0D, 0xD8, 0xF6, 0x23, 0x12], // Guitar
+    [0x11, 0x11, 0x08, 0x08, 0xFA, 0xB2, 0x20, 0x12], // Wurly
+    [0x31, 0x61, 0x0C, 0x07, 0xA8, 0x64, 0x61, 0x27], // Flute
+    [0x32, 0x21, 0x1E, 0x06, 0xE1, 0x76, 0x01, 0x28], // Clarinet
+    [0x02, 0x01, 0x06, 0x00, 0xA3, 0xE2, 0xF4, 0xF4], // Synth
+    [0x21, 0x61, 0x1D, 0x07, 0x82, 0x81, 0x11, 0x07], // Trumpet
+    [0x23, 0x21, 0x22, 0x17, 0xA2, 0x72, 0x01, 0x17], // Organ
+    [0x35, 0x11, 0x25, 0x00, 0x40, 0x73, 0x72, 0x01], // Bells
+    [0xB5, 0x01, 0x0F, 0x0F, 0xA8, 0xA5, 0x51, 0x02], // Vibes
+    [0x17, 0xC1, 0x24, 0x07, 0xF8, 0xF8, 0x22, 0x12], // Vibraphone
+    [0x71, 0x23, 0x11, 0x06, 0x65, 0x74, 0x18, 0x16], // Tutti
+    [0x01, 0x02, 0xD3, 0x05, 0xC9, 0x95, 0x03, 0x02], // Fretless
+    [0x61, 0x63, 0x0C, 0x00, 0x94, 0xC0, 0x33, 0xF6], // Synth bass
+    [0x21, 0x72, 0x0D, 0x00, 0xC1, 0xD5, 0x56, 0x06], // Sweep
+];
+
+// Frequency multipliers, doubled so that the x1/2 setting stays an integer
+const MULTIPLIERS: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+
+// Key scale level by the top four F-number bits, in 0.75dB units at 6dB/octave
+const KSL_LEVELS: [u8; 16] = [0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64];
+
+// Attenuation is counted in 0.375dB steps throughout; the envelope covers 48dB.
+const MAX_LEVEL: u8 = 127;
+const ATTENUATION_STEPS: usize = 512;
+
+// Operator outputs are 13-bit signed values. Fed into another operator's phase, which
+// has 1024 steps per cycle, a full-scale output shifts it by four cycles.
+const OUTPUT_SCALE: f32 = 4095.0;
+
+// LFOs: tremolo is a 3.7Hz triangle of up to 4.8dB; vibrato steps through 8 positions at 6.1Hz.
+const AM_STEP_SAMPLES: u16 = 512;
+const AM_STEPS: u8 = 26;
+const PM_STEP_SAMPLES: u16 = 1024;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+/// One operator's view of an instrument: the bits of the patch that apply to it.
+struct Params {
+    tremolo: bool,
+    vibrato: bool,
+    sustained: bool, // Envelope holds at the sustain level instead of carrying on into release
+    key_scale_rate: bool,
+    multiplier: u32,
+    key_scale_level: u8,
+    rectified: bool, // Negative half of the sine wave is cut off
+    attack: u8,
+    decay: u8,
+    sustain_level: u8,
+    release: u8,
+}
+
+impl Params {
+    fn new(patch: &[u8; 8], carrier: bool) -> Self {
+        let i = carrier as usize;
+        Params {
+            tremolo: (patch[i] & 0x80) != 0,
+            vibrato: (patch[i] & 0x40) != 0,
+            sustained: (patch[i] & 0x20) != 0,
+            key_scale_rate: (patch[i] & 0x10) != 0,
+            multiplier: MULTIPLIERS[(patch[i] & 0x0F) as usize],
+            key_scale_level: patch[2 + i] >> 6,
+            rectified: (patch[3] & if carrier { 0x10 } else { 0x08 }) != 0,
+            attack: patch[4 + i] >> 4,
+            decay: patch[4 + i] & 0x0F,
+            sustain_level: patch[6 + i] >> 4,
+            release: patch[6 + i] & 0x0F,
+        }
+    }
+}
+
+struct Operator {
+    phase: u32, // 19 bits, of which the top 10 index the sine table
+    level: u8,  // Envelope attenuation
+    stage: Stage,
+    envelope_counter: u32,
+}
+
+impl Operator {
+    fn new() -> Self {
+        Operator { phase: 0, level: MAX_LEVEL, stage: Stage::Release, envelope_counter: 0 }
+    }
+
+    fn key_on(&mut self) {
+        self.phase = 0;
+        self.stage = Stage::Attack;
+        self.envelope_counter = 0;
+    }
+
+    /// Advances the envelope by one sample. `release` is the rate to use once the key is off.
+    fn clock_envelope(&mut self, params: &Params, key_scale: u8, release: u8) {
+        let rate = match self.stage {
+            Stage::Attack => params.attack,
+            Stage::Decay => params.decay,
+            Stage::Sustain if params.sustained => 0,
+            Stage::Sustain => params.release,
+            Stage::Release => release,
+        };
+        if rate == 0 {
+            return;
+        }
+        let rate = (rate * 4 + key_scale).min(63);
+        if self.stage == Stage::Attack && rate >= 60 {
+            self.level = 0;
+        } else {
+            // Every four rates double the speed, with quarter steps in between
+            self.envelope_counter += (4 + (rate as u32 & 3)) << (rate >> 2);
+            while self.envelope_counter >= 0x10000 {
+                self.envelope_counter -= 0x10000;
+                self.level = match self.stage {
+                    Stage::Attack => self.level.saturating_sub((self.level >> 2) + 1),
+                    _ => (self.level + 1).min(MAX_LEVEL),
+                };
+            }
+        }
+        match self.stage {
+            Stage::Attack if self.level == 0 => self.stage = Stage::Decay,
+            Stage::Decay if self.level >= params.sustain_level * 8 => self.stage = Stage::Sustain,
+            _ => {}
+        }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.phase);
+        w.write_u8(self.level);
+        w.write_u8(self.stage as u8);
+        w.write_u32(self.envelope_counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.phase = r.read_u32()? & 0x7FFFF;
+        self.level = r.read_u8()?.min(MAX_LEVEL);
+        self.stage = match r.read_u8()? {
+            0 => Stage::Attack,
+            1 => Stage::Decay,
+            2 => Stage::Sustain,
+            3 => Stage::Release,
+            _ => return Err(StateError::Invalid("OPLL envelope stage")),
+        };
+        self.envelope_counter = r.read_u32()?;
+        Ok(())
+    }
+}
+
+struct Channel {
+    frequency_low: u8, // $10-$15: F-number bits 0-7
+    control: u8,       // $20-$25: bit 0 F-number bit 8, 1-3 block, 4 key on, 5 sustain
+    instrument: u8,    // $30-$35: instrument in the top nibble, volume (attenuation) in the bottom
+    modulator: Operator,
+    carrier: Operator,
+    feedback: [i16; 2], // The modulator's last two outputs
+}
+
+impl Channel {
+    fn new() -> Self {
+        Channel {
+            frequency_low: 0,
+            control: 0,
+            instrument: 0,
+            modulator: Operator::new(),
+            carrier: Operator::new(),
+            feedback: [0; 2],
+        }
+    }
+
+    fn f_number(&self) -> u16 {
+        self.frequency_low as u16 | ((self.control as u16 & 0x01) << 8)
+    }
+
+    fn block(&self) -> u8 {
+        (self.control >> 1) & 0x07
+    }
+
+    fn key_on(&self) -> bool {
+        (self.control & 0x10) != 0
+    }
+
+    fn sustain(&self) -> bool {
+        (self.control & 0x20) != 0
+    }
+
+    fn write_control(&mut self, data: u8) {
+        let was_on = self.key_on();
+        self.control = data;
+        if self.key_on() && !was_on {
+            self.modulator.key_on();
+            self.carrier.key_on();
+        } else if !self.key_on() && was_on {
+            self.modulator.stage = Stage::Release;
+            self.carrier.stage = Stage::Release;
+        }
+    }
+}
+
+/// The sound half of the VRC7: a cut-down Yamaha YM2413 (OPLL) with six two-operator FM
+/// channels, no rhythm mode and its own set of built-in instruments. Each channel's
+/// modulator, optionally fed back into itself, bends the phase of its carrier, whose
+/// output is what's heard. The synthesizer is clocked at its own rate, and its latest
+/// sample is held for the console's mixer, so the output doesn't depend on the
+/// host's sample rate.
+pub struct Opll {
+    address: u8,     // $9010
+    custom: [u8; 8], // $00-$07
+    channels: [Channel; 6],
+    cycles: u8,
+    am_counter: u16,
+    am_step: u8,
+    pm_counter: u16,
+    pm_step: u8,
+    output: i32, // Sum of the carriers' latest outputs
+
+    sine: Vec<f32>,
+    amplitudes: Vec<f32>, // Linear gain by attenuation in 0.375dB steps
+}
+
+impl Opll {
+    pub fn new() -> Self {
+        Opll {
+            address: 0,
+            custom: [0; 8],
+            channels: std::array::from_fn(|_| Channel::new()),
+            cycles: 0,
+            am_counter: 0,
+            am_step: 0,
+            pm_counter: 0,
+            pm_step: 0,
+            output: 0,
+            sine: (0..1024).map(|i| ((i as f32 + 0.5) * PI / 512.0).sin()).collect(),
+            amplitudes: (0..ATTENUATION_STEPS).map(|i| 10f32.powf(i as f32 * -0.375 / 20.0)).collect(),
+        }
+    }
+
+    /// Silences every channel and clears the registers, as $E000 bit 6 does.
+    pub fn reset(&mut self) {
+        self.address = 0;
+        self.custom = [0; 8];
+        self.channels = std::array::from_fn(|_| Channel::new());
+        self.output = 0;
+    }
+
+    pub fn write_address(&mut self, data: u8) {
+        self.address = data;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        let channel = (self.address & 0x0F) as usize;
+        match self.address {
+            0x00..=0x07 => self.custom[self.address as usize] = data,
+            0x10..=0x15 => self.channels[channel].frequency_low = data,
+            0x20..=0x25 => self.channels[channel].write_control(data),
+            0x30..=0x35 => self.channels[channel].instrument = data,
+            _ => {}
+        }
+    }
+
+    /// Clocked once per CPU cycle.
+    pub fn clock(&mut self) {
+        self.cycles += 1;
+        if self.cycles == CPU_CYCLES_PER_SAMPLE {
+            self.cycles = 0;
+            self.generate_sample();
+        }
+    }
+
+    /// The latest sample in the APU's mix. Channels swing between -OUTPUT_SCALE and
+    /// +OUTPUT_SCALE, twice the range of a pulse, which only goes positive.
+    pub fn output(&self) -> f32 {
+        self.output as f32 * (apu::PULSE_FULL_VOLUME / 2.0 / OUTPUT_SCALE)
+    }
+
+    fn generate_sample(&mut self) {
+        self.am_counter += 1;
+        if self.am_counter == AM_STEP_SAMPLES {
+            self.am_counter = 0;
+            self.am_step = (self.am_step + 1) % AM_STEPS;
+        }
+        self.pm_counter += 1;
+        if self.pm_counter == PM_STEP_SAMPLES {
+            self.pm_counter = 0;
+            self.pm_step = (self.pm_step + 1) & 0x07;
+        }
+        let tremolo = if self.am_step < AM_STEPS / 2 + 1 { self.am_step } else { AM_STEPS - self.am_step };
+
+        let mut output = 0;
+        for i in 0..self.channels.len() {
+            output += self.clock_channel(i, tremolo) as i32;
+        }
+        self.output = output;
+    }
+
+    /// Runs a channel for one sample and returns its carrier's output.
+    fn clock_channel(&mut self, index: usize, tremolo: u8) -> i16 {
+        let channel = &self.channels[index];
+        let instrument = channel.instrument >> 4;
+        let patch = if instrument == 0 { self.custom } else { PATCHES[instrument as usize - 1] };
+        let modulator = Params::new(&patch, false);
+        let carrier = Params::new(&patch, true);
+
+        let f_number = channel.f_number();
+        let block = channel.block();
+        let release = |params: &Params| match (params.sustained, channel.sustain()) {
+            (_, true) => 5,
+            (true, false) => params.release,
+            (false, false) => 7,
+        };
+        let modulator_release = release(&modulator);
+        let carrier_release = release(&carrier);
+        let modulator_step = self.phase_step(&modulator, f_number, block);
+        let carrier_step = self.phase_step(&carrier, f_number, block);
+        let modulator_level = self.attenuation(&modulator, f_number, block, tremolo) + (patch[2] & 0x3F) as usize * 2;
+        let carrier_level = self.attenuation(&carrier, f_number, block, tremolo) + (channel.instrument & 0x0F) as usize * 8;
+
+        let channel = &mut self.channels[index];
+        let key_scale = |params: &Params| {
+            if params.key_scale_rate { (block << 1) | (f_number >> 8) as u8 } else { block >> 1 }
+        };
+        channel.modulator.clock_envelope(&modulator, key_scale(&modulator), modulator_release);
+        channel.carrier.clock_envelope(&carrier, key_scale(&carrier), carrier_release);
+        channel.modulator.phase = (channel.modulator.phase + modulator_step) & 0x7FFFF;
+        channel.carrier.phase = (channel.carrier.phase + carrier_step) & 0x7FFFF;
+
+        let feedback = match patch[3] & 0x07 {
+            0 => 0,
+            amount => (channel.feedback[0] as i32 + channel.feedback[1] as i32) >> (10 - amount),
+        };
+        let modulator_out = operator_output(
+            &self.sine,
+            &self.amplitudes,
+            (channel.modulator.phase >> 9) as i32 + feedback,
+            channel.modulator.level as usize + modulator_level,
+            modulator.rectified,
+        );
+        channel.feedback = [channel.feedback[1], modulator_out];
+        if channel.carrier.level == MAX_LEVEL {
+            return 0;
+        }
+        operator_output(
+            &self.sine,
+            &self.amplitudes,
+            (channel.carrier.phase >> 9) as i32 + modulator_out as i32,
+            channel.carrier.level as usize + carrier_level,
+            carrier.rectified,
+        )
+    }
+
+    /// Phase increment per sample, with vibrato applied.
+    fn phase_step(&self, params: &Params, f_number: u16, block: u8) -> u32 {
+        let mut f_number = f_number as i32 * 2;
+        if params.vibrato {
+            // Up to about 13 cents either way, deeper for higher notes
+            let depth = (f_number >> 7) & 0x07;
+            f_number += match self.pm_step {
+                1 | 3 => depth >> 1,
+                2 => depth,
+                5 | 7 => -(depth >> 1),
+                6 => -depth,
+                _ => 0,
+            };
+        }
+        (((f_number as u32) << block) * params.multiplier) >> 2
+    }
+
+    /// Attenuation from key scaling and tremolo, in 0.375dB steps.
+    fn attenuation(&self, params: &Params, f_number: u16, block: u8, tremolo: u8) -> usize {
+        let mut attenuation = 0;
+        if params.key_scale_level != 0 {
+            let level = (KSL_LEVELS[(f_number >> 5) as usize] as i32 - 8 * (8 - block as i32)).max(0) as usize;
+            attenuation += (level * 2) >> (3 - params.key_scale_level);
+        }
+        if params.tremolo {
+            attenuation += tremolo as usize;
+        }
+        attenuation
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.address);
+        w.write_bytes(&self.custom);
+        for channel in &self.channels {
+            w.write_u8(channel.frequency_low);
+            w.write_u8(channel.control);
+            w.write_u8(channel.instrument);
+            channel.modulator.save_state(w);
+            channel.carrier.save_state(w);
+            w.write_i16(channel.feedback[0]);
+            w.write_i16(channel.feedback[1]);
+        }
+        w.write_u8(self.cycles);
+        w.write_u16(self.am_counter);
+        w.write_u8(self.am_step);
+        w.write_u16(self.pm_counter);
+        w.write_u8(self.pm_step);
+        w.write_u32(self.output as u32);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.address = r.read_u8()?;
+        r.read_bytes(&mut self.custom)?;
+        for channel in &mut self.channels {
+            channel.frequency_low = r.read_u8()?;
+            channel.control = r.read_u8()?;
+            channel.instrument = r.read_u8()?;
+            channel.modulator.load_state(r)?;
+            channel.carrier.load_state(r)?;
+            channel.feedback = [r.read_i16()?, r.read_i16()?];
+        }
+        self.cycles = r.read_u8()?;
+        self.am_counter = r.read_u16()?;
+        self.am_step = r.read_u8()?;
+        self.pm_counter = r.read_u16()?;
+        self.pm_step = r.read_u8()?;
+        if self.cycles >= CPU_CYCLES_PER_SAMPLE
+            || self.am_counter >= AM_STEP_SAMPLES
+            || self.am_step >= AM_STEPS
+            || self.pm_counter >= PM_STEP_SAMPLES
+            || self.pm_step >= 8
+        {
+            return Err(StateError::Invalid("OPLL timing"));
+        }
+        self.output = r.read_u32()? as i32;
+        Ok(())
+    }
+}
+
+/// One operator's output for a phase (1024 steps per cycle) and total attenuation.
+fn operator_output(sine: &[f32], amplitudes: &[f32], phase: i32, attenuation: usize, rectified: bool) -> i16 {
+    let wave = sine[(phase & 0x3FF) as usize];
+    if (rectified && wave < 0.0) || attenuation >= amplitudes.len() {
+        return 0;
+    }
+    (wave * amplitudes[attenuation] * OUTPUT_SCALE) as i16
+}
@@ -0,0 +1,253 @@
+use super::{Mapper, check_submapper};
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Chip {
+    Tc0190, // Mapper 33
+    Tc0690, // Mapper 48: TC0190 plus a scanline IRQ
+    X1005,  // Mapper 80: registers in $7EF0-$7EFF, and 128 bytes of battery RAM
+}
+
+/// Taito's mappers share a layout: 8KB PRG banks with the last fixed, and two 2KB CHR
+/// banks for $0000-$0FFF next to four 1KB banks for $1000-$1FFF. None of the three has
+/// NES 2.0 submappers.
+///
+/// The TC0690's IRQ counts scanlines by watching PPU A12 rise, as it does once a line
+/// when backgrounds come from $0000 and sprites from $1000. Rises less than three CPU
+/// cycles after the last fall are ignored, so the sprite fetches only count once.
+pub struct Taito {
+    chip: Chip,
+
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    ram: [u8; 128], // X1-005
+    battery: bool,
+
+    prg_banks: [u8; 3], // TC0190/TC0690 only switch the first two
+    chr_2k: [u8; 2],
+    chr_1k: [u8; 4],
+    mirroring: Mirroring,
+    ram_enable: u8, // X1-005 $7EF8: $A3 unlocks the RAM
+
+    // TC0690 IRQ
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_cycles: u8,
+}
+
+impl Taito {
+    pub fn new(cartridge: &Cartridge, chip: Chip) -> Result<Self, String> {
+        check_submapper(cartridge, &[])?;
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Ok(Taito {
+            chip,
+            prg_rom: cartridge.prg_rom.clone(),
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            ram: [0; 128],
+            battery: cartridge.battery,
+            prg_banks: [0, 1, 2],
+            chr_2k: [0, 1],
+            chr_1k: [4, 5, 6, 7],
+            mirroring: cartridge.mirroring,
+            ram_enable: 0,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: 0,
+        })
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match (self.chip, addr) {
+            (Chip::X1005, 0x8000..=0xDFFF) => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            (_, 0x8000..=0xBFFF) => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            (_, 0xC000..=0xDFFF) => bank_count.saturating_sub(2),
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let offset = if addr < 0x1000 {
+            self.chr_2k[(addr >> 11) as usize & 1] as usize * 0x0800 + (addr & 0x07FF) as usize
+        } else {
+            self.chr_1k[(addr >> 10) as usize & 3] as usize * 0x0400 + (addr & 0x03FF) as usize
+        };
+        offset % self.chr.len()
+    }
+
+    fn clock_scanline(&mut self) {
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Taito {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x7F00..=0x7FFF if self.chip == Chip::X1005 && self.ram_enable == 0xA3 => self.ram[(addr & 0x7F) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if self.chip == Chip::X1005 {
+            match addr {
+                0x7EF0..=0x7EF1 => self.chr_2k[(addr & 1) as usize] = data >> 1,
+                0x7EF2..=0x7EF5 => self.chr_1k[(addr - 0x7EF2) as usize] = data,
+                0x7EF6..=0x7EF7 => {
+                    self.mirroring = if (data & 0x01) == 0 { Mirroring::Horizontal } else { Mirroring::Vertical };
+                }
+                0x7EF8..=0x7EF9 => self.ram_enable = data,
+                0x7EFA..=0x7EFF => self.prg_banks[((addr - 0x7EFA) >> 1) as usize] = data,
+                0x7F00..=0x7FFF if self.ram_enable == 0xA3 => self.ram[(addr & 0x7F) as usize] = data,
+                _ => {}
+            }
+            return;
+        }
+        match addr & 0xE003 {
+            0x8000 => {
+                self.prg_banks[0] = data & 0x3F;
+                if self.chip == Chip::Tc0190 {
+                    self.mirroring = if (data & 0x40) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+                }
+            }
+            0x8001 => self.prg_banks[1] = data & 0x3F,
+            0x8002..=0x8003 => self.chr_2k[(addr & 1) as usize] = data,
+            0xA000..=0xA003 => self.chr_1k[(addr & 3) as usize] = data,
+            0xC000 if self.chip == Chip::Tc0690 => self.irq_latch = data ^ 0xFF, // The chip counts the complement
+            0xC001 if self.chip == Chip::Tc0690 => self.irq_reload = true,
+            0xC002 if self.chip == Chip::Tc0690 => self.irq_enabled = true,
+            0xC003 if self.chip == Chip::Tc0690 => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            0xE000 if self.chip == Chip::Tc0690 => {
+                self.mirroring = if (data & 0x40) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        if self.chip == Chip::Tc0690 {
+            let high = (addr & 0x1000) != 0;
+            if high && !self.a12_high && self.a12_low_cycles >= 3 {
+                self.clock_scanline();
+            }
+            if !high && self.a12_high {
+                self.a12_low_cycles = 0;
+            }
+            self.a12_high = high;
+        }
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_tick(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        match (self.chip, bank_count - bank) {
+            (_, 1) => 0xE000,
+            (Chip::Tc0190 | Chip::Tc0690, 2) => 0xC000,
+            _ => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery && self.chip == Chip::X1005 { Some(&self.ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.ram.len());
+        self.ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.ram);
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_2k);
+        w.write_bytes(&self.chr_1k);
+        w.write_bool(self.mirroring == Mirroring::Horizontal);
+        w.write_u8(self.ram_enable);
+        w.write_u8(self.irq_latch);
+        w.write_u8(self.irq_counter);
+        w.write_bool(self.irq_reload);
+        w.write_bool(self.irq_enabled);
+        w.write_bool(self.irq_pending);
+        w.write_bool(self.a12_high);
+        w.write_u8(self.a12_low_cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.ram)?;
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_2k)?;
+        r.read_bytes(&mut self.chr_1k)?;
+        self.mirroring = if r.read_bool()? { Mirroring::Horizontal } else { Mirroring::Vertical };
+        self.ram_enable = r.read_u8()?;
+        self.irq_latch = r.read_u8()?;
+        self.irq_counter = r.read_u8()?;
+        self.irq_reload = r.read_bool()?;
+        self.irq_enabled = r.read_bool()?;
+        self.irq_pending = r.read_bool()?;
+        self.a12_high = r.read_bool()?;
+        self.a12_low_cycles = r.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,390 @@
+use crate::assembler;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::console::{Console, Region};
+
+// Each test ROM's 8KB PRG banks start with their own number and end with the same
+// program, so it keeps running whatever gets switched in under it. The program writes
+// the board's registers and calls `sample`, which copies the bank numbers it finds at
+// $8000, $A000, $C000 and $E000 to the next four bytes of RAM. CHR comes in 1KB banks,
+// each also starting with its number.
+const HARNESS: &str = "
+        .org $FF00
+    reset:
+        sei
+        ldx #$FF
+        txs
+        inx
+        jsr test
+    done:
+        jmp done
+    sample:
+        lda $8000
+        sta $00,x
+        lda $A000
+        sta $01,x
+        lda $C000
+        sta $02,x
+        lda $E000
+        sta $03,x
+        inx
+        inx
+        inx
+        inx
+        rts
+    test:
+";
+
+/// Builds a board with `prg_banks` 8KB PRG banks and `chr_banks` 1KB CHR banks (none for
+/// CHR RAM), and runs `program` on it until it's done.
+fn run(mapper: u16, submapper: u8, prg_banks: usize, chr_banks: usize, program: &str) -> Console {
+    let source = format!("{}{}\n        rts\n        .org $FFFA\n        .word reset, reset, reset\n", HARNESS, program);
+    let code = assembler::assemble(&source, 0xFF00, false).unwrap();
+    let mut prg = Vec::new();
+    for bank in 0..prg_banks {
+        let mut data = vec![0xFF; 0x2000];
+        data[0] = bank as u8;
+        for (start, bytes) in &code {
+            let offset = *start as usize - 0xE000;
+            data[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+        prg.extend(data);
+    }
+    let mut chr = vec![0; chr_banks * 0x400];
+    for bank in 0..chr_banks {
+        chr[bank * 0x400] = bank as u8;
+    }
+
+    let mut console = Console::new(&Cartridge::for_test(mapper, submapper, prg, chr), Region::Ntsc).unwrap();
+    console.reset();
+    for _ in 0..10_000 {
+        let pc = console.cpu.pc;
+        console.step();
+        if console.cpu.pc == pc {
+            return console;
+        }
+    }
+    panic!("test program for mapper {} never finished", mapper);
+}
+
+/// The PRG banks the nth call to `sample` found at $8000, $A000, $C000 and $E000.
+fn sampled(console: &Console, n: u16) -> [u8; 4] {
+    std::array::from_fn(|i| console.peek(n * 4 + i as u16))
+}
+
+/// The 1KB CHR banks mapped at $0000-$1FFF now.
+fn chr_banks(console: &mut Console) -> [u8; 8] {
+    std::array::from_fn(|i| console.bus.mapper.chr_read(i as u16 * 0x400))
+}
+
+#[test]
+fn mapper_11_switches_32k_prg_and_8k_chr() {
+    let mut console = run(11, 0, 16, 32, "lda #$21\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 6, 7]);
+    assert_eq!(chr_banks(&mut console), [16, 17, 18, 19, 20, 21, 22, 23]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_34_bnrom_switches_32k_prg() {
+    let console = run(34, 2, 16, 0, "lda #$02\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [8, 9, 10, 11]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_34_nina001_switches_prg_and_4k_chr() {
+    let mut console = run(34, 1, 8, 64, "lda #1\n sta $7FFD\n lda #3\n sta $7FFE\n lda #5\n sta $7FFF\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 6, 7]);
+    assert_eq!(chr_banks(&mut console), [12, 13, 14, 15, 20, 21, 22, 23]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_66_switches_32k_prg_and_8k_chr() {
+    let mut console = run(66, 0, 16, 32, "lda #$12\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 6, 7]);
+    assert_eq!(chr_banks(&mut console), [16, 17, 18, 19, 20, 21, 22, 23]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+const CAMERICA: &str = "lda #2\n sta $C100\n lda #$10\n sta $9000\n jsr sample";
+
+#[test]
+fn mapper_71_switches_16k_prg_and_ignores_mirroring_writes() {
+    let console = run(71, 0, 16, 0, CAMERICA);
+    assert_eq!(sampled(&console, 0), [4, 5, 14, 15]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_71_fire_hawk_switches_single_screen_mirroring() {
+    let console = run(71, 1, 16, 0, CAMERICA);
+    assert_eq!(sampled(&console, 0), [4, 5, 14, 15]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn mapper_78_holy_diver_switches_horizontal_and_vertical() {
+    let mut console = run(78, 3, 16, 128, "lda #$3A\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 14, 15]);
+    assert_eq!(chr_banks(&mut console), [24, 25, 26, 27, 28, 29, 30, 31]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_78_cosmo_carrier_switches_single_screen() {
+    let mut console = run(78, 1, 16, 128, "lda #$3A\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 14, 15]);
+    assert_eq!(chr_banks(&mut console), [24, 25, 26, 27, 28, 29, 30, 31]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn mapper_87_swaps_the_chr_bank_bits() {
+    let mut console = run(87, 0, 4, 32, "lda #$01\n sta $6000\n jsr sample");
+    assert_eq!(sampled(&console, 0), [0, 1, 2, 3]);
+    assert_eq!(chr_banks(&mut console), [16, 17, 18, 19, 20, 21, 22, 23]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+const IREM_G101: &str = "
+        lda #3
+        sta $8000
+        lda #5
+        sta $A000
+        lda #7
+        sta $B003
+        jsr sample
+        lda #$02
+        sta $9000
+        jsr sample";
+
+#[test]
+fn mapper_32_swaps_prg_windows_and_switches_mirroring() {
+    let mut console = run(32, 0, 16, 32, IREM_G101);
+    assert_eq!(sampled(&console, 0), [3, 5, 14, 15]);
+    assert_eq!(sampled(&console, 1), [14, 5, 3, 15]);
+    assert_eq!(chr_banks(&mut console)[3], 7);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_32_major_league_has_no_mode_register() {
+    let console = run(32, 1, 16, 32, IREM_G101);
+    assert_eq!(sampled(&console, 1), [3, 5, 14, 15]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenLower);
+}
+
+#[test]
+fn mapper_18_writes_banks_a_nibble_at_a_time() {
+    let program = "
+        lda #$05
+        sta $8000
+        lda #$01
+        sta $8001
+        lda #$09
+        sta $8002
+        lda #$0C
+        sta $9000
+        lda #$03
+        sta $B002
+        lda #$01
+        sta $B003
+        lda #$03
+        sta $F002
+        jsr sample";
+    let mut console = run(18, 0, 32, 32, program);
+    assert_eq!(sampled(&console, 0), [21, 9, 12, 31]);
+    assert_eq!(chr_banks(&mut console)[3], 19);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+const TAITO_TC0190: &str = "
+        lda #$03
+        sta $8000
+        lda #$06
+        sta $8001
+        lda #$05
+        sta $8002
+        lda #$09
+        sta $A001
+        jsr sample";
+
+#[test]
+fn mapper_33_switches_banks_and_mirroring() {
+    let mut console = run(33, 0, 16, 32, TAITO_TC0190);
+    assert_eq!(sampled(&console, 0), [3, 6, 14, 15]);
+    // The second 2KB bank and the other 1KB banks keep their power-on values
+    assert_eq!(chr_banks(&mut console), [10, 11, 2, 3, 4, 9, 6, 7]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_48_switches_mirroring_at_e000() {
+    let console = run(48, 0, 16, 32, TAITO_TC0190);
+    assert_eq!(sampled(&console, 0), [3, 6, 14, 15]);
+    // The TC0690 has no mirroring bit in $8000
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+
+    let console = run(48, 0, 16, 32, "lda #$00\n sta $E000");
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_80_switches_banks_through_7ef0() {
+    let program = "
+        lda #3
+        sta $7EFA
+        lda #5
+        sta $7EFC
+        lda #7
+        sta $7EFE
+        lda #$0A
+        sta $7EF0
+        lda #$0D
+        sta $7EF4
+        lda #1
+        sta $7EF6
+        jsr sample";
+    let mut console = run(80, 0, 16, 32, program);
+    assert_eq!(sampled(&console, 0), [3, 5, 7, 15]);
+    let chr = chr_banks(&mut console);
+    assert_eq!((chr[0], chr[1], chr[6]), (10, 11, 13));
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_16_fcg_decodes_registers_at_6000() {
+    let program = "
+        lda #3
+        sta $6008
+        lda #1
+        sta $8008
+        lda #9
+        sta $6002
+        lda #2
+        sta $6009
+        jsr sample";
+    let mut console = run(16, 4, 16, 32, program);
+    assert_eq!(sampled(&console, 0), [6, 7, 14, 15]);
+    assert_eq!(chr_banks(&mut console)[2], 9);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenLower);
+}
+
+#[test]
+fn mapper_16_lz93d50_decodes_registers_at_8000() {
+    let program = "
+        lda #3
+        sta $8008
+        lda #1
+        sta $6008
+        lda #9
+        sta $8002
+        lda #3
+        sta $8009
+        jsr sample";
+    let mut console = run(16, 5, 16, 32, program);
+    assert_eq!(sampled(&console, 0), [6, 7, 14, 15]);
+    assert_eq!(chr_banks(&mut console)[2], 9);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenUpper);
+}
+
+#[test]
+fn mapper_153_picks_the_outer_prg_half_with_chr_registers() {
+    let program = "
+        lda #3
+        sta $8008
+        lda #0
+        sta $8009
+        jsr sample
+        lda #1
+        sta $8000
+        jsr sample";
+    let console = run(153, 0, 64, 0, program);
+    assert_eq!(sampled(&console, 0), [6, 7, 30, 31]);
+    assert_eq!(sampled(&console, 1), [38, 39, 62, 63]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_159_switches_prg_chr_and_mirroring() {
+    let mut console = run(159, 0, 16, 32, "lda #3\n sta $8008\n lda #5\n sta $8007\n lda #2\n sta $8009\n jsr sample");
+    assert_eq!(sampled(&console, 0), [6, 7, 14, 15]);
+    assert_eq!(chr_banks(&mut console)[7], 5);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::SingleScreenLower);
+}
+
+#[test]
+fn mapper_58_latches_the_address() {
+    let mut console = run(58, 0, 16, 64, "sta $8053\n jsr sample\n sta $8082\n jsr sample");
+    assert_eq!(sampled(&console, 0), [6, 7, 6, 7]);
+    assert_eq!(sampled(&console, 1), [4, 5, 6, 7]);
+    assert_eq!(chr_banks(&mut console)[0], 0);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+
+    let mut console = run(58, 0, 16, 64, "sta $8053");
+    assert_eq!(chr_banks(&mut console)[0], 16);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_200_latches_the_address() {
+    let mut console = run(200, 0, 16, 64, "sta $800D\n jsr sample");
+    assert_eq!(sampled(&console, 0), [10, 11, 10, 11]);
+    assert_eq!(chr_banks(&mut console)[0], 40);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_201_latches_the_address() {
+    let mut console = run(201, 0, 16, 64, "sta $8002\n jsr sample");
+    assert_eq!(sampled(&console, 0), [8, 9, 10, 11]);
+    assert_eq!(chr_banks(&mut console)[0], 16);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_202_latches_the_address() {
+    let mut console = run(202, 0, 16, 64, "sta $8007\n jsr sample\n sta $8008\n jsr sample");
+    assert_eq!(sampled(&console, 0), [6, 7, 6, 7]);
+    assert_eq!(sampled(&console, 1), [8, 9, 8, 9]);
+    assert_eq!(chr_banks(&mut console)[0], 32);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+
+    let console = run(202, 0, 16, 64, "sta $8009\n jsr sample");
+    assert_eq!(sampled(&console, 0), [8, 9, 10, 11]);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_203_latches_the_data() {
+    let mut console = run(203, 0, 16, 32, "lda #$0E\n sta $8100\n jsr sample");
+    assert_eq!(sampled(&console, 0), [6, 7, 6, 7]);
+    assert_eq!(chr_banks(&mut console)[0], 16);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn mapper_212_latches_the_address() {
+    let mut console = run(212, 0, 16, 64, "sta $C00A\n jsr sample\n sta $8005\n jsr sample");
+    assert_eq!(sampled(&console, 0), [4, 5, 6, 7]);
+    assert_eq!(sampled(&console, 1), [10, 11, 10, 11]);
+    assert_eq!(chr_banks(&mut console)[0], 40);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Vertical);
+}
+
+#[test]
+fn mapper_225_latches_the_address() {
+    let mut console = run(225, 0, 16, 64, "sta $B0C5\n jsr sample");
+    assert_eq!(sampled(&console, 0), [6, 7, 6, 7]);
+    assert_eq!(chr_banks(&mut console)[0], 40);
+    assert_eq!(console.bus.mapper.mirroring(), Mirroring::Horizontal);
+}
+
+#[test]
+fn unknown_submappers_are_rejected() {
+    for (mapper, submapper) in [(18, 1), (33, 2), (71, 2), (78, 2), (225, 1)] {
+        let cartridge = Cartridge::for_test(mapper, submapper, vec![0; 0x8000], Vec::new());
+        assert!(super::create(&cartridge).is_err(), "mapper {} submapper {}", mapper, submapper);
+    }
+}
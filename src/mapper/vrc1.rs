@@ -0,0 +1,121 @@
+use super::Mapper;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Mapper 75 (Konami VRC1): three switchable 8KB PRG banks with the last one fixed,
+/// and two 4KB CHR banks whose top bit lives in the mirroring register.
+pub struct Vrc1 {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 2],
+    mirroring: Mirroring,
+}
+
+impl Vrc1 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Vrc1 {
+            prg_rom: cartridge.prg_rom.clone(),
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 2],
+            mirroring: cartridge.mirroring,
+        }
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF => self.prg_banks[2] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 12) as usize & 1] as usize;
+        (bank * 0x1000 + (addr & 0x0FFF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc1 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr & 0xF000 {
+            0x8000 => self.prg_banks[0] = data & 0x0F,
+            0x9000 => {
+                if self.mirroring != Mirroring::FourScreen {
+                    self.mirroring = if (data & 1) == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+                }
+                self.chr_banks[0] = (self.chr_banks[0] & 0x0F) | ((data & 0x02) << 3);
+                self.chr_banks[1] = (self.chr_banks[1] & 0x0F) | ((data & 0x04) << 2);
+            }
+            0xA000 => self.prg_banks[1] = data & 0x0F,
+            0xC000 => self.prg_banks[2] = data & 0x0F,
+            0xE000 => self.chr_banks[0] = (self.chr_banks[0] & 0x10) | (data & 0x0F),
+            0xF000 => self.chr_banks[1] = (self.chr_banks[1] & 0x10) | (data & 0x0F),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_banks);
+        w.write_bool(self.mirroring == Mirroring::Horizontal);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_banks)?;
+        let horizontal = r.read_bool()?;
+        if self.mirroring != Mirroring::FourScreen {
+            self.mirroring = if horizontal { Mirroring::Horizontal } else { Mirroring::Vertical };
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,230 @@
+use super::Mapper;
+use super::vrc_irq::VrcIrq;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Mappers 21, 22, 23 and 25 (Konami VRC2 and VRC4). The two chips share a register
+/// layout; VRC4 adds a PRG swap mode, wider CHR bank numbers and the VRC IRQ counter.
+/// Each board wires the chip's A0/A1 register select pins to different CPU address
+/// lines, which the NES 2.0 submapper says. Without one, both candidate lines are
+/// decoded at once, which works because games only ever use one of the pairs.
+pub struct Vrc4 {
+    vrc2: bool,
+    a0: u16, // CPU address lines driving the chip's register select pins
+    a1: u16,
+    chr_shift: u8, // VRC2a ignores the low bit of its CHR bank numbers
+
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // Empty on VRC2 boards without RAM, which have a 1-bit latch there instead
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 2],
+    prg_swap: bool,   // $9002 bit 1 (VRC4): swap the $8000 and $C000 windows
+    chr_banks: [u16; 8],
+    mirroring: u8,    // $9000
+    latch: u8,        // $6000 on VRC2 boards without RAM
+    irq: VrcIrq,
+}
+
+impl Vrc4 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        // (VRC2, A0 lines, A1 lines)
+        let (vrc2, a0, a1) = match (cartridge.mapper, cartridge.submapper) {
+            (21, 1) => (false, 0x02, 0x04), // VRC4a
+            (21, 2) => (false, 0x40, 0x80), // VRC4c
+            (21, _) => (false, 0x42, 0x84),
+            (22, _) => (true, 0x02, 0x01), // VRC2a
+            (23, 1) => (false, 0x01, 0x02), // VRC4f
+            (23, 2) => (false, 0x04, 0x08), // VRC4e
+            (23, 3) => (true, 0x01, 0x02),  // VRC2b
+            (23, _) => (false, 0x05, 0x0A),
+            (25, 1) => (false, 0x02, 0x01), // VRC4b
+            (25, 2) => (false, 0x08, 0x04), // VRC4d
+            (25, 3) => (true, 0x02, 0x01),  // VRC2c
+            (_, _) => (false, 0x0A, 0x05),
+        };
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        let has_ram = !vrc2 || cartridge.prg_ram_size + cartridge.prg_nvram_size > 0;
+        Vrc4 {
+            vrc2,
+            a0,
+            a1,
+            chr_shift: if cartridge.mapper == 22 { 1 } else { 0 },
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: if has_ram { vec![0; 0x2000] } else { Vec::new() },
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: [0; 2],
+            prg_swap: false,
+            chr_banks: [0; 8],
+            mirroring: if cartridge.mirroring == Mirroring::Horizontal { 1 } else { 0 },
+            latch: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    /// Folds the board's wiring back into the chip's view: $x000-$x003.
+    fn register(&self, addr: u16) -> u16 {
+        let mut register = addr & 0xF000;
+        if (addr & self.a0) != 0 {
+            register |= 1;
+        }
+        if (addr & self.a1) != 0 {
+            register |= 2;
+        }
+        register
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let second_last = bank_count.saturating_sub(2);
+        let bank = match addr {
+            0x8000..=0x9FFF if self.prg_swap => second_last,
+            0x8000..=0x9FFF => self.prg_banks[0] as usize,
+            0xA000..=0xBFFF => self.prg_banks[1] as usize,
+            0xC000..=0xDFFF if self.prg_swap => self.prg_banks[0] as usize,
+            0xC000..=0xDFFF => second_last,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = (self.chr_banks[(addr >> 10) as usize & 7] >> self.chr_shift) as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x6000..=0x6FFF => self.latch,
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => self.prg_ram[(addr & 0x1FFF) as usize] = data,
+            0x6000..=0x6FFF => self.latch = data & 0x01,
+            0x8000..=0xFFFF => {}
+            _ => return,
+        }
+        let register = self.register(addr);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1F,
+            0x9000..=0x9003 if self.vrc2 => self.mirroring = data & 0x01,
+            0x9000..=0x9001 => self.mirroring = data & 0x03,
+            0x9002..=0x9003 => self.prg_swap = (data & 0x02) != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = data & 0x1F,
+            0xB000..=0xE003 => {
+                // Two registers per 1KB bank: $xxx0/$xxx2 the low nibble, $xxx1/$xxx3 the high bits
+                let bank = ((register - 0xB000) >> 11) as usize | ((register & 0x02) >> 1) as usize;
+                let high_mask = if self.vrc2 { 0x0F } else { 0x1F };
+                let value = &mut self.chr_banks[bank];
+                *value = if (register & 0x01) == 0 {
+                    (*value & 0x1F0) | (data & 0x0F) as u16
+                } else {
+                    (*value & 0x00F) | (((data & high_mask) as u16) << 4)
+                };
+            }
+            0xF000 if !self.vrc2 => self.irq.write_latch_nibble(false, data),
+            0xF001 if !self.vrc2 => self.irq.write_latch_nibble(true, data),
+            0xF002 if !self.vrc2 => self.irq.write_control(data),
+            0xF003 if !self.vrc2 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.mirroring {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        match bank_count - bank {
+            1 => 0xE000,
+            2 => 0xC000,
+            _ => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery && !self.prg_ram.is_empty() { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bool(self.prg_swap);
+        for &bank in &self.chr_banks {
+            w.write_u16(bank);
+        }
+        w.write_u8(self.mirroring);
+        w.write_u8(self.latch);
+        self.irq.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        self.prg_swap = r.read_bool()?;
+        for bank in &mut self.chr_banks {
+            *bank = r.read_u16()?;
+        }
+        self.mirroring = r.read_u8()?;
+        self.latch = r.read_u8()?;
+        self.irq.load_state(r)
+    }
+}
@@ -0,0 +1,370 @@
+use super::Mapper;
+use super::vrc_irq::VrcIrq;
+use crate::apu;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// A VRC6 pulse channel: 8 duty settings out of 16 steps, plus a mode that holds
+/// the output at the volume level for use as a 4-bit DAC.
+struct Vrc6Pulse {
+    volume: u8,
+    duty: u8,
+    constant: bool,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+}
+
+impl Vrc6Pulse {
+    fn new() -> Self {
+        Vrc6Pulse { volume: 0, duty: 0, constant: false, period: 0, enabled: false, timer: 0, step: 15 }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.volume = data & 0x0F;
+                self.duty = (data >> 4) & 0x07;
+                self.constant = (data & 0x80) != 0;
+            }
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 15;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer == 0 {
+            self.timer = self.period >> shift;
+            self.step = self.step.wrapping_sub(1) & 0x0F;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.enabled && (self.constant || self.step <= self.duty) { self.volume } else { 0 }
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.volume);
+        w.write_u8(self.duty);
+        w.write_bool(self.constant);
+        w.write_u16(self.period);
+        w.write_bool(self.enabled);
+        w.write_u16(self.timer);
+        w.write_u8(self.step);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.volume = r.read_u8()? & 0x0F;
+        self.duty = r.read_u8()? & 0x07;
+        self.constant = r.read_bool()?;
+        self.period = r.read_u16()? & 0x0FFF;
+        self.enabled = r.read_bool()?;
+        self.timer = r.read_u16()?;
+        self.step = r.read_u8()? & 0x0F;
+        Ok(())
+    }
+}
+
+/// The VRC6 sawtooth: an accumulator that gains `rate` every other timer clock and
+/// resets after seven additions, of which the top five bits are output.
+struct Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    timer: u16,
+    step: u8,
+    accumulator: u8,
+}
+
+impl Sawtooth {
+    fn new() -> Self {
+        Sawtooth { rate: 0, period: 0, enabled: false, timer: 0, step: 0, accumulator: 0 }
+    }
+
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0x0F) << 8);
+                self.enabled = (data & 0x80) != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled {
+            return;
+        }
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period >> shift;
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if (self.step & 1) == 0 {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.rate);
+        w.write_u16(self.period);
+        w.write_bool(self.enabled);
+        w.write_u16(self.timer);
+        w.write_u8(self.step);
+        w.write_u8(self.accumulator);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.rate = r.read_u8()? & 0x3F;
+        self.period = r.read_u16()? & 0x0FFF;
+        self.enabled = r.read_bool()?;
+        self.timer = r.read_u16()?;
+        self.step = r.read_u8()?;
+        if self.step >= 14 {
+            return Err(StateError::Invalid("VRC6 sawtooth step"));
+        }
+        self.accumulator = r.read_u8()?;
+        Ok(())
+    }
+}
+
+/// Mappers 24 and 26 (Konami VRC6): 16KB + 8KB of switchable PRG, eight 1KB CHR banks
+/// with 2KB layouts, the VRC IRQ counter and three extra sound channels. Mapper 26
+/// boards swap the A0 and A1 register select lines.
+pub struct Vrc6 {
+    swapped_lines: bool,
+
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 2], // $8000 (16KB), $C000 (8KB)
+    chr_banks: [u8; 8], // $D000-$E003
+    ppu_control: u8,    // $B003: bits 0-1 CHR layout, 2-3 mirroring, 7 PRG RAM enable
+    irq: VrcIrq,
+
+    // Audio
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Sawtooth,
+    frequency_control: u8, // $9003: bit 0 halts the channels, bits 1-2 speed them up 16x/256x
+}
+
+impl Vrc6 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Vrc6 {
+            swapped_lines: cartridge.mapper == 26,
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: [0; 2],
+            chr_banks: [0; 8],
+            ppu_control: if cartridge.mirroring == Mirroring::Horizontal { 0x04 } else { 0x00 },
+            irq: VrcIrq::new(),
+            pulses: [Vrc6Pulse::new(), Vrc6Pulse::new()],
+            sawtooth: Sawtooth::new(),
+            frequency_control: 0,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.ppu_control & 0x80) != 0
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x8000..=0xBFFF => (self.prg_banks[0] as usize) * 2 + ((addr as usize >> 13) & 1),
+            0xC000..=0xDFFF => self.prg_banks[1] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    // Only the layouts licensed games use; the modes that map CHR ROM into the
+    // nametables are left out.
+    fn chr_index(&self, addr: u16) -> usize {
+        let slot = (addr >> 10) as usize & 7;
+        let a10 = slot & 1;
+        let bank = match self.ppu_control & 0x03 {
+            0 => self.chr_banks[slot] as usize,
+            1 => (self.chr_banks[slot >> 1] as usize & !1) | a10,
+            _ if slot < 4 => self.chr_banks[slot] as usize,
+            _ => (self.chr_banks[4 + ((slot - 4) >> 1)] as usize & !1) | a10,
+        };
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr < 0x8000 {
+            if (0x6000..=0x7FFF).contains(&addr) && self.prg_ram_enabled() {
+                self.prg_ram[(addr & 0x1FFF) as usize] = data;
+            }
+            return;
+        }
+        let (a0, a1) = if self.swapped_lines { (addr & 0x02, addr & 0x01) } else { (addr & 0x01, addr & 0x02) };
+        let register = (addr & 0xF000) | ((a0 != 0) as u16) | (((a1 != 0) as u16) << 1);
+        match register {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x0F,
+            0x9000..=0x9002 => self.pulses[0].write(register & 0x03, data),
+            0x9003 => self.frequency_control = data & 0x07,
+            0xA000..=0xA002 => self.pulses[1].write(register & 0x03, data),
+            0xB000..=0xB002 => self.sawtooth.write(register & 0x03, data),
+            0xB003 => self.ppu_control = data,
+            0xC000..=0xC003 => self.prg_banks[1] = data & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(register & 0x03) as usize] = data,
+            0xE000..=0xE003 => self.chr_banks[4 + (register & 0x03) as usize] = data,
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.ppu_control >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.clock();
+        if (self.frequency_control & 0x01) == 0 {
+            let shift = match self.frequency_control {
+                0x04..=0x07 => 8,
+                0x02..=0x03 => 4,
+                _ => 0,
+            };
+            for pulse in &mut self.pulses {
+                pulse.clock(shift);
+            }
+            self.sawtooth.clock(shift);
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        // The channels sum linearly on the cartridge. The pulses reach 15, and the sawtooth's
+        // top five accumulator bits reach 31, about twice as loud.
+        let sum = self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output();
+        sum as f32 * (apu::PULSE_FULL_VOLUME / 15.0)
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        match bank_count - bank {
+            1 => 0xE000,
+            _ if bank % 2 == 1 => 0xA000,
+            _ => 0x8000,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_banks);
+        w.write_u8(self.ppu_control);
+        self.irq.save_state(w);
+        for pulse in &self.pulses {
+            pulse.save_state(w);
+        }
+        self.sawtooth.save_state(w);
+        w.write_u8(self.frequency_control);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_banks)?;
+        self.ppu_control = r.read_u8()?;
+        self.irq.load_state(r)?;
+        for pulse in &mut self.pulses {
+            pulse.load_state(r)?;
+        }
+        self.sawtooth.load_state(r)?;
+        self.frequency_control = r.read_u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+use super::Mapper;
+use super::opll::Opll;
+use super::vrc_irq::VrcIrq;
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+/// Mapper 85 (Konami VRC7): three switchable 8KB PRG banks, eight 1KB CHR banks, the VRC
+/// IRQ counter and a six-channel FM synthesizer. The second register of each pair is
+/// selected by A4 on VRC7a boards (submapper 2) and A3 on VRC7b (submapper 1); without a
+/// submapper both are decoded.
+pub struct Vrc7 {
+    select_line: u16,
+
+    prg_rom: Vec<u8>,
+    prg_ram: [u8; 0x2000],
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    battery: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8, // $E000: bits 0-1 mirroring, 6 audio reset, 7 PRG RAM enable
+    irq: VrcIrq,
+    audio: Opll,
+}
+
+impl Vrc7 {
+    pub fn new(cartridge: &Cartridge) -> Self {
+        let chr_is_ram = cartridge.chr_rom.is_empty();
+        Vrc7 {
+            select_line: match cartridge.submapper {
+                1 => 0x08,
+                2 => 0x10,
+                _ => 0x18,
+            },
+            prg_rom: cartridge.prg_rom.clone(),
+            prg_ram: [0; 0x2000],
+            chr: cartridge.chr_memory(),
+            chr_is_ram,
+            battery: cartridge.battery,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: if cartridge.mirroring == Mirroring::Horizontal { 0x01 } else { 0x00 },
+            irq: VrcIrq::new(),
+            audio: Opll::new(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        (self.control & 0x80) != 0
+    }
+
+    fn prg_index(&self, addr: u16) -> usize {
+        let bank_count = self.prg_rom.len() / 0x2000;
+        let bank = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) >> 13] as usize,
+            _ => bank_count - 1,
+        };
+        (bank % bank_count) * 0x2000 + (addr & 0x1FFF) as usize
+    }
+
+    fn chr_index(&self, addr: u16) -> usize {
+        let bank = self.chr_banks[(addr >> 10) as usize & 7] as usize;
+        (bank * 0x0400 + (addr & 0x03FF) as usize) % self.chr.len()
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => self.prg_ram[(addr & 0x1FFF) as usize],
+            0x8000..=0xFFFF => self.prg_rom[self.prg_index(addr)],
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr < 0x8000 {
+            if (0x6000..=0x7FFF).contains(&addr) && self.prg_ram_enabled() {
+                self.prg_ram[(addr & 0x1FFF) as usize] = data;
+            }
+            return;
+        }
+        // The synthesizer decodes A4 and A5 on every board
+        match addr & 0xF030 {
+            0x9010 => return self.audio.write_address(data),
+            0x9030 => return self.audio.write_data(data),
+            _ => {}
+        }
+        let register = (addr & 0xF000) | (((addr & self.select_line) != 0) as u16);
+        match register {
+            0x8000 => self.prg_banks[0] = data & 0x3F,
+            0x8001 => self.prg_banks[1] = data & 0x3F,
+            0x9000 => self.prg_banks[2] = data & 0x3F,
+            0xA000..=0xD001 => {
+                let bank = ((register - 0xA000) >> 11) as usize | (register & 0x01) as usize;
+                self.chr_banks[bank] = data;
+            }
+            0xE000 => {
+                self.control = data;
+                if (data & 0x40) != 0 {
+                    self.audio.reset();
+                }
+            }
+            0xE001 => self.irq.write_latch(data),
+            0xF000 => self.irq.write_control(data),
+            0xF001 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn chr_read(&mut self, addr: u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn chr_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram {
+            let index = self.chr_index(addr);
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_tick(&mut self) {
+        self.irq.clock();
+        if (self.control & 0x40) == 0 {
+            self.audio.clock();
+        }
+    }
+
+    fn audio_output(&self) -> f32 {
+        if (self.control & 0x40) == 0 { self.audio.output() } else { 0.0 }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn prg_bank_size(&self) -> usize {
+        0x2000
+    }
+
+    fn prg_bank_origin(&self, bank: usize, bank_count: usize) -> u16 {
+        if bank + 1 == bank_count { 0xE000 } else { 0x8000 }
+    }
+
+    fn prg_offset(&self, addr: u16) -> Option<usize> {
+        if addr >= 0x8000 { Some(self.prg_index(addr)) } else { None }
+    }
+
+    fn chr_offset(&self, addr: u16) -> Option<usize> {
+        if self.chr_is_ram { None } else { Some(self.chr_index(addr)) }
+    }
+
+    fn save_data(&self) -> Option<&[u8]> {
+        if self.battery { Some(&self.prg_ram) } else { None }
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.prg_ram);
+        if self.chr_is_ram {
+            w.write_bytes(&self.chr);
+        }
+        w.write_bytes(&self.prg_banks);
+        w.write_bytes(&self.chr_banks);
+        w.write_u8(self.control);
+        self.irq.save_state(w);
+        self.audio.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.prg_ram)?;
+        if self.chr_is_ram {
+            r.read_bytes(&mut self.chr)?;
+        }
+        r.read_bytes(&mut self.prg_banks)?;
+        r.read_bytes(&mut self.chr_banks)?;
+        self.control = r.read_u8()?;
+        self.irq.load_state(r)?;
+        self.audio.load_state(r)
+    }
+}
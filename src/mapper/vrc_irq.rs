@@ -0,0 +1,110 @@
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// CPU cycles per scanline, times 3: the prescaler counts PPU dots in steps of three
+const PRESCALER_PERIOD: i16 = 341;
+
+/// The IRQ counter shared by VRC4, VRC6 and VRC7: an 8-bit up-counter that reloads
+/// from a latch when it overflows. In scanline mode a prescaler divides the CPU clock
+/// down to roughly one count per scanline; in cycle mode it counts every CPU cycle.
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> Self {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: PRESCALER_PERIOD,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    /// VRC4 writes the latch four bits at a time.
+    pub fn write_latch_nibble(&mut self, high: bool, data: u8) {
+        self.latch = if high { (self.latch & 0x0F) | (data << 4) } else { (self.latch & 0xF0) | (data & 0x0F) };
+    }
+
+    /// Bit 0: enable again after acknowledge, bit 1: enable, bit 2: cycle mode.
+    pub fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = (data & 0x01) != 0;
+        self.enabled = (data & 0x02) != 0;
+        self.cycle_mode = (data & 0x04) != 0;
+        self.pending = false;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    /// Clocked once per CPU cycle.
+    pub fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.count();
+        } else {
+            self.prescaler -= 3;
+            if self.prescaler <= 0 {
+                self.prescaler += PRESCALER_PERIOD;
+                self.count();
+            }
+        }
+    }
+
+    fn count(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub fn pending(&self) -> bool {
+        self.pending
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.latch);
+        w.write_u8(self.counter);
+        w.write_i16(self.prescaler);
+        w.write_bool(self.enabled);
+        w.write_bool(self.enable_after_ack);
+        w.write_bool(self.cycle_mode);
+        w.write_bool(self.pending);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.latch = r.read_u8()?;
+        self.counter = r.read_u8()?;
+        self.prescaler = r.read_i16()?;
+        if !(-2..=PRESCALER_PERIOD).contains(&self.prescaler) {
+            return Err(StateError::Invalid("VRC IRQ prescaler"));
+        }
+        self.enabled = r.read_bool()?;
+        self.enable_after_ack = r.read_bool()?;
+        self.cycle_mode = r.read_bool()?;
+        self.pending = r.read_bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+use crate::console::{Console, Region};
+use crate::mapper::NsfBoard;
+use crate::savestate;
+
+// NSF layout: a 128-byte header ("NESM\x1A", version, song count, first song, load/init/play
+// addresses, 32-byte title, artist and copyright strings, NTSC play rate, initial banks, PAL
+// play rate, region and expansion chip flags, and on NSF2 the data length), then the data.
+// NSFe is "NSFE" and then chunks of a little-endian u32 length, a 4-byte ID and the data,
+// up to NEND. An ID starting with an upper case letter is one a player must understand;
+// NSF2 files can follow their data with NSFe metadata chunks.
+
+// Expansion sound chips, as flagged in the header
+pub const CHIP_VRC6: u8 = 0x01;
+pub const CHIP_VRC7: u8 = 0x02;
+pub const CHIP_FDS: u8 = 0x04;
+pub const CHIP_MMC5: u8 = 0x08;
+pub const CHIP_N163: u8 = 0x10;
+pub const CHIP_5B: u8 = 0x20;
+
+const CHIP_NAMES: [(u8, &str); 6] = [
+    (CHIP_VRC6, "VRC6"),
+    (CHIP_VRC7, "VRC7"),
+    (CHIP_FDS, "FDS"),
+    (CHIP_MMC5, "MMC5"),
+    (CHIP_N163, "Namco 163"),
+    (CHIP_5B, "Sunsoft 5B"),
+];
+
+// Microseconds between PLAY calls when a file leaves the rate out: one per frame
+const NTSC_PLAY_SPEED: u16 = 16639;
+const PAL_PLAY_SPEED: u16 = 19997;
+
+/// A parsed NSF or NSFe tune set.
+pub struct Nsf {
+    pub songs: u8,
+    pub start_song: u8, // Counting from 1
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub banks: Option<[u8; 8]>, // Initial $5FF8-$5FFF, or None if the tune isn't bankswitched
+    pub ntsc_speed: u16,        // Microseconds between PLAY calls
+    pub pal_speed: u16,
+    pub region: Option<Region>, // None if the tune plays on either
+    pub chips: u8,
+    pub data: Vec<u8>,
+
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    pub ripper: String,
+
+    // Per-track metadata from NSFe chunks; empty for plain NSF
+    pub track_labels: Vec<String>,
+    pub track_times: Vec<Option<u32>>, // Milliseconds
+    pub track_fades: Vec<Option<u32>>,
+}
+
+impl Nsf {
+    /// Names of the expansion chips the tune uses.
+    pub fn chip_names(&self) -> Vec<&'static str> {
+        CHIP_NAMES.iter().filter(|(chip, _)| (self.chips & chip) != 0).map(|&(_, name)| name).collect()
+    }
+
+    /// A track's label, counting from 1, if the file names it.
+    pub fn track_label(&self, track: u8) -> Option<&str> {
+        self.track_labels.get(track as usize - 1).map(String::as_str).filter(|label| !label.is_empty())
+    }
+
+    /// A track's length and fade-out in milliseconds, counting from 1, if the file gives them.
+    pub fn track_length(&self, track: u8) -> Option<(u32, u32)> {
+        let time = (*self.track_times.get(track as usize - 1)?)?;
+        let fade = self.track_fades.get(track as usize - 1).copied().flatten().unwrap_or(0);
+        Some((time, fade))
+    }
+
+    fn empty() -> Nsf {
+        Nsf {
+            songs: 1,
+            start_song: 1,
+            load_addr: 0,
+            init_addr: 0,
+            play_addr: 0,
+            banks: None,
+            ntsc_speed: NTSC_PLAY_SPEED,
+            pal_speed: PAL_PLAY_SPEED,
+            region: Some(Region::Ntsc),
+            chips: 0,
+            data: Vec::new(),
+            title: String::new(),
+            artist: String::new(),
+            copyright: String::new(),
+            ripper: String::new(),
+            track_labels: Vec::new(),
+            track_times: Vec::new(),
+            track_fades: Vec::new(),
+        }
+    }
+
+    fn set_region(&mut self, flags: u8) {
+        self.region = match flags & 0x03 {
+            0 => Some(Region::Ntsc),
+            1 => Some(Region::Pal),
+            _ => None,
+        };
+    }
+
+    fn set_banks(&mut self, init: &[u8]) {
+        let mut banks = [0; 8];
+        banks[..init.len().min(8)].copy_from_slice(&init[..init.len().min(8)]);
+        self.banks = if banks.iter().any(|&bank| bank != 0) { Some(banks) } else { None };
+    }
+
+    /// Reads one NSFe chunk into the tune.
+    fn read_chunk(&mut self, id: &[u8], data: &[u8]) -> Result<(), String> {
+        match id {
+            b"INFO" => {
+                if data.len() < 8 {
+                    return Err("NSFe INFO chunk is too short.".to_string());
+                }
+                self.load_addr = u16::from_le_bytes([data[0], data[1]]);
+                self.init_addr = u16::from_le_bytes([data[2], data[3]]);
+                self.play_addr = u16::from_le_bytes([data[4], data[5]]);
+                self.set_region(data[6]);
+                self.chips = data[7];
+                self.songs = data.get(8).copied().unwrap_or(1);
+                // Counts from 0 here, so 255 would be song 256, which can't exist
+                self.start_song = data.get(9).copied().unwrap_or(0).checked_add(1).ok_or("NSFe INFO chunk's starting song is out of range.")?;
+            }
+            b"DATA" => self.data = data.to_vec(),
+            b"BANK" => self.set_banks(data),
+            b"RATE" => {
+                let speed = |i: usize| data.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+                self.ntsc_speed = speed(0).unwrap_or(NTSC_PLAY_SPEED);
+                self.pal_speed = speed(2).unwrap_or(PAL_PLAY_SPEED);
+            }
+            b"NEND" => {}
+            b"auth" => {
+                let mut strings = strings(data).into_iter();
+                self.title = strings.next().unwrap_or_default();
+                self.artist = strings.next().unwrap_or_default();
+                self.copyright = strings.next().unwrap_or_default();
+                self.ripper = strings.next().unwrap_or_default();
+            }
+            b"tlbl" => self.track_labels = strings(data),
+            b"time" => self.track_times = milliseconds(data),
+            b"fade" => self.track_fades = milliseconds(data),
+            _ if id[0].is_ascii_uppercase() => {
+                return Err(format!("NSFe chunk {} is required but not supported.", String::from_utf8_lossy(id)));
+            }
+            _ => {} // Optional: playlists, sound effect lists, text and the like
+        }
+        Ok(())
+    }
+
+    /// Reads NSFe chunks from `offset` up to NEND or the end of the file. Returns whether
+    /// there was an INFO chunk.
+    fn read_chunks(&mut self, bytes: &[u8], mut offset: usize) -> Result<bool, String> {
+        let mut has_info = false;
+        while offset < bytes.len() {
+            let header = bytes.get(offset..offset + 8).ok_or("NSFe file is truncated (chunk header).")?;
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let id = &header[4..8];
+            offset += 8;
+            let data = bytes.get(offset..offset + len).ok_or_else(|| {
+                format!("NSFe file is truncated ({} chunk).", String::from_utf8_lossy(id))
+            })?;
+            offset += len;
+            self.read_chunk(id, data)?;
+            has_info |= id == b"INFO";
+            if id == b"NEND" {
+                break;
+            }
+        }
+        Ok(has_info)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.songs == 0 {
+            return Err("NSF file has no songs.".to_string());
+        }
+        if self.data.is_empty() {
+            return Err("NSF file has no data.".to_string());
+        }
+        // FDS tunes can load into the RAM adapter's RAM at $6000 too
+        let lowest = if (self.chips & CHIP_FDS) != 0 { 0x6000 } else { 0x8000 };
+        if self.load_addr < lowest {
+            return Err(format!("NSF load address ${:04X} is below ${:04X}.", self.load_addr, lowest));
+        }
+        if self.banks.is_none() && self.load_addr as usize + self.data.len() > 0x10000 {
+            return Err("NSF data runs past $FFFF, but the tune isn't bankswitched.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Parses an NSF or NSFe file, telling them apart by their magic.
+pub fn parse(bytes: &[u8]) -> Result<Nsf, String> {
+    let mut nsf = Nsf::empty();
+    if bytes.starts_with(b"NSFE") {
+        if !nsf.read_chunks(bytes, 4)? {
+            return Err("NSFe file has no INFO chunk.".to_string());
+        }
+    } else if bytes.len() >= 128 && bytes.starts_with(b"NESM\x1A") {
+        let header = &bytes[..128];
+        let word = |i: usize| u16::from_le_bytes([header[i], header[i + 1]]);
+        nsf.songs = header[0x06];
+        nsf.start_song = header[0x07].max(1);
+        nsf.load_addr = word(0x08);
+        nsf.init_addr = word(0x0A);
+        nsf.play_addr = word(0x0C);
+        nsf.title = string(&header[0x0E..0x2E]);
+        nsf.artist = string(&header[0x2E..0x4E]);
+        nsf.copyright = string(&header[0x4E..0x6E]);
+        nsf.ntsc_speed = if word(0x6E) != 0 { word(0x6E) } else { NTSC_PLAY_SPEED };
+        nsf.set_banks(&header[0x70..0x78]);
+        nsf.pal_speed = if word(0x78) != 0 { word(0x78) } else { PAL_PLAY_SPEED };
+        nsf.set_region(header[0x7A]);
+        nsf.chips = header[0x7B];
+
+        // NSF2 can give the data's length, and follow it with metadata chunks
+        let data_len = u32::from_le_bytes([header[0x7D], header[0x7E], header[0x7F], 0]) as usize;
+        if header[0x05] >= 2 && data_len > 0 {
+            nsf.data = bytes.get(128..128 + data_len).ok_or("NSF file is truncated (data).")?.to_vec();
+            nsf.read_chunks(bytes, 128 + data_len)?;
+        } else {
+            nsf.data = bytes[128..].to_vec();
+        }
+    } else {
+        return Err("Invalid NSF header.".to_string());
+    }
+    nsf.validate()?;
+    Ok(nsf)
+}
+
+/// A fixed-size header string: null-padded, and not always null-terminated.
+fn string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// A run of null-terminated strings.
+fn strings(bytes: &[u8]) -> Vec<String> {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+    bytes.split(|&b| b == 0).map(string).collect()
+}
+
+/// Per-track times, where a negative one means "not given".
+fn milliseconds(bytes: &[u8]) -> Vec<Option<u32>> {
+    bytes.chunks_exact(4).map(|b| u32::try_from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok()).collect()
+}
+
+/// Plays a tune the way a hardware NSF player does: sets the APU up, calls INIT once
+/// for the chosen song, then calls PLAY at the tune's rate. Between calls the CPU idles
+/// in the board's loop; a PLAY that's due while a call is still running is skipped.
+pub struct Player {
+    console: Console,
+    play_addr: u16,
+    play_period: f64, // CPU cycles between PLAY calls
+    next_play: f64,
+}
+
+impl Player {
+    /// Starts `song` (counting from 1), with audio sampled at `sample_rate` Hz.
+    pub fn new(tune: &Nsf, song: u8, region: Region, sample_rate: u32) -> Self {
+        let board = Box::new(NsfBoard::new(tune));
+        let mut console = Console::with_mapper(board, region, savestate::crc32(&[&tune.data]));
+        console.bus.apu.set_sample_rate(Some(sample_rate));
+        for addr in 0x4000..=0x4013 {
+            console.write(addr, 0x00);
+        }
+        console.write(0x4015, 0x00);
+        console.write(0x4015, 0x0F);
+        console.write(0x4017, 0x40); // No frame IRQ
+        if (tune.chips & CHIP_FDS) != 0 {
+            console.write(0x4080, 0x80);
+            console.write(0x408A, 0xE8);
+        }
+
+        let speed = if region == Region::Pal { tune.pal_speed } else { tune.ntsc_speed };
+        let play_period = speed as f64 * region.cpu_clock_hz() / 1_000_000.0;
+        let mut player = Player { console, play_addr: tune.play_addr, play_period, next_play: play_period };
+        let cpu = &mut player.console.cpu;
+        cpu.sp = 0xFD;
+        cpu.status = 0x24; // Interrupts disabled
+        cpu.ac = song - 1;
+        cpu.idx = (region == Region::Pal) as u8;
+        cpu.idy = 0;
+        cpu.pc = NsfBoard::IDLE_LOOP;
+        player.call(tune.init_addr);
+        player
+    }
+
+    /// Chooses between time-multiplexed and clean expansion audio, as for cartridges.
+    pub fn set_audio_multiplexing(&mut self, enabled: bool) {
+        self.console.bus.mapper.set_audio_multiplexing(enabled);
+    }
+
+    /// JSRs to a routine that returns into the idle loop.
+    fn call(&mut self, addr: u16) {
+        let cpu = &mut self.console.cpu;
+        let return_addr = NsfBoard::IDLE_LOOP - 1; // RTS adds 1
+        for byte in [(return_addr >> 8) as u8, return_addr as u8] {
+            let addr = 0x0100 + cpu.sp as usize;
+            cpu.memory_mut()[addr] = byte;
+            cpu.sp = cpu.sp.wrapping_sub(1);
+        }
+        cpu.pc = addr;
+    }
+
+    /// Plays until `count` more samples have been produced.
+    pub fn render(&mut self, count: usize) -> Result<Vec<f32>, String> {
+        let mut samples = Vec::with_capacity(count);
+        while samples.len() < count {
+            let cycles = self.console.cycles() as f64;
+            if cycles >= self.next_play {
+                if self.console.cpu.pc == NsfBoard::IDLE_LOOP {
+                    self.call(self.play_addr);
+                }
+                while self.next_play <= cycles {
+                    self.next_play += self.play_period;
+                }
+            }
+            if self.console.step() == 0 {
+                return Err(format!("CPU jammed at ${:04X}.", self.console.cpu.pc));
+            }
+            samples.append(&mut self.console.bus.apu.take_samples());
+        }
+        samples.truncate(count);
+        Ok(samples)
+    }
+}
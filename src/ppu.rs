@@ -1,5 +1,10 @@
-// A real implementation would need access to the cartridge (CHR ROM) and a bus.
-// For now, we'll keep it self-contained.
+use crate::cdl;
+use crate::console::Region;
+use crate::mapper::Mapper;
+use crate::savestate::{StateError, StateReader, StateWriter};
+
+// Pattern table and nametable accesses go through the cartridge's mapper,
+// which the bus passes in on every call.
 
 pub struct PPU {
     // PPU Memory
@@ -21,15 +26,28 @@ pub struct PPU {
     oam_addr: u8,
 
     // Rendering State
-    scanline: i16,
+    scanline: i16, // -1 is the pre-render scanline
     cycle: u16,
+    frame: u64,
+    region: Region,
+
+    nmi_pending: bool,
+    frame_complete: bool,
 
     // Data buffer for PPUDATA reads
     data_buffer: u8,
+
+    // Fetch pipeline
+    tile_index: u8,            // Nametable byte for the tile whose pattern is being fetched
+    secondary_oam: [u8; 32],   // Sprites found for the next scanline
+    sprite_count: u8,
+
+    // Code/Data Log flags for CHR ROM, while logging
+    chr_log: Option<Vec<u8>>,
 }
 
 impl PPU {
-    pub fn new() -> Self {
+    pub fn new(region: Region) -> Self {
         PPU {
             vram: [0; 2048],
             oam_data: [0; 256],
@@ -44,12 +62,141 @@ impl PPU {
             oam_addr: 0,
             scanline: -1, // Start on the pre-render scanline
             cycle: 0,
+            frame: 0,
+            region,
+            nmi_pending: false,
+            frame_complete: false,
             data_buffer: 0,
+            tile_index: 0,
+            secondary_oam: [0xFF; 32],
+            sprite_count: 0,
+            chr_log: None,
+        }
+    }
+
+    pub fn scanline(&self) -> i16 {
+        self.scanline
+    }
+
+    pub fn cycle(&self) -> u16 {
+        self.cycle
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    fn rendering_enabled(&self) -> bool {
+        (self.ppumask & 0b0001_1000) != 0
+    }
+
+    /// Starts or stops recording CHR ROM usage into FCEUX `.cdl` flags.
+    pub fn set_chr_log(&mut self, log: Option<Vec<u8>>) {
+        self.chr_log = log;
+    }
+
+    pub fn chr_log(&self) -> Option<&[u8]> {
+        self.chr_log.as_deref()
+    }
+
+    fn log_chr(&mut self, mapper: &dyn Mapper, addr: u16, flags: u8) {
+        if let Some(log) = &mut self.chr_log
+            && let Some(entry) = mapper.chr_offset(addr).and_then(|offset| log.get_mut(offset))
+        {
+            *entry |= flags;
+        }
+    }
+
+    /// Returns true once per NMI edge raised by the PPU.
+    pub fn poll_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    /// Returns true once per frame, when the PPU enters vertical blank.
+    pub fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+
+    pub fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.vram);
+        w.write_bytes(&self.oam_data);
+        w.write_bytes(&self.palette_ram);
+        w.write_u16(self.v);
+        w.write_u16(self.t);
+        w.write_u8(self.x);
+        w.write_bool(self.w);
+        w.write_u8(self.ppuctrl);
+        w.write_u8(self.ppumask);
+        w.write_u8(self.ppustatus);
+        w.write_u8(self.oam_addr);
+        w.write_i16(self.scanline);
+        w.write_u16(self.cycle);
+        w.write_u64(self.frame);
+        w.write_bool(self.nmi_pending);
+        w.write_bool(self.frame_complete);
+        w.write_u8(self.data_buffer);
+        w.write_u8(self.tile_index);
+        w.write_bytes(&self.secondary_oam);
+        w.write_u8(self.sprite_count);
+    }
+
+    pub fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.read_bytes(&mut self.vram)?;
+        r.read_bytes(&mut self.oam_data)?;
+        r.read_bytes(&mut self.palette_ram)?;
+        self.v = r.read_u16()?;
+        self.t = r.read_u16()?;
+        self.x = r.read_u8()?;
+        self.w = r.read_bool()?;
+        self.ppuctrl = r.read_u8()?;
+        self.ppumask = r.read_u8()?;
+        self.ppustatus = r.read_u8()?;
+        self.oam_addr = r.read_u8()?;
+        self.scanline = r.read_i16()?;
+        self.cycle = r.read_u16()?;
+        if self.scanline < -1 || self.scanline > self.region.last_scanline() || self.cycle > 340 {
+            return Err(StateError::Invalid("PPU position"));
+        }
+        self.frame = r.read_u64()?;
+        self.nmi_pending = r.read_bool()?;
+        self.frame_complete = r.read_bool()?;
+        self.data_buffer = r.read_u8()?;
+        self.tile_index = r.read_u8()?;
+        r.read_bytes(&mut self.secondary_oam)?;
+        self.sprite_count = r.read_u8()?;
+        if self.sprite_count > 8 {
+            return Err(StateError::Invalid("sprite count"));
         }
+        Ok(())
+    }
+
+    /// Reads the PPU address space ($0000-$3FFF).
+    fn read_vram(&mut self, mapper: &mut dyn Mapper, addr: u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => mapper.chr_read(addr),
+            0x2000..=0x3EFF => mapper.nametable_read(addr, &self.vram),
+            _ => self.palette_ram[palette_index(addr)],
+        }
+    }
+
+    fn write_vram(&mut self, mapper: &mut dyn Mapper, addr: u16, data: u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => mapper.chr_write(addr, data),
+            0x2000..=0x3EFF => mapper.nametable_write(addr, data, &mut self.vram),
+            _ => self.palette_ram[palette_index(addr)] = data,
+        }
+    }
+
+    /// Increments VRAM address after a PPUDATA read/write, controlled by PPUCTRL
+    fn increment_vram_addr(&mut self) {
+        let step = if (self.ppuctrl & 0b100) == 0 { 1 } else { 32 };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
     }
 
     /// Handles CPU reads from PPU registers ($2000-$2007)
-    pub fn cpu_read(&mut self, addr: u16) -> u8 {
+    pub fn cpu_read(&mut self, addr: u16, mapper: &mut dyn Mapper) -> u8 {
         match addr {
             0x2002 => { // PPUSTATUS
                 // Reading status register clears the VBlank flag and the address latch
@@ -62,18 +209,21 @@ impl PPU {
                 self.oam_data[self.oam_addr as usize]
             }
             0x2007 => { // PPUDATA
-                let mut data = self.vram[(self.v & 0x3FFF) as usize]; // Placeholder, needs mirroring logic
-                if self.v < 0x3F00 {
+                let addr = self.v & 0x3FFF;
+                if addr < 0x2000 {
+                    self.log_chr(mapper, addr, cdl::CHR_READ);
+                }
+                let mut data = self.read_vram(mapper, addr);
+                if addr < 0x3F00 {
                     // Reads from VRAM are buffered, so the first read is invalid
                     let buffered_data = self.data_buffer;
                     self.data_buffer = data;
                     data = buffered_data;
                 } else {
-                    // Palette RAM reads are not buffered
-                    self.data_buffer = self.vram[(self.v - 0x1000) as usize]; // Mirror down
+                    // Palette RAM reads are not buffered, but fill the buffer with the nametable underneath
+                    self.data_buffer = self.read_vram(mapper, addr - 0x1000);
                 }
-                // Increment VRAM address after read/write, controlled by PPUCTRL
-                self.v += if (self.ppuctrl & 0b100) == 0 { 1 } else { 32 };
+                self.increment_vram_addr();
                 data
             }
             _ => 0, // Other registers are write-only or have no readable value
@@ -81,9 +231,13 @@ impl PPU {
     }
 
     /// Handles CPU writes to PPU registers ($2000-$2007)
-    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+    pub fn cpu_write(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
         match addr {
             0x2000 => { // PPUCTRL
+                // Enabling NMI during VBlank raises one immediately
+                if (self.ppuctrl & 0x80) == 0 && (data & 0x80) != 0 && (self.ppustatus & 0x80) != 0 {
+                    self.nmi_pending = true;
+                }
                 self.ppuctrl = data;
                 // Update temporary VRAM address with nametable bits from control register
                 self.t = (self.t & 0xF3FF) | ((data as u16 & 0x03) << 10);
@@ -119,25 +273,181 @@ impl PPU {
                 }
             }
             0x2007 => { // PPUDATA
-                self.vram[(self.v & 0x3FFF) as usize] = data; // Placeholder, needs mirroring
-                // Increment VRAM address after read/write, controlled by PPUCTRL
-                self.v += if (self.ppuctrl & 0b100) == 0 { 1 } else { 32 };
+                self.write_vram(mapper, self.v, data);
+                self.increment_vram_addr();
             }
             _ => {}
         }
     }
 
     /// Executes one PPU cycle.
-    /// This is where all the rendering magic will happen.
-    pub fn step(&mut self) {
-        // TODO: Implement PPU rendering cycle by cycle.
-        // This involves:
-        // - Incrementing cycle and scanline counters.
-        // - Handling different parts of a scanline (visible, hblank, vblank).
-        // - Fetching nametable data, pattern table data, and attribute data.
-        // - Evaluating sprites for the next scanline.
-        // - Outputting a pixel to a frame buffer.
-        // - Setting PPUSTATUS flags (VBlank, sprite overflow, etc.).
-        // - Triggering NMI on VBlank if enabled in PPUCTRL.
+    pub fn step(&mut self, mapper: &mut dyn Mapper) {
+        // TODO: Output pixels to a frame buffer and set sprite 0 hit.
+        if self.rendering_enabled() && self.scanline < 240 {
+            self.render_fetches(mapper);
+        }
+
+        if self.scanline == self.region.vblank_scanline() && self.cycle == 1 {
+            self.ppustatus |= 0b1000_0000; // Set VBlank flag
+            self.frame_complete = true;
+            if (self.ppuctrl & 0x80) != 0 {
+                self.nmi_pending = true;
+            }
+        } else if self.scanline == -1 && self.cycle == 1 {
+            self.ppustatus &= 0b0001_1111; // Clear VBlank, sprite 0 hit and overflow
+        }
+
+        self.cycle += 1;
+        // On NTSC, the pre-render scanline is one dot shorter on odd frames while rendering
+        if self.scanline == -1
+            && self.cycle == 340
+            && self.region.skips_odd_frame_dot()
+            && self.frame % 2 == 1
+            && self.rendering_enabled()
+        {
+            self.cycle = 341;
+        }
+        if self.cycle > 340 {
+            self.cycle = 0;
+            self.scanline += 1;
+            if self.scanline > self.region.last_scanline() {
+                self.scanline = -1;
+                self.frame += 1;
+            }
+        }
+    }
+
+    /// Performs the memory fetches of one rendering dot, in the order the 2C02 makes them.
+    /// Mappers watch these to switch CHR banks (MMC2/MMC4) or count scanlines.
+    fn render_fetches(&mut self, mapper: &mut dyn Mapper) {
+        match self.cycle {
+            1..=256 | 321..=336 => {
+                match self.cycle % 8 {
+                    1 => self.tile_index = self.read_vram(mapper, 0x2000 | (self.v & 0x0FFF)),
+                    3 => {
+                        let attribute = 0x23C0 | (self.v & 0x0C00) | ((self.v >> 4) & 0x38) | ((self.v >> 2) & 0x07);
+                        self.read_vram(mapper, attribute);
+                    }
+                    5 => self.fetch_pattern(mapper, self.background_pattern_address()),
+                    7 => self.fetch_pattern(mapper, self.background_pattern_address() + 8),
+                    0 => self.increment_coarse_x(),
+                    _ => {}
+                }
+                if self.cycle == 256 {
+                    self.increment_y();
+                }
+            }
+            257..=320 => {
+                if self.cycle == 257 {
+                    self.v = (self.v & !0x041F) | (self.t & 0x041F); // Horizontal scroll from t
+                    self.evaluate_sprites();
+                }
+                if self.scanline == -1 && (280..=304).contains(&self.cycle) {
+                    self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0); // Vertical scroll from t
+                }
+                // Eight dots per sprite slot: two garbage nametable fetches, then the pattern bytes
+                let slot = (self.cycle - 257) / 8;
+                match (self.cycle - 257) % 8 {
+                    0 | 2 => {
+                        self.read_vram(mapper, 0x2000 | (self.v & 0x0FFF));
+                    }
+                    4 => self.fetch_pattern(mapper, self.sprite_pattern_address(slot as usize)),
+                    6 => self.fetch_pattern(mapper, self.sprite_pattern_address(slot as usize) + 8),
+                    _ => {}
+                }
+            }
+            337 | 339 => {
+                // Unused nametable fetches at the end of the line
+                self.read_vram(mapper, 0x2000 | (self.v & 0x0FFF));
+            }
+            _ => {}
+        }
+    }
+
+    /// Reads one pattern table byte for rendering. Until pixels are drawn only the
+    /// fetch itself matters, for the mapper and the Code/Data Log.
+    fn fetch_pattern(&mut self, mapper: &mut dyn Mapper, addr: u16) {
+        self.log_chr(mapper, addr, cdl::CHR_RENDERED);
+        mapper.chr_read(addr);
     }
+
+    fn sprite_height(&self) -> i16 {
+        if (self.ppuctrl & 0x20) != 0 { 16 } else { 8 }
+    }
+
+    fn background_pattern_address(&self) -> u16 {
+        let table = ((self.ppuctrl & 0x10) as u16) << 8;
+        table | ((self.tile_index as u16) << 4) | ((self.v >> 12) & 0x07)
+    }
+
+    /// Low plane address of a sprite row for the next scanline. Empty slots hold $FF
+    /// bytes, and the PPU still fetches tile $FF for them.
+    fn sprite_pattern_address(&self, slot: usize) -> u16 {
+        let [y, tile, attributes, _] = [0, 1, 2, 3].map(|i| self.secondary_oam[slot * 4 + i]);
+        let height = self.sprite_height();
+        let mut row = (self.scanline - y as i16).rem_euclid(height) as u16;
+        if (attributes & 0x80) != 0 {
+            row = height as u16 - 1 - row; // Vertical flip
+        }
+        let (table, tile) = if height == 16 {
+            // 8x16 sprites pick their table with bit 0 of the tile number
+            (((tile & 1) as u16) << 12, (tile & 0xFE) as u16 + row / 8)
+        } else {
+            (((self.ppuctrl & 0x08) as u16) << 9, tile as u16)
+        };
+        table | (tile << 4) | (row % 8)
+    }
+
+    /// Fills secondary OAM with the first eight sprites on the next scanline.
+    fn evaluate_sprites(&mut self) {
+        self.secondary_oam = [0xFF; 32];
+        self.sprite_count = 0;
+        let height = self.sprite_height();
+        for sprite in self.oam_data.chunks_exact(4) {
+            let row = self.scanline - sprite[0] as i16;
+            if !(0..height).contains(&row) {
+                continue;
+            }
+            if self.sprite_count == 8 {
+                self.ppustatus |= 0b0010_0000; // Sprite overflow, without the hardware's false positives
+                break;
+            }
+            let slot = self.sprite_count as usize * 4;
+            self.secondary_oam[slot..slot + 4].copy_from_slice(sprite);
+            self.sprite_count += 1;
+        }
+    }
+
+    fn increment_coarse_x(&mut self) {
+        if (self.v & 0x001F) == 31 {
+            self.v &= !0x001F;
+            self.v ^= 0x0400; // Next horizontal nametable
+        } else {
+            self.v += 1;
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if (self.v & 0x7000) != 0x7000 {
+            self.v += 0x1000; // Fine Y
+            return;
+        }
+        self.v &= !0x7000;
+        let mut coarse_y = (self.v & 0x03E0) >> 5;
+        if coarse_y == 29 {
+            coarse_y = 0;
+            self.v ^= 0x0800; // Next vertical nametable
+        } else if coarse_y == 31 {
+            coarse_y = 0; // Out of range rows wrap without switching nametables
+        } else {
+            coarse_y += 1;
+        }
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+}
+
+/// Maps a palette address to palette RAM, folding the sprite backdrop entries onto the background ones.
+fn palette_index(addr: u16) -> usize {
+    let index = (addr & 0x1F) as usize;
+    if index >= 16 && index.is_multiple_of(4) { index - 16 } else { index }
 }
@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+
+// Snapshots are stored newest-first as a chain: the most recent one is kept whole,
+// and every older one as the XOR against its newer neighbour, run-length compressed.
+// Consecutive states differ in a few hundred bytes, so deltas are mostly zero runs.
+// Walking back from the newest snapshot rebuilds each older one, and the oldest
+// entry can be dropped at any time since nothing depends on it.
+
+struct Delta {
+    frame: u64,     // Frame the reconstructed snapshot was taken on
+    len: usize,     // Length of the reconstructed snapshot
+    data: Vec<u8>,  // Compressed XOR against the next newer snapshot
+}
+
+/// Ring buffer of machine states for stepping backwards through gameplay.
+pub struct Rewind {
+    interval: u32,    // Frames between snapshots
+    max_bytes: usize, // Budget for compressed deltas
+    frame: u64,       // Frames recorded since the buffer was created
+
+    newest: Option<(u64, Vec<u8>)>,
+    deltas: VecDeque<Delta>, // Oldest at the front
+    delta_bytes: usize,
+}
+
+impl Rewind {
+    pub fn new(interval: u32, max_bytes: usize) -> Self {
+        Rewind {
+            interval: interval.max(1),
+            max_bytes,
+            frame: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    /// Counts a finished frame. Returns true if a snapshot should be taken now.
+    pub fn frame_done(&mut self) -> bool {
+        self.frame += 1;
+        self.frame.is_multiple_of(self.interval as u64)
+    }
+
+    /// Stores a snapshot of the current frame, evicting the oldest ones past the memory budget.
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some((frame, previous)) = self.newest.take() {
+            let data = compress(&xor(&previous, &state));
+            self.delta_bytes += data.len();
+            self.deltas.push_back(Delta { frame, len: previous.len(), data });
+        }
+        self.newest = Some((self.frame, state));
+
+        while self.delta_bytes > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(oldest) => self.delta_bytes -= oldest.data.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Drops snapshots until reaching one at least `frames` frames back, or the oldest one.
+    /// Returns that snapshot and how many frames back it is, without removing it.
+    pub fn rewind(&mut self, frames: u32) -> Option<(Vec<u8>, u64)> {
+        let target = self.frame.saturating_sub(frames as u64);
+        let (mut frame, mut state) = self.newest.take()?;
+        while frame > target {
+            let Some(delta) = self.deltas.pop_back() else { break };
+            self.delta_bytes -= delta.data.len();
+            state = xor(&state, &decompress(&delta.data, delta.len));
+            state.truncate(delta.len);
+            frame = delta.frame;
+        }
+
+        let rewound = self.frame - frame;
+        self.frame = frame;
+        self.newest = Some((frame, state.clone()));
+        Some((state, rewound))
+    }
+
+    /// Bytes currently held, including the uncompressed newest snapshot.
+    pub fn memory_usage(&self) -> usize {
+        self.delta_bytes + self.newest.as_ref().map_or(0, |(_, state)| state.len())
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+}
+
+/// XORs two snapshots, treating the shorter one as zero-padded.
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let len = a.len().max(b.len());
+    (0..len)
+        .map(|i| a.get(i).copied().unwrap_or(0) ^ b.get(i).copied().unwrap_or(0))
+        .collect()
+}
+
+// Compressed format: repeated [zero run length][literal length][literal bytes],
+// with both lengths as LEB128 varints.
+fn compress(delta: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+    while i < delta.len() {
+        let zeros_start = i;
+        while i < delta.len() && delta[i] == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        // Short zero runs inside a literal cost more to encode than to copy
+        while i < delta.len() && !(delta[i] == 0 && delta[i..].iter().take(4).all(|&b| b == 0)) {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend_from_slice(&delta[literal_start..i]);
+    }
+    out
+}
+
+fn decompress(data: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+    let mut pos = 0;
+    while pos < data.len() {
+        let zeros = read_varint(data, &mut pos);
+        let literals = read_varint(data, &mut pos);
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&data[pos..pos + literals]);
+        pos += literals;
+    }
+    out.resize(len.max(out.len()), 0);
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A snapshot that differs from its neighbours in every byte, so each delta
+    /// compresses to the same 66 bytes: two one-byte lengths and 64 literals.
+    fn snapshot(frame: u64) -> Vec<u8> {
+        vec![frame as u8; 64]
+    }
+
+    /// Records `frames` frames, snapshotting as the buffer asks.
+    fn record(rewind: &mut Rewind, frames: u64) {
+        for _ in 0..frames {
+            if rewind.frame_done() {
+                rewind.push(snapshot(rewind.frame));
+            }
+        }
+    }
+
+    #[test]
+    fn rewinds_to_the_snapshot_frames_back() {
+        let mut rewind = Rewind::new(1, usize::MAX);
+        rewind.push(snapshot(0));
+        record(&mut rewind, 10);
+        assert_eq!(rewind.rewind(3), Some((snapshot(7), 3)));
+        assert_eq!(rewind.rewind(0), Some((snapshot(7), 0)));
+
+        // Recording carries on from the frame rewound to
+        record(&mut rewind, 2);
+        assert_eq!(rewind.rewind(1), Some((snapshot(8), 1)));
+    }
+
+    #[test]
+    fn rewinds_at_least_as_far_as_asked_between_snapshots() {
+        let mut rewind = Rewind::new(4, usize::MAX);
+        rewind.push(snapshot(0));
+        record(&mut rewind, 12);
+        assert_eq!(rewind.rewind(3), Some((snapshot(8), 4)));
+    }
+
+    #[test]
+    fn rewinds_only_as_far_as_the_memory_budget_kept() {
+        let mut rewind = Rewind::new(1, 3 * 66);
+        rewind.push(snapshot(0));
+        record(&mut rewind, 10);
+        // Deltas for frames 7, 8 and 9 fit, behind the whole snapshot of frame 10
+        assert_eq!(rewind.memory_usage(), 3 * 66 + 64);
+        assert_eq!(rewind.rewind(100), Some((snapshot(7), 3)));
+        assert_eq!(rewind.memory_usage(), 64);
+    }
+
+    #[test]
+    fn clearing_forgets_every_snapshot() {
+        let mut rewind = Rewind::new(1, usize::MAX);
+        rewind.push(snapshot(0));
+        record(&mut rewind, 5);
+        rewind.clear();
+        assert_eq!(rewind.memory_usage(), 0);
+        assert_eq!(rewind.rewind(1), None);
+    }
+
+    #[test]
+    fn compression_round_trips_long_runs() {
+        // Runs past 127 bytes need multi-byte varints
+        let mut delta = vec![0; 300];
+        delta.extend((0..200).map(|i| i as u8 | 1));
+        delta.extend([0, 0, 7, 0, 0, 0, 0, 0]);
+        let compressed = compress(&delta);
+        assert!(compressed.len() < delta.len());
+        assert_eq!(decompress(&compressed, delta.len()), delta);
+        // Trailing zeros past the compressed data come back from the length
+        assert_eq!(decompress(&compress(&[1, 2]), 4), [1, 2, 0, 0]);
+    }
+}
@@ -0,0 +1,263 @@
+use std::fmt;
+
+// Every state starts with: magic, format version, CRC32 of the ROM it was taken from.
+const MAGIC: &[u8; 4] = b"SAMN";
+pub const VERSION: u16 = 5;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    RomMismatch { expected: u32, found: u32 },
+    Truncated,
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a samnes save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {} (expected {})", v, VERSION),
+            StateError::RomMismatch { expected, found } => {
+                write!(f, "save state belongs to ROM {:08X}, but {:08X} is loaded", found, expected)
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid(what) => write!(f, "save state has an invalid {}", what),
+        }
+    }
+}
+
+/// Little-endian serializer used by every component's `save_state`.
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    /// Starts a new state with the header for the given ROM.
+    pub fn new(rom_hash: u32) -> Self {
+        let mut writer = StateWriter { buf: Vec::new() };
+        writer.write_bytes(MAGIC);
+        writer.write_u16(VERSION);
+        writer.write_u32(rom_hash);
+        writer
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_i16(&mut self, value: i16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    /// Writes a fixed-size block; the reader must know its length.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Counterpart to `StateWriter`. Every read fails with `Truncated` past the end of the data.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Validates the header against the loaded ROM and positions the reader after it.
+    pub fn new(data: &'a [u8], rom_hash: u32) -> Result<Self, StateError> {
+        let mut reader = StateReader { data, pos: 0 };
+        let mut magic = [0; 4];
+        reader.read_bytes(&mut magic).map_err(|_| StateError::BadMagic)?;
+        if &magic != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = reader.read_u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let found = reader.read_u32()?;
+        if found != rom_hash {
+            return Err(StateError::RomMismatch { expected: rom_hash, found });
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(StateError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, StateError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, StateError> {
+        Ok(i16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Fills `out` completely from the state.
+    pub fn read_bytes(&mut self, out: &mut [u8]) -> Result<(), StateError> {
+        out.copy_from_slice(self.take(out.len())?);
+        Ok(())
+    }
+
+    /// Errors if anything is left over, which means the state doesn't match this build's layout.
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.pos == self.data.len() { Ok(()) } else { Err(StateError::Invalid("length")) }
+    }
+}
+
+/// CRC-32 (IEEE), used to tie save states and other per-game files to a ROM.
+pub fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for chunk in chunks {
+        for &byte in *chunk {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            }
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+    use crate::cartridge::Cartridge;
+    use crate::console::{Console, Region};
+
+    // Keeps the CPU, RAM, PPU (NMI, VRAM writes), APU (a pulse tone, frame IRQ) and
+    // CHR RAM busy, so a state that misses anything shows up as a divergence later.
+    const PROGRAM: &str = "
+        reset:
+            sei
+            ldx #$FF
+            txs
+            lda #$80
+            sta $2000
+            lda #$0F
+            sta $4015
+            lda #$BF
+            sta $4000
+            lda #$50
+            sta $4002
+            lda #$08
+            sta $4003
+        loop:
+            inc $10
+            lda #$00
+            sta $2006
+            lda $10
+            sta $2006
+            sta $2007
+            jmp loop
+        nmi:
+            inc $11
+            lda $2002
+            rti
+            .org $FFFA
+            .word nmi, reset, nmi
+    ";
+
+    fn console(fill: u8) -> Console {
+        let mut prg = vec![fill; 0x4000];
+        for (start, bytes) in assembler::assemble(PROGRAM, 0xC000, false).unwrap() {
+            let offset = start as usize - 0xC000;
+            prg[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        let mut console = Console::new(&Cartridge::for_test(0, 0, prg, Vec::new()), Region::Ntsc).unwrap();
+        console.reset();
+        console
+    }
+
+    fn run_frames(console: &mut Console, frames: u32) {
+        for _ in 0..frames {
+            assert!(console.run_frame());
+        }
+    }
+
+    #[test]
+    fn loading_a_state_reproduces_the_machine() {
+        let mut console = console(0xFF);
+        run_frames(&mut console, 3);
+        let saved = console.save_state();
+        run_frames(&mut console, 3);
+        let expected = console.save_state();
+
+        console.load_state(&saved).unwrap();
+        assert_eq!(console.save_state(), saved);
+        assert_eq!(console.frames(), 3);
+        run_frames(&mut console, 3);
+        assert!(console.save_state() == expected, "the machine diverged after loading a state");
+    }
+
+    #[test]
+    fn states_from_another_rom_are_rejected() {
+        let mut other = console(0xEA);
+        run_frames(&mut other, 1);
+        let mut console = console(0xFF);
+        let before = console.save_state();
+        assert!(matches!(console.load_state(&other.save_state()), Err(StateError::RomMismatch { .. })));
+        assert!(console.save_state() == before, "a rejected state changed the machine");
+    }
+
+    #[test]
+    fn damaged_states_leave_the_machine_as_it_was() {
+        let mut console = console(0xFF);
+        run_frames(&mut console, 1);
+        let state = console.save_state();
+        run_frames(&mut console, 1);
+        let before = console.save_state();
+
+        let mut bad_magic = state.clone();
+        bad_magic[0] ^= 0xFF;
+        assert_eq!(console.load_state(&bad_magic), Err(StateError::BadMagic));
+        let mut bad_version = state.clone();
+        bad_version[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(console.load_state(&bad_version), Err(StateError::UnsupportedVersion(VERSION + 1)));
+        assert_eq!(console.load_state(&state[..state.len() - 1]), Err(StateError::Truncated));
+        let mut padded = state.clone();
+        padded.push(0);
+        assert_eq!(console.load_state(&padded), Err(StateError::Invalid("length")));
+        assert!(console.save_state() == before, "a rejected state changed the machine");
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+/// Where a symbol lives. ROM symbols are tied to a PRG offset so they follow
+/// their bank wherever the mapper puts it; everything else is a CPU address.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Location {
+    Cpu(u16),
+    Prg(usize),
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub comment: Option<String>,
+    pub size: usize, // Bytes covered, for arrays and multi-byte variables
+}
+
+/// Labels and comments imported from other tools' symbol files.
+pub struct Symbols {
+    cpu: BTreeMap<u16, Symbol>,
+    prg: BTreeMap<usize, Symbol>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Symbols { cpu: BTreeMap::new(), prg: BTreeMap::new() }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cpu.is_empty() && self.prg.is_empty()
+    }
+
+    /// Loads a symbol file, picking the format from its name:
+    /// FCEUX `.nl` (`game.nes.ram.nl`, or `game.nes.N.nl` for 16KB PRG bank N),
+    /// Mesen `.mlb`, or ca65 `.dbg`. Returns how many symbols were added.
+    pub fn load(&mut self, path: &Path) -> Result<usize, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_lowercase()).unwrap_or_default();
+        let before = self.cpu.len() + self.prg.len();
+        if let Some(stem) = file_name.strip_suffix(".nl") {
+            let bank = match stem.rsplit_once('.').map(|(_, bank)| bank) {
+                Some("ram") | None => None,
+                Some(bank) => Some(usize::from_str_radix(bank, 16).map_err(|_| format!("{}: can't tell which bank this is for", path.display()))?),
+            };
+            self.load_nl(&text, bank);
+        } else if file_name.ends_with(".mlb") {
+            self.load_mlb(&text);
+        } else if file_name.ends_with(".dbg") {
+            self.load_dbg(&text);
+        } else {
+            return Err(format!("{}: unknown symbol file type, expected .nl, .mlb or .dbg", path.display()));
+        }
+        Ok(self.cpu.len() + self.prg.len() - before)
+    }
+
+    fn add(&mut self, location: Location, symbol: Symbol) {
+        match location {
+            Location::Cpu(addr) => self.cpu.insert(addr, symbol),
+            Location::Prg(offset) => self.prg.insert(offset, symbol),
+        };
+    }
+
+    // FCEUX: `$C000#Name#Comment`, or `$0300/10#Name#` for a 16 byte array.
+    // Bank files hold CPU addresses within that 16KB bank.
+    fn load_nl(&mut self, text: &str, bank: Option<usize>) {
+        for line in text.lines() {
+            let mut fields = line.splitn(3, '#');
+            let (Some(address), Some(name)) = (fields.next(), fields.next()) else { continue };
+            let comment = fields.next().map(|c| c.trim()).filter(|c| !c.is_empty()).map(str::to_string);
+            let (address, size) = address.split_once('/').unwrap_or((address, "1"));
+            let (Ok(addr), Ok(size)) = (parse_hex(address), usize::from_str_radix(size.trim(), 16)) else { continue };
+            let location = match bank {
+                Some(bank) if addr >= 0x8000 => Location::Prg(bank * 0x4000 + (addr & 0x3FFF)),
+                _ => Location::Cpu(addr as u16),
+            };
+            let name = name.trim();
+            if !name.is_empty() {
+                self.add(location, Symbol { name: name.to_string(), comment, size: size.max(1) });
+            }
+        }
+    }
+
+    // Mesen: `P:0AF0:Name:Comment` or `P:0AF0-0AF3:Name`, where the type is P (PRG ROM
+    // offset), R (internal RAM), S (save RAM), W (work RAM) or G (CPU address).
+    // Mesen 2 spells the types out (NesPrgRom, NesInternalRam, ...).
+    fn load_mlb(&mut self, text: &str) {
+        for line in text.lines() {
+            let mut fields = line.splitn(4, ':');
+            let (Some(kind), Some(range), Some(name)) = (fields.next(), fields.next(), fields.next()) else { continue };
+            let comment = fields.next().map(|c| c.replace("\\n", " ").trim().to_string()).filter(|c| !c.is_empty());
+            let (start, end) = range.split_once('-').unwrap_or((range, range));
+            let (Ok(start), Ok(end)) = (parse_hex(start), parse_hex(end)) else { continue };
+            let location = match kind.trim() {
+                "P" | "NesPrgRom" => Location::Prg(start),
+                "R" | "NesInternalRam" => Location::Cpu(start as u16 & 0x7FF),
+                "S" | "W" | "NesSaveRam" | "NesWorkRam" => Location::Cpu(0x6000 + (start as u16 & 0x1FFF)),
+                "G" | "NesMemory" => Location::Cpu(start as u16),
+                _ => continue,
+            };
+            // Comment-only entries have no name; show the address, as Mesen does
+            let name = match name.trim() {
+                "" if comment.is_none() => continue,
+                "" => format!("${:04X}", start),
+                name => name.to_string(),
+            };
+            self.add(location, Symbol { name, comment, size: end.saturating_sub(start) + 1 });
+        }
+    }
+
+    // ca65/ld65 debug info: tab-separated records of comma-separated key=value pairs.
+    // Symbols in segments written to the output file map to PRG offsets.
+    fn load_dbg(&mut self, text: &str) {
+        struct Segment {
+            start: usize,
+            size: usize,
+            file_offset: Option<usize>, // PRG offset of the segment start
+        }
+        let mut segments: HashMap<String, Segment> = HashMap::new();
+        let mut symbols = Vec::new();
+        for line in text.lines() {
+            let Some((record, fields)) = line.split_once(char::is_whitespace) else { continue };
+            let fields = parse_dbg_fields(fields);
+            let number = |key: &str| fields.get(key).and_then(|value| parse_dbg_number(value));
+            match record {
+                "seg" => {
+                    let Some(id) = fields.get("id") else { continue };
+                    // iNES output starts with a 16 byte header before PRG ROM
+                    let header = if fields.get("oname").is_some_and(|name| name.to_lowercase().ends_with(".nes")) { 16 } else { 0 };
+                    let segment = Segment {
+                        start: number("start").unwrap_or(0),
+                        size: number("size").unwrap_or(0),
+                        file_offset: number("ooffs").and_then(|offset| offset.checked_sub(header)),
+                    };
+                    segments.insert(id.clone(), segment);
+                }
+                "sym" if fields.get("type").is_some_and(|kind| kind == "lab" || kind == "equ") => {
+                    let (Some(name), Some(value)) = (fields.get("name"), number("val")) else { continue };
+                    symbols.push((name.clone(), value, fields.get("seg").cloned(), number("size").unwrap_or(1)));
+                }
+                _ => {}
+            }
+        }
+
+        for (name, value, segment, size) in symbols {
+            let location = match segment.and_then(|id| segments.get(&id)) {
+                Some(Segment { start, size, file_offset: Some(offset) }) if (*start..start + size).contains(&value) => {
+                    Location::Prg(offset + value - start)
+                }
+                _ => Location::Cpu(value as u16),
+            };
+            self.add(location, Symbol { name, comment: None, size: size.max(1) });
+        }
+    }
+
+    /// The symbol covering a location, and how far into it the location is.
+    pub fn get(&self, location: Location) -> Option<(&Symbol, usize)> {
+        let (start, symbol) = match location {
+            Location::Cpu(addr) => self.cpu.range(..=addr).next_back().map(|(&start, symbol)| (start as usize, symbol))?,
+            Location::Prg(offset) => self.prg.range(..=offset).next_back().map(|(&start, symbol)| (start, symbol))?,
+        };
+        let index = match location {
+            Location::Cpu(addr) => addr as usize,
+            Location::Prg(offset) => offset,
+        } - start;
+        if index < symbol.size { Some((symbol, index)) } else { None }
+    }
+
+    /// Name for a CPU address, e.g. `Reset` or `buffer+3`. ROM addresses are looked
+    /// up by `prg_offset`, the PRG byte currently mapped there.
+    pub fn name(&self, addr: u16, prg_offset: Option<usize>) -> Option<String> {
+        let location = match prg_offset {
+            Some(offset) => Location::Prg(offset),
+            None => Location::Cpu(addr),
+        };
+        match self.get(location)? {
+            (symbol, 0) => Some(symbol.name.clone()),
+            (symbol, index) => Some(format!("{}+{}", symbol.name, index)),
+        }
+    }
+
+    /// Comment attached to exactly this location.
+    pub fn comment(&self, location: Location) -> Option<&str> {
+        match self.get(location)? {
+            (symbol, 0) => symbol.comment.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Finds a symbol by name.
+    pub fn find(&self, name: &str) -> Option<Location> {
+        let cpu = self.cpu.iter().find(|(_, symbol)| symbol.name == name).map(|(&addr, _)| Location::Cpu(addr));
+        cpu.or_else(|| self.prg.iter().find(|(_, symbol)| symbol.name == name).map(|(&offset, _)| Location::Prg(offset)))
+    }
+}
+
+fn parse_hex(text: &str) -> Result<usize, std::num::ParseIntError> {
+    usize::from_str_radix(text.trim().trim_start_matches('$'), 16)
+}
+
+fn parse_dbg_number(text: &str) -> Option<usize> {
+    match text.strip_prefix("0x") {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+/// Splits `key=value,key="quoted, value"` pairs.
+fn parse_dbg_fields(text: &str) -> HashMap<&str, String> {
+    let mut fields = HashMap::new();
+    let mut rest = text.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, next) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                (quoted[..end].to_string(), quoted.get(end + 1..).unwrap_or(""))
+            }
+            None => {
+                let end = value.find(',').unwrap_or(value.len());
+                (value[..end].to_string(), &value[end..])
+            }
+        };
+        fields.insert(key.trim(), value);
+        rest = next.trim_start_matches(',');
+    }
+    fields
+}
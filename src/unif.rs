@@ -0,0 +1,129 @@
+use crate::cartridge::{Cartridge, Mirroring};
+use crate::console::Region;
+
+// UNIF layout: a 32-byte header ("UNIF", revision, padding), then chunks of a 4-byte ID,
+// a little-endian u32 length and the data. The board is named by its PCB designation
+// (MAPR) rather than an iNES mapper number.
+
+/// Board names (less any NES-/HVC-/UNL-/BMC-/BTL-/AVE- prefix) and their iNES mapper and
+/// NES 2.0 submapper. Only boards `mapper::create` can build are listed, so any other
+/// board is reported by name.
+const BOARDS: &[(&str, u16, u8)] = &[
+    // Nintendo discrete boards
+    ("NROM", 0, 0),
+    ("NROM-128", 0, 0),
+    ("NROM-256", 0, 0),
+    ("RROM", 0, 0),
+    ("RROM-128", 0, 0),
+    ("BNROM", 34, 2),
+    ("GNROM", 66, 0),
+    ("MHROM", 66, 0),
+    // MMC2, MMC4
+    ("PNROM", 9, 0),
+    ("PEEOROM", 9, 0),
+    ("FJROM", 10, 0),
+    ("FKROM", 10, 0),
+    // MMC5
+    ("EKROM", 5, 0),
+    ("ELROM", 5, 0),
+    ("ETROM", 5, 0),
+    ("EWROM", 5, 0),
+    // Licensees and pirates
+    ("IREM-HOLYDIVER", 78, 3),
+    ("NINA-001", 34, 1),
+    ("GK-192", 58, 0),
+];
+
+/// Parses a UNIF image into the same `Cartridge` the iNES loader produces.
+pub fn parse(bytes: &[u8]) -> Result<Cartridge, String> {
+    if bytes.len() < 32 || &bytes[0..4] != b"UNIF" {
+        return Err("Invalid UNIF header.".to_string());
+    }
+
+    let mut board = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = Mirroring::Horizontal;
+    let mut battery = false;
+    let mut region = None;
+
+    let mut offset = 32;
+    while offset < bytes.len() {
+        let header = bytes.get(offset..offset + 8).ok_or("UNIF file is truncated (chunk header).")?;
+        let id = &header[0..4];
+        let len = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        offset += 8;
+        let data = bytes.get(offset..offset + len).ok_or_else(|| {
+            format!("UNIF file is truncated ({} chunk).", String::from_utf8_lossy(id))
+        })?;
+        offset += len;
+
+        match id {
+            b"MAPR" => {
+                // Null-terminated, though some files pad or omit the terminator
+                let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+                board = Some(String::from_utf8_lossy(&data[..end]).trim().to_string());
+            }
+            b"MIRR" => {
+                mirroring = match data.first() {
+                    Some(1) => Mirroring::Vertical,
+                    Some(2) => Mirroring::SingleScreenLower,
+                    Some(3) => Mirroring::SingleScreenUpper,
+                    Some(4) => Mirroring::FourScreen,
+                    _ => Mirroring::Horizontal, // 5 is "mapper-controlled"
+                };
+            }
+            b"BATR" => battery = true,
+            b"TVCI" => {
+                region = match data.first() {
+                    Some(0) => Some(Region::Ntsc),
+                    Some(1) => Some(Region::Pal),
+                    _ => None,
+                };
+            }
+            _ => {
+                // PRG0-PRGF and CHR0-CHRF, concatenated in hex digit order
+                let bank = (id[3] as char).to_digit(16);
+                match (&id[0..3], bank) {
+                    (b"PRG", Some(n)) => prg_chunks[n as usize] = Some(data),
+                    (b"CHR", Some(n)) => chr_chunks[n as usize] = Some(data),
+                    _ => {} // NAME, READ, DINF, CTRL, PCK/CCK checksums and the like
+                }
+            }
+        }
+    }
+
+    let board = board.ok_or("UNIF file has no MAPR (board name) chunk.")?;
+    let (mapper, submapper) = lookup_board(&board).ok_or_else(|| format!("UNIF board {} is not supported.", board))?;
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter().copied()).collect();
+    if prg_rom.is_empty() {
+        return Err("UNIF file has no PRG ROM.".to_string());
+    }
+
+    Ok(Cartridge {
+        chr_ram_size: if chr_rom.is_empty() { 8192 } else { 0 },
+        prg_rom,
+        chr_rom,
+        trainer: None,
+        mapper,
+        submapper,
+        mirroring,
+        battery,
+        // UNIF doesn't describe RAM either, so assume the usual 8KB like iNES 1.0
+        prg_ram_size: 8192,
+        prg_nvram_size: 0,
+        region,
+        disk_sides: Vec::new(),
+    })
+}
+
+/// Finds a board by its full name or, failing that, without its manufacturer prefix.
+fn lookup_board(name: &str) -> Option<(u16, u8)> {
+    let name = name.to_ascii_uppercase();
+    let short = ["NES-", "HVC-", "UNL-", "BMC-", "BTL-", "AVE-"].iter().find_map(|prefix| name.strip_prefix(prefix));
+    BOARDS
+        .iter()
+        .find(|(board, _, _)| board.eq_ignore_ascii_case(&name) || short.is_some_and(|short| board.eq_ignore_ascii_case(short)))
+        .map(|&(_, mapper, submapper)| (mapper, submapper))
+}
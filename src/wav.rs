@@ -0,0 +1,38 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// Cutoff of the high-pass filter that takes the DC offset out of the mix, as the
+// console's own output stage does
+const HIGH_PASS_HZ: f32 = 37.0;
+
+/// Writes samples in the APU mix's 0.0-1.0 range as a mono 16-bit PCM WAV file, centred
+/// on zero by a high-pass filter.
+pub fn write(path: &Path, sample_rate: u32, samples: &[f32]) -> io::Result<()> {
+    let data_len = (samples.len() * 2) as u32;
+    let mut bytes = Vec::with_capacity(44 + data_len as usize);
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVE");
+
+    bytes.extend_from_slice(b"fmt ");
+    bytes.extend_from_slice(&16u32.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // PCM
+    bytes.extend_from_slice(&1u16.to_le_bytes()); // Mono
+    bytes.extend_from_slice(&sample_rate.to_le_bytes());
+    bytes.extend_from_slice(&(sample_rate * 2).to_le_bytes()); // Bytes per second
+    bytes.extend_from_slice(&2u16.to_le_bytes()); // Bytes per frame
+    bytes.extend_from_slice(&16u16.to_le_bytes()); // Bits per sample
+
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    let decay = 1.0 / (1.0 + 2.0 * std::f32::consts::PI * HIGH_PASS_HZ / sample_rate as f32);
+    let (mut last_in, mut last_out) = (samples.first().copied().unwrap_or(0.0), 0.0);
+    for &sample in samples {
+        last_out = decay * (last_out + sample - last_in);
+        last_in = sample;
+        let pcm = (last_out * i16::MAX as f32).clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+        bytes.extend_from_slice(&pcm.to_le_bytes());
+    }
+    fs::write(path, bytes)
+}